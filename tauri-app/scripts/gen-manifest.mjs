@@ -0,0 +1,90 @@
+// 为模型目录生成 model.manifest.json：记录 model.onnx 与 tokenizer.json 的 SHA-256、大小与词表大小，
+// 应用加载模型前按清单校验文件完整性（见 src-tauri/src/manifest.rs）
+//
+// 用法：node scripts/gen-manifest.mjs <模型目录> --model-id <id> [--dimension N] [--matryoshka 128,256] [--optional]
+//   --optional  目录中没有 model.onnx 时跳过（用于可选的重排、稀疏模型）
+
+import { createHash } from "node:crypto";
+import { createReadStream, existsSync, readFileSync, statSync, writeFileSync } from "node:fs";
+import { join } from "node:path";
+
+const MANIFEST_FILE = "model.manifest.json";
+const FILES = ["model.onnx", "tokenizer.json"];
+
+function parseArgs(argv) {
+  const [dir, ...rest] = argv;
+  const opts = { dir, optional: false, matryoshka: [] };
+  for (let i = 0; i < rest.length; i++) {
+    switch (rest[i]) {
+      case "--model-id":
+        opts.modelId = rest[++i];
+        break;
+      case "--dimension":
+        opts.dimension = Number(rest[++i]);
+        break;
+      case "--matryoshka":
+        opts.matryoshka = rest[++i].split(",").map(Number);
+        break;
+      case "--optional":
+        opts.optional = true;
+        break;
+      default:
+        throw new Error(`未知参数 ${rest[i]}`);
+    }
+  }
+  if (!opts.dir || !opts.modelId) {
+    throw new Error("用法: gen-manifest.mjs <模型目录> --model-id <id> [--dimension N] [--matryoshka 128,256] [--optional]");
+  }
+  return opts;
+}
+
+function sha256File(path) {
+  return new Promise((resolve, reject) => {
+    const hash = createHash("sha256");
+    createReadStream(path)
+      .on("data", (chunk) => hash.update(chunk))
+      .on("end", () => resolve(hash.digest("hex")))
+      .on("error", reject);
+  });
+}
+
+// 与 tokenizers 的 get_vocab_size(true) 一致：词表与 added_tokens 中不重复的 token 数
+function vocabSize(tokenizerPath) {
+  const tokenizer = JSON.parse(readFileSync(tokenizerPath, "utf8"));
+  const vocab = tokenizer.model?.vocab ?? {};
+  const tokens = new Set(Array.isArray(vocab) ? vocab.map(([token]) => token) : Object.keys(vocab));
+  for (const added of tokenizer.added_tokens ?? []) {
+    tokens.add(added.content);
+  }
+  return tokens.size;
+}
+
+const opts = parseArgs(process.argv.slice(2));
+if (!existsSync(join(opts.dir, "model.onnx"))) {
+  if (opts.optional) {
+    console.log(`[manifest] ${opts.dir} 中没有 model.onnx，跳过`);
+    process.exit(0);
+  }
+  console.error(`[manifest] ${opts.dir} 中没有 model.onnx，请先下载模型文件`);
+  process.exit(1);
+}
+
+const files = {};
+for (const name of FILES) {
+  const path = join(opts.dir, name);
+  if (!existsSync(path)) {
+    console.error(`[manifest] 缺少 ${path}`);
+    process.exit(1);
+  }
+  files[name] = { sha256: await sha256File(path), size: statSync(path).size };
+}
+
+const manifest = {
+  model_id: opts.modelId,
+  files,
+  dimension: opts.dimension ?? null,
+  vocab_size: vocabSize(join(opts.dir, "tokenizer.json")),
+  matryoshka_dims: opts.matryoshka,
+};
+writeFileSync(join(opts.dir, MANIFEST_FILE), JSON.stringify(manifest, null, 2) + "\n");
+console.log(`[manifest] 已写入 ${join(opts.dir, MANIFEST_FILE)}（${opts.modelId}）`);
@@ -0,0 +1,86 @@
+//! 暴力检索基准：在 10K / 100K / 1M 个 384 维 f32 向量上取 top 20
+//!
+//! - scan/full_sort：为全部向量打分后整体排序再截断（改用 top-k 之前的做法）
+//! - scan/top_k：有界小顶堆；默认启用 parallel 特性，按分片并行扫描
+//! - dot：逐元素顺序累加与分路累加的点积
+//!
+//! 运行 `cargo bench --bench search`，加 `--no-default-features` 可对比单线程扫描。
+//! 1M 个向量的向量文件约 1.5 GB，写在系统临时目录中，测完即删除。
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+use tauri_app_lib::quant::{dot_lanes, QuantVec, QueryVec, VectorFormat};
+use tauri_app_lib::vector_store::VectorStore;
+
+const DIM: usize = 384;
+const K: usize = 20;
+
+/// xorshift 生成的 L2 归一化向量
+fn random_unit(state: &mut u64) -> Vec<f32> {
+    let mut v: Vec<f32> = (0..DIM)
+        .map(|_| {
+            *state ^= *state << 13;
+            *state ^= *state >> 7;
+            *state ^= *state << 17;
+            (*state >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+        })
+        .collect();
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    v.iter_mut().for_each(|x| *x /= norm);
+    v
+}
+
+fn scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scan");
+    group.sample_size(10);
+    for n in [10_000usize, 100_000, 1_000_000] {
+        let path = std::env::temp_dir().join(format!("locallens-bench-{n}.vectors"));
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+        let rows = (0..n as i64).map(|id| {
+            let v = random_unit(&mut state);
+            (id, QuantVec::quantize(&v, VectorFormat::F32).to_bytes())
+        });
+        VectorStore::write_rows(&path, DIM, VectorFormat::F32, 0, n as i64 - 1, rows).unwrap();
+        let store = VectorStore::open(&path).unwrap();
+        let query = QueryVec::new(random_unit(&mut state));
+
+        group.throughput(Throughput::Elements(n as u64));
+        group.bench_with_input(BenchmarkId::new("full_sort", n), &store, |b, store| {
+            b.iter(|| {
+                let mut scored: Vec<(i64, f32)> = store.scores(&query).collect();
+                scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+                scored.truncate(K);
+                scored
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("top_k", n), &store, |b, store| {
+            b.iter(|| store.top_k(&query, K, |_| false).into_sorted_vec())
+        });
+
+        drop(store);
+        std::fs::remove_file(&path).ok();
+    }
+    group.finish();
+}
+
+fn dot(c: &mut Criterion) {
+    let mut state = 1;
+    let (a, b) = (random_unit(&mut state), random_unit(&mut state));
+    let mut group = c.benchmark_group("dot");
+    group.bench_function("sequential", |bench| {
+        bench.iter(|| {
+            black_box(&a)
+                .iter()
+                .zip(black_box(&b))
+                .map(|(x, y)| x * y)
+                .sum::<f32>()
+        })
+    });
+    group.bench_function("lanes", |bench| {
+        bench.iter(|| dot_lanes(black_box(&a), black_box(&b), |x| x))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, scan, dot);
+criterion_main!(benches);
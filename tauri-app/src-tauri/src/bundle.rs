@@ -0,0 +1,807 @@
+//! 索引包：把若干集合（或整个数据库）导出为一个可移植文件，在另一台机器上合并导入，
+//! 无需重新编码全部文件
+//!
+//! 索引包本身是 SQLite 数据库：VACUUM INTO 得到的快照去掉范围外的集合与本机缓存，
+//! 另加 bundle_meta（格式版本、模型、维度等）与 bundle_files（文件所属的根目录及以 /
+//! 分隔的相对路径）。导入时相对路径拼接到映射后的新根目录上，不在根目录下的文件保持原路径。
+
+use crate::collections::{self, ChunkSettings};
+use crate::migrations;
+use crate::quant::{QuantVec, VectorFormat};
+use rusqlite::{Connection, OptionalExtension, Result as SqlResult};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// 索引包格式版本（bundle_meta / bundle_files 的结构变化时递增）
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// 索引包文件扩展名
+pub const BUNDLE_EXTENSION: &str = "llbundle";
+
+/// 索引包摘要（导出结果与导入前预览）
+#[derive(Clone, Debug, Serialize)]
+pub struct BundleInfo {
+    pub version: u32,
+    pub model_id: String,
+    /// 存储的向量维度；包内没有向量时为 None
+    pub dim: Option<usize>,
+    /// 截断存储时另存的完整向量维度；没有完整向量时为 None
+    pub full_dim: Option<usize>,
+    pub vector_format: String,
+    pub sparse_model: Option<String>,
+    pub exported_at: String,
+    pub collections: Vec<BundleCollection>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BundleCollection {
+    pub id: i64,
+    pub name: String,
+    /// 导出时的根目录（导入时按原样作为映射的键）
+    pub roots: Vec<String>,
+    pub files: i64,
+    pub chunks: i64,
+}
+
+/// 导入目标模型的向量设置，索引包须与之兼容
+pub struct MergeTarget {
+    pub model_id: String,
+    pub dim: usize,
+    pub full_dim: usize,
+    /// 目标库是否另存完整向量
+    pub rescore: bool,
+    pub format: VectorFormat,
+    pub sparse_model: Option<String>,
+}
+
+/// 合并结果；`added` / `removed` 供调用方增量更新向量缓存
+pub struct MergeOutcome {
+    pub collections: Vec<i64>,
+    pub files: usize,
+    pub chunks: usize,
+    pub added: Vec<(i64, QuantVec)>,
+    pub removed: Vec<i64>,
+}
+
+// ── 导出 ──────────────────────────────────────────────────────────────────────
+
+/// 把 `ids` 中的集合（为空时为全部集合）导出到 `dest`，返回索引包摘要
+///
+/// 先写到同目录的临时文件，完成后改名，中途失败不会留下不完整的包
+pub fn export(conn: &Connection, dest: &Path, ids: &[i64]) -> Result<BundleInfo, String> {
+    let partial = dest.with_extension("partial");
+    std::fs::remove_file(&partial).ok();
+    conn.execute(
+        "VACUUM INTO ?1",
+        rusqlite::params![partial.to_string_lossy()],
+    )
+    .map_err(|e| format!("数据库快照失败: {e}"))?;
+    let result = trim_snapshot(&partial, ids)
+        .and_then(|_| std::fs::rename(&partial, dest).map_err(|e| format!("写入索引包失败: {e}")));
+    if let Err(e) = result {
+        std::fs::remove_file(&partial).ok();
+        return Err(e);
+    }
+    Bundle::open(dest).map(|b| b.info.clone())
+}
+
+/// 在快照上删去范围外的集合与本机数据，写入包信息并把根目录下的路径改为相对路径
+fn trim_snapshot(path: &Path, ids: &[i64]) -> Result<(), String> {
+    let mut conn = Connection::open(path).map_err(|e| e.to_string())?;
+    conn.pragma_update(None, "foreign_keys", true)
+        .map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    if !ids.is_empty() {
+        for &id in ids {
+            collections::get(&tx, id)?;
+        }
+        let list: Vec<String> = ids.iter().map(i64::to_string).collect();
+        // 文件、chunk 及其派生数据随集合级联删除
+        tx.execute(
+            &format!(
+                "DELETE FROM collections WHERE id NOT IN ({})",
+                list.join(", ")
+            ),
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    let meta = |key: &str| -> Option<String> {
+        tx.query_row("SELECT value FROM app_meta WHERE key = ?1", [key], |r| {
+            r.get(0)
+        })
+        .ok()
+    };
+    // 包内的向量须出自同一个模型
+    let models = collections::models_in_use(&tx).map_err(|e| e.to_string())?;
+    let model_id = match models.as_slice() {
+        [model] => model.clone(),
+        [] => return Err("没有可导出的集合".into()),
+        _ => {
+            return Err(format!(
+                "所选集合使用了不同的嵌入模型（{}），请按模型分别导出",
+                models.join("、")
+            ))
+        }
+    };
+    let vector_format = meta("vector_format").unwrap_or_else(|| "f32".into());
+    let sparse_model = meta("sparse_model");
+    let dim = |table: &str| -> Result<Option<usize>, String> {
+        let blob: Option<Vec<u8>> = tx
+            .query_row(&format!("SELECT embedding FROM {table} LIMIT 1"), [], |r| {
+                r.get(0)
+            })
+            .optional()
+            .map_err(|e| e.to_string())?;
+        blob.map(|b| QuantVec::from_bytes(&b, None).map(|v| v.dim()))
+            .transpose()
+    };
+    let (dim, full_dim) = (dim("chunk_embeddings")?, dim("chunk_embeddings_full")?);
+
+    // embedding 缓存与应用设置属于本机，不随包迁移
+    tx.execute_batch(
+        "
+        DELETE FROM embedding_cache;
+        DELETE FROM embedding_cache_windows;
+        DELETE FROM app_meta;
+        CREATE TABLE bundle_meta (
+            key   TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+        CREATE TABLE bundle_files (
+            file_id  INTEGER PRIMARY KEY REFERENCES files(id) ON DELETE CASCADE,
+            root     TEXT NOT NULL,
+            rel_path TEXT NOT NULL
+        );
+        ",
+    )
+    .map_err(|e| e.to_string())?;
+    let entries = [
+        ("version", Some(BUNDLE_VERSION.to_string())),
+        ("model_id", Some(model_id)),
+        ("dim", dim.map(|d| d.to_string())),
+        ("full_dim", full_dim.map(|d| d.to_string())),
+        ("vector_format", Some(vector_format)),
+        ("sparse_model", sparse_model),
+    ];
+    for (key, value) in entries {
+        if let Some(value) = value {
+            tx.execute(
+                "INSERT INTO bundle_meta (key, value) VALUES (?1, ?2)",
+                rusqlite::params![key, value],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    tx.execute(
+        "INSERT INTO bundle_meta (key, value) VALUES ('exported_at', CURRENT_TIMESTAMP)",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+
+    record_relative_paths(&tx).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    conn.execute_batch("VACUUM").map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// 文件位于所属集合的某个根目录下时（取最长的根目录），记下该根目录与相对路径
+fn record_relative_paths(conn: &Connection) -> SqlResult<()> {
+    let mut roots: HashMap<i64, Vec<String>> = HashMap::new();
+    let rows = conn
+        .prepare("SELECT collection_id, path FROM collection_roots")?
+        .query_map([], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)))?
+        .collect::<SqlResult<Vec<_>>>()?;
+    for (id, root) in rows {
+        roots.entry(id).or_default().push(root);
+    }
+    for list in roots.values_mut() {
+        list.sort_by_key(|r| std::cmp::Reverse(r.len()));
+    }
+
+    let files = conn
+        .prepare("SELECT id, collection_id, path FROM files")?
+        .query_map([], |r| {
+            Ok((
+                r.get::<_, i64>(0)?,
+                r.get::<_, i64>(1)?,
+                r.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<SqlResult<Vec<_>>>()?;
+    let mut insert =
+        conn.prepare("INSERT INTO bundle_files (file_id, root, rel_path) VALUES (?1, ?2, ?3)")?;
+    for (id, collection_id, path) in files {
+        let found = roots.get(&collection_id).and_then(|list| {
+            list.iter()
+                .find_map(|root| Some((root, relative_to(&path, root)?)))
+        });
+        if let Some((root, relative)) = found {
+            insert.execute(rusqlite::params![id, root, relative])?;
+        }
+    }
+    Ok(())
+}
+
+/// `path` 在 `root` 之下时返回以 / 分隔的相对路径（Windows 根目录下的 \ 一并转换）
+fn relative_to(path: &str, root: &str) -> Option<String> {
+    let root = root.trim_end_matches(['/', '\\']);
+    let rest = path.strip_prefix(root)?;
+    let rest = rest.strip_prefix('/').or_else(|| rest.strip_prefix('\\'))?;
+    if rest.is_empty() {
+        return None;
+    }
+    Some(if root.contains('\\') {
+        rest.replace('\\', "/")
+    } else {
+        rest.to_string()
+    })
+}
+
+// ── 导入 ──────────────────────────────────────────────────────────────────────
+
+/// 打开的索引包：原文件复制到临时目录并迁移到当前结构版本，原文件不被修改
+pub struct Bundle {
+    conn: Option<Connection>,
+    copy: PathBuf,
+    pub info: BundleInfo,
+}
+
+impl Drop for Bundle {
+    fn drop(&mut self) {
+        // 先关闭连接（Windows 不允许删除打开中的文件）
+        self.conn.take();
+        std::fs::remove_file(&self.copy).ok();
+    }
+}
+
+impl Bundle {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        let copy = std::env::temp_dir().join(format!(
+            "locallens-bundle-{}-{nanos}.db",
+            std::process::id()
+        ));
+        std::fs::copy(path, &copy).map_err(|e| format!("读取索引包失败: {e}"))?;
+        let opened = Connection::open(&copy)
+            .map_err(|e| e.to_string())
+            .and_then(|mut conn| Ok((read_info(&mut conn)?, conn)));
+        match opened {
+            Ok((info, conn)) => Ok(Bundle {
+                conn: Some(conn),
+                copy,
+                info,
+            }),
+            Err(e) => {
+                std::fs::remove_file(&copy).ok();
+                Err(e)
+            }
+        }
+    }
+
+    fn conn(&self) -> &Connection {
+        self.conn.as_ref().expect("bundle connection")
+    }
+}
+
+fn read_info(conn: &mut Connection) -> Result<BundleInfo, String> {
+    let meta: HashMap<String, String> = conn
+        .prepare("SELECT key, value FROM bundle_meta")
+        .and_then(|mut stmt| {
+            stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+                .collect()
+        })
+        .map_err(|_| "不是 LocalLens 索引包".to_string())?;
+    let version: u32 = meta
+        .get("version")
+        .and_then(|v| v.parse().ok())
+        .ok_or("索引包缺少格式版本")?;
+    if version > BUNDLE_VERSION {
+        return Err(format!(
+            "索引包格式版本为 {version}，高于当前支持的 {BUNDLE_VERSION}，请升级应用"
+        ));
+    }
+    // 较早版本导出的包与普通数据库一样逐步迁移
+    migrations::migrate(conn)?;
+
+    let dim = |key: &str| meta.get(key).and_then(|v| v.parse().ok());
+    let mut collections = Vec::new();
+    for c in collections::list(conn).map_err(|e| e.to_string())? {
+        let (files, chunks) = conn
+            .query_row(
+                "SELECT COUNT(*), COALESCE(SUM(chunk_count), 0) FROM files WHERE collection_id = ?1",
+                [c.id],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .map_err(|e| e.to_string())?;
+        collections.push(BundleCollection {
+            id: c.id,
+            name: c.name,
+            roots: c.roots,
+            files,
+            chunks,
+        });
+    }
+    Ok(BundleInfo {
+        version,
+        model_id: meta.get("model_id").cloned().unwrap_or_default(),
+        dim: dim("dim"),
+        full_dim: dim("full_dim"),
+        vector_format: meta.get("vector_format").cloned().unwrap_or_default(),
+        sparse_model: meta.get("sparse_model").cloned(),
+        exported_at: meta.get("exported_at").cloned().unwrap_or_default(),
+        collections,
+    })
+}
+
+/// 合并前的兼容性检查：模型必须与目标集合相同，存储维度必须与目标库一致（格式不同时导入时转换）
+fn check_compatible(info: &BundleInfo, target: &MergeTarget) -> Result<(), String> {
+    if info.model_id != target.model_id {
+        return Err(format!(
+            "索引包的向量由 {} 生成，目标集合的模型为 {}，无法合并（请在新机器上重新导入文件）",
+            info.model_id, target.model_id
+        ));
+    }
+    if let Some(dim) = info.dim.filter(|&d| d != target.dim) {
+        return Err(format!(
+            "索引包的向量维度为 {dim}，当前存储维度为 {}，请先在模型设置中调整截断维度",
+            target.dim
+        ));
+    }
+    Ok(())
+}
+
+/// 把索引包合并到 `conn`（调用方负责事务）
+///
+/// `root_map` 把导出时的根目录映射到本机路径，未列出的根目录保持原样；
+/// `into` 给出时全部文件并入该集合，否则每个集合在本机新建同名集合（重名时加序号）。
+/// 同一集合中已有的同路径文件被替换
+pub fn merge(
+    conn: &Connection,
+    bundle: &Bundle,
+    root_map: &HashMap<String, String>,
+    into: Option<i64>,
+    target: &MergeTarget,
+) -> Result<MergeOutcome, String> {
+    check_compatible(&bundle.info, target)?;
+    let src = bundle.conn();
+    let remap = |root: &str| {
+        root_map
+            .get(root)
+            .cloned()
+            .unwrap_or_else(|| root.to_string())
+    };
+    let mut outcome = MergeOutcome {
+        collections: vec![],
+        files: 0,
+        chunks: 0,
+        added: vec![],
+        removed: vec![],
+    };
+
+    // 索引包中的集合 → 本机集合
+    let mut targets: HashMap<i64, i64> = HashMap::new();
+    for c in collections::list(src).map_err(|e| e.to_string())? {
+        let local = match into {
+            Some(id) => collections::get(conn, id)?.id,
+            None => create_unique(conn, &c.name, &target.model_id, &c.chunking)?,
+        };
+        for root in &c.roots {
+            collections::add_root(conn, local, &remap(root)).map_err(|e| e.to_string())?;
+        }
+        targets.insert(c.id, local);
+        if !outcome.collections.contains(&local) {
+            outcome.collections.push(local);
+        }
+    }
+
+    let copy_sparse =
+        target.sparse_model.is_some() && target.sparse_model == bundle.info.sparse_model;
+    let files = src
+        .prepare(
+            "SELECT f.id, f.collection_id, f.path, b.root, b.rel_path, f.name, f.size_bytes, f.modified_at,
+                    f.content_hash, f.format, f.chunk_count
+             FROM files f LEFT JOIN bundle_files b ON b.file_id = f.id
+             ORDER BY f.id",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map([], |r| {
+                Ok(BundleFile {
+                    id: r.get(0)?,
+                    collection_id: r.get(1)?,
+                    path: r.get(2)?,
+                    root: r.get(3)?,
+                    rel_path: r.get(4)?,
+                    name: r.get(5)?,
+                    size_bytes: r.get(6)?,
+                    modified_at: r.get(7)?,
+                    content_hash: r.get(8)?,
+                    format: r.get(9)?,
+                    chunk_count: r.get(10)?,
+                })
+            })?
+            .collect::<SqlResult<Vec<_>>>()
+        })
+        .map_err(|e| e.to_string())?;
+
+    for file in files {
+        let path = match (&file.root, &file.rel_path) {
+            (Some(root), Some(relative)) => {
+                let mut p = PathBuf::from(remap(root));
+                p.extend(relative.split('/'));
+                p.to_string_lossy().to_string()
+            }
+            _ => file.path.clone(),
+        };
+        let collection_id = targets[&file.collection_id];
+        copy_file(
+            conn,
+            src,
+            &file,
+            collection_id,
+            &path,
+            target,
+            copy_sparse,
+            &mut outcome,
+        )
+        .map_err(|e| format!("合并 {path} 失败: {e}"))?;
+        outcome.files += 1;
+    }
+    Ok(outcome)
+}
+
+struct BundleFile {
+    id: i64,
+    collection_id: i64,
+    /// 导出时的绝对路径
+    path: String,
+    /// 所属根目录及相对它的路径（不在任何根目录下时为 None）
+    root: Option<String>,
+    rel_path: Option<String>,
+    name: String,
+    size_bytes: Option<i64>,
+    modified_at: Option<String>,
+    content_hash: Option<String>,
+    format: String,
+    chunk_count: i64,
+}
+
+/// 新建集合；名称已被占用时依次尝试「名称 (2)」「名称 (3)」……
+fn create_unique(
+    conn: &Connection,
+    name: &str,
+    model_id: &str,
+    chunking: &ChunkSettings,
+) -> Result<i64, String> {
+    let mut candidate = name.to_string();
+    for n in 2.. {
+        let taken: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM collections WHERE name = ?1)",
+                [&candidate],
+                |r| r.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if !taken {
+            break;
+        }
+        candidate = format!("{name} ({n})");
+    }
+    collections::create(conn, &candidate, model_id, chunking).map(|c| c.id)
+}
+
+/// 解析包内向量并转换为目标格式，维度不符时报错
+fn convert(blob: &[u8], dim: usize, format: VectorFormat) -> Result<QuantVec, String> {
+    let v = QuantVec::from_bytes(blob, Some(dim))?;
+    Ok(if v.format() == format {
+        v
+    } else {
+        QuantVec::quantize(&v.to_f32(), format)
+    })
+}
+
+/// 写入一个文件及其 chunk、向量、窗口与稀疏 term（替换本机同路径文件的旧数据）
+#[allow(clippy::too_many_arguments)]
+fn copy_file(
+    conn: &Connection,
+    src: &Connection,
+    file: &BundleFile,
+    collection_id: i64,
+    path: &str,
+    target: &MergeTarget,
+    copy_sparse: bool,
+    outcome: &mut MergeOutcome,
+) -> Result<(), String> {
+    let file_id: i64 = conn
+        .query_row(
+            "INSERT INTO files (collection_id, path, name, size_bytes, modified_at, content_hash,
+                                format, chunk_count)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT(collection_id, path) DO UPDATE SET
+                 imported_at  = CURRENT_TIMESTAMP,
+                 name         = excluded.name,
+                 size_bytes   = excluded.size_bytes,
+                 modified_at  = excluded.modified_at,
+                 content_hash = excluded.content_hash,
+                 format       = excluded.format,
+                 chunk_count  = excluded.chunk_count
+             RETURNING id",
+            rusqlite::params![
+                collection_id,
+                path,
+                file.name,
+                file.size_bytes,
+                file.modified_at,
+                file.content_hash,
+                file.format,
+                file.chunk_count
+            ],
+            |r| r.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let old: Vec<i64> = conn
+        .prepare_cached("SELECT id FROM chunks WHERE file_id = ?1")
+        .and_then(|mut stmt| stmt.query_map([file_id], |r| r.get(0))?.collect())
+        .map_err(|e| e.to_string())?;
+    outcome.removed.extend(old);
+    conn.execute("DELETE FROM chunks WHERE file_id = ?1", [file_id])
+        .map_err(|e| e.to_string())?;
+
+    let chunks: Vec<BundleChunk> = src
+        .prepare_cached(
+            "SELECT c.id, c.content, c.chunk_index, c.byte_start, c.byte_end,
+                    e.embedding, f.embedding
+             FROM chunks c
+             LEFT JOIN chunk_embeddings e ON e.chunk_id = c.id
+             LEFT JOIN chunk_embeddings_full f ON f.chunk_id = c.id
+             WHERE c.file_id = ?1
+             ORDER BY c.chunk_index",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map([file.id], |r| {
+                Ok(BundleChunk {
+                    id: r.get(0)?,
+                    content: r.get(1)?,
+                    chunk_index: r.get(2)?,
+                    byte_start: r.get(3)?,
+                    byte_end: r.get(4)?,
+                    embedding: r.get(5)?,
+                    full: r.get(6)?,
+                })
+            })?
+            .collect()
+        })
+        .map_err(|e| e.to_string())?;
+
+    for chunk in chunks {
+        copy_chunk(conn, src, file_id, chunk, target, copy_sparse, outcome)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+struct BundleChunk {
+    id: i64,
+    content: String,
+    chunk_index: i64,
+    byte_start: Option<i64>,
+    byte_end: Option<i64>,
+    embedding: Option<Vec<u8>>,
+    full: Option<Vec<u8>>,
+}
+
+/// 写入一个 chunk 及其派生数据；向量解析失败与 SQL 错误一样中止整个导入
+fn copy_chunk(
+    conn: &Connection,
+    src: &Connection,
+    file_id: i64,
+    chunk: BundleChunk,
+    target: &MergeTarget,
+    copy_sparse: bool,
+    outcome: &mut MergeOutcome,
+) -> Result<(), String> {
+    let sql = |e: rusqlite::Error| e.to_string();
+    let chunk_id = conn
+        .prepare_cached(
+            "INSERT INTO chunks (file_id, content, chunk_index, byte_start, byte_end)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .and_then(|mut stmt| {
+            stmt.insert(rusqlite::params![
+                file_id,
+                chunk.content,
+                chunk.chunk_index,
+                chunk.byte_start,
+                chunk.byte_end
+            ])
+        })
+        .map_err(sql)?;
+    outcome.chunks += 1;
+
+    if let Some(blob) = &chunk.embedding {
+        let v = convert(blob, target.dim, target.format)?;
+        conn.prepare_cached("INSERT INTO chunk_embeddings (chunk_id, embedding) VALUES (?1, ?2)")
+            .and_then(|mut stmt| stmt.execute(rusqlite::params![chunk_id, v.to_bytes()]))
+            .map_err(sql)?;
+        outcome.added.push((chunk_id, v));
+    }
+    // 完整向量只在目标库开启重新打分时保留（与本机一样存为 f32）；缺少时重新打分沿用截断分数
+    if let Some(blob) = chunk.full.as_ref().filter(|_| target.rescore) {
+        let v = convert(blob, target.full_dim, VectorFormat::F32)?;
+        conn.prepare_cached(
+            "INSERT INTO chunk_embeddings_full (chunk_id, embedding) VALUES (?1, ?2)",
+        )
+        .and_then(|mut stmt| stmt.execute(rusqlite::params![chunk_id, v.to_bytes()]))
+        .map_err(sql)?;
+    }
+
+    let windows: Vec<(i64, i64, i64, Vec<u8>)> = src
+        .prepare_cached(
+            "SELECT window_index, byte_start, byte_end, embedding FROM chunk_windows
+             WHERE chunk_id = ?1",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map([chunk.id], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?))
+            })?
+            .collect()
+        })
+        .map_err(sql)?;
+    for (index, start, end, blob) in windows {
+        let v = convert(&blob, target.dim, target.format)?;
+        conn.prepare_cached(
+            "INSERT INTO chunk_windows (chunk_id, window_index, byte_start, byte_end, embedding)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .and_then(|mut stmt| {
+            stmt.execute(rusqlite::params![chunk_id, index, start, end, v.to_bytes()])
+        })
+        .map_err(sql)?;
+    }
+
+    // 稀疏 term 是稀疏模型的词表下标，模型不同时不可用
+    if copy_sparse {
+        let terms: Vec<(i64, f64)> = src
+            .prepare_cached("SELECT term_id, weight FROM chunk_sparse WHERE chunk_id = ?1")
+            .and_then(|mut stmt| {
+                stmt.query_map([chunk.id], |r| Ok((r.get(0)?, r.get(1)?)))?
+                    .collect()
+            })
+            .map_err(sql)?;
+        let mut insert = conn
+            .prepare_cached(
+                "INSERT INTO chunk_sparse (term_id, chunk_id, weight) VALUES (?1, ?2, ?3)",
+            )
+            .map_err(sql)?;
+        for (term, weight) in terms {
+            insert
+                .execute(rusqlite::params![term, chunk_id, weight])
+                .map_err(sql)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::db;
+
+    fn target(model_id: &str, dim: usize) -> MergeTarget {
+        MergeTarget {
+            model_id: model_id.into(),
+            dim,
+            full_dim: 8,
+            rescore: false,
+            format: VectorFormat::F16,
+            sparse_model: Some("splade".into()),
+        }
+    }
+
+    #[test]
+    fn relative_paths_stay_under_root() {
+        assert_eq!(
+            relative_to("/docs/a/b.txt", "/docs").as_deref(),
+            Some("a/b.txt")
+        );
+        assert_eq!(
+            relative_to("/docs/a/b.txt", "/docs/").as_deref(),
+            Some("a/b.txt")
+        );
+        assert_eq!(relative_to("/docs2/b.txt", "/docs"), None);
+        assert_eq!(
+            relative_to(r"C:\Docs\a\b.txt", r"C:\Docs").as_deref(),
+            Some("a/b.txt")
+        );
+    }
+
+    #[test]
+    fn exported_collection_merges_with_remapped_roots() {
+        let src = db();
+        let vector = QuantVec::quantize(&[0.5, 0.5, 0.5, 0.5], VectorFormat::F32).to_bytes();
+        src.execute_batch(
+            "
+            INSERT INTO app_meta (key, value) VALUES
+                ('vector_format', 'f32'), ('sparse_model', 'splade');
+            UPDATE collections SET model_id = 'm';
+            INSERT INTO collections (id, name, model_id) VALUES (2, 'Research', 'm');
+            INSERT INTO collection_roots (collection_id, path) VALUES
+                (1, '/old/notes'), (2, '/old/research');
+            INSERT INTO files (id, collection_id, path, name, chunk_count) VALUES
+                (1, 1, '/old/notes/a.txt', 'a.txt', 1),
+                (2, 2, '/old/research/sub/b.txt', 'b.txt', 1),
+                (3, 2, '/elsewhere/c.txt', 'c.txt', 1);
+            INSERT INTO chunks (id, file_id, content, chunk_index) VALUES
+                (1, 1, 'a0', 0), (2, 2, 'b0', 0), (3, 3, 'c0', 0);
+            INSERT INTO chunk_sparse (term_id, chunk_id, weight) VALUES (7, 2, 0.5);
+            ",
+        )
+        .unwrap();
+        for id in 1..=3 {
+            src.execute(
+                "INSERT INTO chunk_embeddings (chunk_id, embedding) VALUES (?1, ?2)",
+                rusqlite::params![id, vector],
+            )
+            .unwrap();
+        }
+
+        let path = std::env::temp_dir().join(format!(
+            "locallens-test-{}.{BUNDLE_EXTENSION}",
+            std::process::id()
+        ));
+        let info = export(&src, &path, &[2]).unwrap();
+        assert!(export(&src, &path, &[9]).is_err());
+        // 模型不同的集合不能导出到同一个包
+        src.execute("UPDATE collections SET model_id = 'other' WHERE id = 1", [])
+            .unwrap();
+        assert!(export(&src, &path, &[]).is_err());
+        let bundle = Bundle::open(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!((info.model_id.as_str(), info.dim), ("m", Some(4)));
+        assert_eq!(info.collections.len(), 1);
+        assert_eq!(info.collections[0].roots, vec!["/old/research".to_string()]);
+        assert_eq!(info.collections[0].files, 2);
+
+        let dest = db();
+        let roots = HashMap::from([("/old/research".to_string(), "/new/research".to_string())]);
+        assert!(merge(&dest, &bundle, &roots, None, &target("other", 4)).is_err());
+        assert!(merge(&dest, &bundle, &roots, None, &target("m", 8)).is_err());
+        let outcome = merge(&dest, &bundle, &roots, None, &target("m", 4)).unwrap();
+        assert_eq!(
+            (outcome.files, outcome.chunks, outcome.added.len()),
+            (2, 2, 2)
+        );
+        assert!(outcome
+            .added
+            .iter()
+            .all(|(_, v)| v.format() == VectorFormat::F16));
+
+        let collection = collections::get(&dest, outcome.collections[0]).unwrap();
+        assert_eq!(collection.name, "Research");
+        assert_eq!(collection.model_id, "m");
+        assert_eq!(collection.roots, vec!["/new/research".to_string()]);
+        let paths: Vec<String> = dest
+            .prepare("SELECT path FROM files ORDER BY id")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<SqlResult<_>>()
+            .unwrap();
+        assert_eq!(paths, vec!["/new/research/sub/b.txt", "/elsewhere/c.txt"]);
+        let sparse: i64 = dest
+            .query_row("SELECT COUNT(*) FROM chunk_sparse", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(sparse, 1);
+
+        // 再次导入时新建的集合加序号，并入已有集合时替换同路径文件
+        let again = merge(&dest, &bundle, &roots, None, &target("m", 4)).unwrap();
+        let renamed = collections::get(&dest, again.collections[0]).unwrap();
+        assert_eq!(renamed.name, "Research (2)");
+        let into = merge(&dest, &bundle, &roots, Some(renamed.id), &target("m", 4)).unwrap();
+        assert_eq!(into.removed.len(), 2);
+    }
+}
@@ -0,0 +1,400 @@
+//! 命名集合（库）：每个集合有自己的根目录、嵌入模型与分段设置，文件按集合归属
+//!
+//! 所有集合共用一个数据库；向量文件与 ANN 索引按模型分开，使用同一模型的集合共用一份，
+//! 检索时按 files.collection_id 限定范围。集合的模型在创建时选定，之后不再更改。
+
+use crate::embedding::WindowOptions;
+use rusqlite::{Connection, OptionalExtension, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// 文本分段参数（按字节计）
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChunkSettings {
+    /// 段落超过该长度时按句子拆分
+    pub max_bytes: usize,
+    /// 短于该长度的段落被丢弃
+    pub min_bytes: usize,
+    /// 非空时超过一个窗口的段落按滑动窗口编码并保留各窗口向量
+    pub long_text: Option<WindowOptions>,
+}
+
+impl Default for ChunkSettings {
+    fn default() -> Self {
+        Self {
+            max_bytes: 500,
+            min_bytes: 30,
+            long_text: None,
+        }
+    }
+}
+
+impl ChunkSettings {
+    fn validate(&self) -> Result<(), String> {
+        if self.max_bytes < 50 || self.min_bytes >= self.max_bytes {
+            return Err(format!(
+                "分段长度无效：最大 {} 字节须不小于 50 且大于最小 {} 字节",
+                self.max_bytes, self.min_bytes
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Collection {
+    pub id: i64,
+    pub name: String,
+    /// 嵌入模型名（内置模型或 resources/models/ 下的附加模型）
+    pub model_id: String,
+    pub chunking: ChunkSettings,
+    /// 导入过的根目录
+    pub roots: Vec<String>,
+    pub created_at: Option<String>,
+}
+
+const COLUMNS: &str = "id, name, chunking, created_at, model_id";
+
+fn from_row(conn: &Connection, row: &rusqlite::Row) -> SqlResult<Collection> {
+    let id: i64 = row.get(0)?;
+    let chunking: String = row.get(2)?;
+    Ok(Collection {
+        id,
+        name: row.get(1)?,
+        chunking: serde_json::from_str(&chunking).unwrap_or_default(),
+        roots: roots(conn, id)?,
+        created_at: row.get(3)?,
+        model_id: row.get(4)?,
+    })
+}
+
+fn roots(conn: &Connection, id: i64) -> SqlResult<Vec<String>> {
+    conn.prepare_cached("SELECT path FROM collection_roots WHERE collection_id = ?1 ORDER BY path")?
+        .query_map([id], |r| r.get(0))?
+        .collect()
+}
+
+/// 全部集合，按 id 排序
+pub fn list(conn: &Connection) -> SqlResult<Vec<Collection>> {
+    let mut stmt = conn.prepare(&format!("SELECT {COLUMNS} FROM collections ORDER BY id"))?;
+    let rows = stmt.query_map([], |row| from_row(conn, row))?;
+    rows.collect()
+}
+
+pub fn get(conn: &Connection, id: i64) -> Result<Collection, String> {
+    conn.query_row(
+        &format!("SELECT {COLUMNS} FROM collections WHERE id = ?1"),
+        [id],
+        |row| from_row(conn, row),
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("集合 {id} 不存在"))
+}
+
+/// 指定的集合；未指定时为 id 最小的集合
+pub fn resolve(conn: &Connection, id: Option<i64>) -> Result<Collection, String> {
+    let id = match id {
+        Some(id) => id,
+        None => conn
+            .query_row("SELECT MIN(id) FROM collections", [], |r| {
+                r.get::<_, Option<i64>>(0)
+            })
+            .map_err(|e| e.to_string())?
+            .ok_or("没有可用的集合，请先创建集合")?,
+    };
+    get(conn, id)
+}
+
+fn check_name(name: &str) -> Result<&str, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("集合名称不能为空".into());
+    }
+    Ok(name)
+}
+
+/// 名称唯一（不区分大小写）冲突时给出可读的错误
+fn name_error(name: &str, e: rusqlite::Error) -> String {
+    match e {
+        rusqlite::Error::SqliteFailure(f, _)
+            if f.code == rusqlite::ErrorCode::ConstraintViolation =>
+        {
+            format!("已存在名为「{name}」的集合")
+        }
+        e => e.to_string(),
+    }
+}
+
+/// 新建集合；模型是否已安装由调用方检查
+pub fn create(
+    conn: &Connection,
+    name: &str,
+    model_id: &str,
+    chunking: &ChunkSettings,
+) -> Result<Collection, String> {
+    let name = check_name(name)?;
+    if model_id.trim().is_empty() {
+        return Err("集合的嵌入模型不能为空".into());
+    }
+    chunking.validate()?;
+    let json = serde_json::to_string(chunking).map_err(|e| e.to_string())?;
+    let id: i64 = conn
+        .query_row(
+            "INSERT INTO collections (name, model_id, chunking) VALUES (?1, ?2, ?3) RETURNING id",
+            rusqlite::params![name, model_id, json],
+            |r| r.get(0),
+        )
+        .map_err(|e| name_error(name, e))?;
+    get(conn, id)
+}
+
+pub fn rename(conn: &Connection, id: i64, name: &str) -> Result<(), String> {
+    let name = check_name(name)?;
+    let changed = conn
+        .execute(
+            "UPDATE collections SET name = ?1 WHERE id = ?2",
+            rusqlite::params![name, id],
+        )
+        .map_err(|e| name_error(name, e))?;
+    if changed == 0 {
+        return Err(format!("集合 {id} 不存在"));
+    }
+    Ok(())
+}
+
+/// 修改分段设置，下次导入时生效
+pub fn set_chunking(conn: &Connection, id: i64, chunking: &ChunkSettings) -> Result<(), String> {
+    chunking.validate()?;
+    let json = serde_json::to_string(chunking).map_err(|e| e.to_string())?;
+    let changed = conn
+        .execute(
+            "UPDATE collections SET chunking = ?1 WHERE id = ?2",
+            rusqlite::params![json, id],
+        )
+        .map_err(|e| e.to_string())?;
+    if changed == 0 {
+        return Err(format!("集合 {id} 不存在"));
+    }
+    Ok(())
+}
+
+pub fn add_root(conn: &Connection, id: i64, path: &str) -> SqlResult<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO collection_roots (collection_id, path) VALUES (?1, ?2)",
+        rusqlite::params![id, path],
+    )?;
+    Ok(())
+}
+
+/// 移除根目录记录；`id` 为 None 时从所有集合中移除
+pub fn remove_root(conn: &Connection, id: Option<i64>, path: &str) -> SqlResult<usize> {
+    conn.execute(
+        "DELETE FROM collection_roots WHERE path = ?1 AND (?2 IS NULL OR collection_id = ?2)",
+        rusqlite::params![path, id],
+    )
+}
+
+/// 集合使用的全部嵌入模型（去重）
+pub fn models_in_use(conn: &Connection) -> SqlResult<Vec<String>> {
+    conn.prepare("SELECT DISTINCT model_id FROM collections ORDER BY model_id")?
+        .query_map([], |r| r.get(0))?
+        .collect()
+}
+
+/// 按嵌入模型分组的集合 id，组按最小的集合 id 排序；`ids` 为空时包括全部集合
+pub fn by_model(conn: &Connection, ids: &[i64]) -> SqlResult<Vec<(String, Vec<i64>)>> {
+    let rows = conn
+        .prepare("SELECT id, model_id FROM collections ORDER BY id")?
+        .query_map([], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)))?
+        .collect::<SqlResult<Vec<_>>>()?;
+    let mut groups: Vec<(String, Vec<i64>)> = Vec::new();
+    for (id, model) in rows {
+        if !ids.is_empty() && !ids.contains(&id) {
+            continue;
+        }
+        match groups.iter_mut().find(|(m, _)| *m == model) {
+            Some((_, members)) => members.push(id),
+            None => groups.push((model, vec![id])),
+        }
+    }
+    Ok(groups)
+}
+
+/// 把向量表（别名 e，含 chunk_id 列）限定为某个模型的集合的 JOIN 片段，模型名绑定到 `?{param}`
+///
+/// CROSS JOIN 固定以向量表为外层循环，按 chunk_id 分批读取时仍走向量表的主键
+pub fn model_join(param: usize) -> String {
+    format!(
+        "CROSS JOIN chunks mc ON mc.id = e.chunk_id
+         CROSS JOIN files mf ON mf.id = mc.file_id
+         CROSS JOIN collections mk ON mk.id = mf.collection_id AND mk.model_id = ?{param}"
+    )
+}
+
+/// 把 chunk（别名 c）限定在 `ids` 集合中的 SQL 条件；`ids` 为空（不限定）时返回 None
+pub fn chunk_condition(ids: &[i64]) -> Option<String> {
+    (!ids.is_empty()).then(|| {
+        let list: Vec<String> = ids.iter().map(i64::to_string).collect();
+        format!(
+            "c.file_id IN (SELECT id FROM files WHERE collection_id IN ({}))",
+            list.join(", ")
+        )
+    })
+}
+
+/// 向量检索的范围：在同一模型的 chunk 中，记下所选集合内外较少的一侧，逐个候选判断
+pub enum ChunkScope {
+    All,
+    Only(HashSet<i64>),
+    Except(HashSet<i64>),
+}
+
+impl ChunkScope {
+    /// `ids` 为该模型下要检索的集合，为空时不限定
+    pub fn load(conn: &Connection, model_id: &str, ids: &[i64]) -> SqlResult<Self> {
+        let Some(condition) = chunk_condition(ids) else {
+            return Ok(ChunkScope::All);
+        };
+        let universe = "c.file_id IN (SELECT f.id FROM files f
+             JOIN collections k ON k.id = f.collection_id WHERE k.model_id = ?1)";
+        let (inside, total): (i64, i64) = conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FILTER (WHERE {condition}), COUNT(*) FROM chunks c
+                 WHERE {universe}"
+            ),
+            [model_id],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )?;
+        if inside == total {
+            return Ok(ChunkScope::All);
+        }
+        let only = inside * 2 <= total;
+        let sql = if only {
+            format!("SELECT c.id FROM chunks c WHERE {universe} AND {condition}")
+        } else {
+            format!("SELECT c.id FROM chunks c WHERE {universe} AND NOT ({condition})")
+        };
+        let ids = conn
+            .prepare(&sql)?
+            .query_map([model_id], |r| r.get(0))?
+            .collect::<SqlResult<HashSet<i64>>>()?;
+        Ok(if only {
+            ChunkScope::Only(ids)
+        } else {
+            ChunkScope::Except(ids)
+        })
+    }
+
+    pub fn is_all(&self) -> bool {
+        matches!(self, ChunkScope::All)
+    }
+
+    pub fn allows(&self, chunk_id: i64) -> bool {
+        match self {
+            ChunkScope::All => true,
+            ChunkScope::Only(ids) => ids.contains(&chunk_id),
+            ChunkScope::Except(ids) => !ids.contains(&chunk_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::db;
+
+    #[test]
+    fn create_rename_and_validate() {
+        let conn = db();
+        let c = create(&conn, " Research ", "m", &ChunkSettings::default()).unwrap();
+        assert_eq!(c.name, "Research");
+        assert_eq!(c.model_id, "m");
+        assert!(create(&conn, "research", "m", &ChunkSettings::default()).is_err());
+        assert!(create(&conn, "  ", "m", &ChunkSettings::default()).is_err());
+        assert!(create(&conn, "Notes", " ", &ChunkSettings::default()).is_err());
+        let bad = ChunkSettings {
+            max_bytes: 40,
+            ..Default::default()
+        };
+        assert!(create(&conn, "Notes", "m", &bad).is_err());
+
+        rename(&conn, c.id, "Work contracts").unwrap();
+        assert!(rename(&conn, c.id, "默认").is_err());
+        assert!(rename(&conn, 99, "x").is_err());
+        add_root(&conn, c.id, "/docs").unwrap();
+        add_root(&conn, c.id, "/docs").unwrap();
+        let c = get(&conn, c.id).unwrap();
+        assert_eq!(c.name, "Work contracts");
+        assert_eq!(c.roots, vec!["/docs".to_string()]);
+        assert_eq!(resolve(&conn, None).unwrap().id, 1);
+    }
+
+    #[test]
+    fn scope_keeps_smaller_side() {
+        let conn = db();
+        create(
+            &conn,
+            "Research",
+            crate::MODEL_NAME,
+            &ChunkSettings::default(),
+        )
+        .unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO files (id, collection_id, path, name) VALUES
+                (1, 1, '/a.txt', 'a.txt'), (2, 2, '/b.txt', 'b.txt');
+            INSERT INTO chunks (id, file_id, content, chunk_index) VALUES
+                (1, 1, 'a0', 0), (2, 1, 'a1', 1), (3, 1, 'a2', 2), (4, 2, 'b0', 0);
+            ",
+        )
+        .unwrap();
+
+        let model = crate::MODEL_NAME;
+        assert!(ChunkScope::load(&conn, model, &[]).unwrap().is_all());
+        assert!(ChunkScope::load(&conn, model, &[1, 2]).unwrap().is_all());
+
+        let research = ChunkScope::load(&conn, model, &[2]).unwrap();
+        assert!(matches!(&research, ChunkScope::Only(ids) if ids.len() == 1));
+        assert!(research.allows(4) && !research.allows(1));
+
+        let default = ChunkScope::load(&conn, model, &[1]).unwrap();
+        assert!(matches!(&default, ChunkScope::Except(ids) if ids.len() == 1));
+        assert!(default.allows(1) && !default.allows(4));
+    }
+
+    #[test]
+    fn scope_and_groups_follow_the_collection_model() {
+        let conn = db();
+        create(&conn, "Research", "other", &ChunkSettings::default()).unwrap();
+        create(&conn, "Notes", crate::MODEL_NAME, &ChunkSettings::default()).unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO files (id, collection_id, path, name) VALUES
+                (1, 1, '/a.txt', 'a.txt'), (2, 2, '/b.txt', 'b.txt'), (3, 3, '/c.txt', 'c.txt');
+            INSERT INTO chunks (id, file_id, content, chunk_index) VALUES
+                (1, 1, 'a0', 0), (2, 2, 'b0', 0), (3, 3, 'c0', 0);
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            by_model(&conn, &[]).unwrap(),
+            vec![
+                (crate::MODEL_NAME.to_string(), vec![1, 3]),
+                ("other".to_string(), vec![2]),
+            ]
+        );
+        assert_eq!(
+            by_model(&conn, &[2]).unwrap(),
+            vec![("other".to_string(), vec![2])]
+        );
+        // 另一模型的集合不计入范围：内置模型的两个集合即为全部
+        assert!(ChunkScope::load(&conn, crate::MODEL_NAME, &[1, 3])
+            .unwrap()
+            .is_all());
+        let default = ChunkScope::load(&conn, crate::MODEL_NAME, &[1]).unwrap();
+        assert!(default.allows(1) && !default.allows(3));
+    }
+}
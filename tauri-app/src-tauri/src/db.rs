@@ -0,0 +1,101 @@
+//! SQLite 连接池：启动时打开并迁移一次，之后各命令复用空闲连接
+//!
+//! 数据库使用 WAL 日志，读连接不会被导入中的写事务阻塞
+
+use crate::migrations;
+use rusqlite::Connection;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// 池中最多保留的空闲连接数（超出的连接用完即关闭）
+const MAX_IDLE: usize = 4;
+
+/// 写锁冲突时的等待上限（WAL 下只有写与写之间会互相等待）
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 页缓存大小，负数表示 KiB
+const CACHE_SIZE_KIB: i64 = -16_000;
+
+pub struct DbPool {
+    path: PathBuf,
+    idle: Mutex<Vec<Connection>>,
+}
+
+impl DbPool {
+    /// 打开数据库、切换到 WAL 并执行结构迁移
+    pub fn open(path: &Path) -> Result<Arc<Self>, String> {
+        let mut conn = Connection::open(path).map_err(|e| e.to_string())?;
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .map_err(|e| e.to_string())?;
+        migrations::migrate(&mut conn)?;
+        configure(&conn).map_err(|e| e.to_string())?;
+        Ok(Arc::new(Self {
+            path: path.to_path_buf(),
+            idle: Mutex::new(vec![conn]),
+        }))
+    }
+
+    /// 取一个连接（无空闲连接时新建），用完随 PooledConn 一起归还
+    pub fn get(self: &Arc<Self>) -> Result<PooledConn, String> {
+        let conn = match self.idle.lock().unwrap().pop() {
+            Some(conn) => conn,
+            None => {
+                let conn = Connection::open(&self.path).map_err(|e| e.to_string())?;
+                configure(&conn).map_err(|e| e.to_string())?;
+                conn
+            }
+        };
+        Ok(PooledConn {
+            conn: Some(conn),
+            pool: self.clone(),
+        })
+    }
+}
+
+/// 连接级设置（每个新连接都需要设置）
+fn configure(conn: &Connection) -> rusqlite::Result<()> {
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    // WAL 下 NORMAL 只在检查点时 fsync，断电最多丢失最近提交的事务，不会损坏数据库
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    conn.pragma_update(None, "cache_size", CACHE_SIZE_KIB)?;
+    conn.pragma_update(None, "foreign_keys", true)?;
+    Ok(())
+}
+
+/// 从池中借出的连接，Drop 时归还
+pub struct PooledConn {
+    conn: Option<Connection>,
+    pool: Arc<DbPool>,
+}
+
+impl Deref for PooledConn {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("connection already returned")
+    }
+}
+
+impl DerefMut for PooledConn {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn.as_mut().expect("connection already returned")
+    }
+}
+
+impl Drop for PooledConn {
+    fn drop(&mut self) {
+        let Some(conn) = self.conn.take() else {
+            return;
+        };
+        // 仍处于事务中的连接（出错提前返回等）直接关闭，由 SQLite 回滚
+        if !conn.is_autocommit() {
+            return;
+        }
+        let mut idle = self.pool.idle.lock().unwrap();
+        if idle.len() < MAX_IDLE {
+            idle.push(conn);
+        }
+    }
+}
@@ -0,0 +1,330 @@
+//! 向量维度：Matryoshka 截断存储的维度设置，以及设置变化时对已存向量的重写
+
+use crate::collections::model_join;
+use crate::embedding::truncate_dim;
+use crate::quant::{QuantVec, VectorFormat};
+use crate::{bump_vector_generation, ModelSettings};
+use rusqlite::{Connection, Result as SqlResult};
+
+/// 每批重写的向量数（每批一个事务，避免一次性读入全部向量）
+const BATCH_ROWS: i64 = 1000;
+
+/// 一个模型的完整维度与实际存储维度
+#[derive(Clone, Copy)]
+pub struct VectorDims {
+    pub full: usize,
+    pub stored: usize,
+    pub rescore: bool,
+}
+
+impl VectorDims {
+    pub fn new(settings: &ModelSettings, full: usize) -> Self {
+        let stored = settings.stored_dim.unwrap_or(full).min(full);
+        Self {
+            full,
+            stored,
+            rescore: settings.rescore && stored < full,
+        }
+    }
+}
+
+/// 按新的存储维度重写使用 `model_id` 的集合中已有的向量，返回（已转换, 无法转换）条数
+///
+/// 逐条检查实际存储的向量：维度不符时优先从完整向量备份截断，否则只能从更高维的已存向量截断；
+/// 无法重建的向量保留原样（检索时因维度校验失败被跳过，需重新导入）。
+/// 开启重新打分时为完整维度的已存向量补存备份（备份始终为 f32）；关闭时只有全部向量都已解决
+/// 才删除备份，否则备份是这些向量唯一的完整副本。
+pub fn resize_vectors(
+    conn: &Connection,
+    model_id: &str,
+    dims: VectorDims,
+    format: VectorFormat,
+) -> SqlResult<(usize, usize)> {
+    let (mut converted, mut unresolved) = (0usize, 0usize);
+    let mut last = 0i64;
+    loop {
+        let tx = conn.unchecked_transaction()?;
+        let batch: Vec<(i64, Vec<u8>, Option<Vec<u8>>)> = tx
+            .prepare_cached(&format!(
+                "SELECT e.chunk_id, e.embedding, f.embedding
+                 FROM chunk_embeddings e {}
+                 LEFT JOIN chunk_embeddings_full f ON f.chunk_id = e.chunk_id
+                 WHERE e.chunk_id > ?1 ORDER BY e.chunk_id LIMIT ?2",
+                model_join(3)
+            ))?
+            .query_map(rusqlite::params![last, BATCH_ROWS, model_id], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+            })?
+            .collect::<SqlResult<_>>()?;
+        let Some(&(max_id, _, _)) = batch.last() else {
+            break;
+        };
+        last = max_id;
+
+        for (chunk_id, stored, full) in batch {
+            let stored = QuantVec::from_bytes(&stored, None).ok();
+            let full = full.and_then(|b| QuantVec::from_bytes(&b, Some(dims.full)).ok());
+            if dims.rescore && full.is_none() {
+                if let Some(v) = stored.as_ref().filter(|v| v.dim() == dims.full) {
+                    tx.prepare_cached(
+                        "INSERT INTO chunk_embeddings_full (chunk_id, embedding) VALUES (?1, ?2)",
+                    )?
+                    .execute(rusqlite::params![
+                        chunk_id,
+                        QuantVec::F32(v.to_f32()).to_bytes()
+                    ])?;
+                }
+            }
+            if stored.as_ref().is_some_and(|v| v.dim() == dims.stored) {
+                continue;
+            }
+            let source = full.or(stored).filter(|v| v.dim() >= dims.stored);
+            let Some(source) = source else {
+                unresolved += 1;
+                continue;
+            };
+            let resized = truncate_dim(&source.to_f32(), dims.stored);
+            tx.prepare_cached("UPDATE chunk_embeddings SET embedding = ?1 WHERE chunk_id = ?2")?
+                .execute(rusqlite::params![
+                    QuantVec::quantize(&resized, format).to_bytes(),
+                    chunk_id
+                ])?;
+            converted += 1;
+        }
+        tx.commit()?;
+    }
+
+    resize_windows(conn, model_id, dims.stored, format)?;
+
+    if !dims.rescore && unresolved == 0 {
+        conn.execute(
+            &format!(
+                "DELETE FROM chunk_embeddings_full WHERE chunk_id IN
+                 (SELECT e.chunk_id FROM chunk_embeddings_full e {})",
+                model_join(1)
+            ),
+            [model_id],
+        )?;
+    }
+    if converted > 0 {
+        bump_vector_generation(conn, model_id)?;
+    }
+    Ok((converted, unresolved))
+}
+
+/// 窗口向量只用于定位：能截断的截断，无法升维的直接删除
+fn resize_windows(
+    conn: &Connection,
+    model_id: &str,
+    dim: usize,
+    format: VectorFormat,
+) -> SqlResult<()> {
+    let mut last = 0i64;
+    loop {
+        let tx = conn.unchecked_transaction()?;
+        let batch: Vec<(i64, Vec<u8>)> = tx
+            .prepare_cached(&format!(
+                "SELECT e.rowid, e.embedding FROM chunk_windows e {}
+                 WHERE e.rowid > ?1 ORDER BY e.rowid LIMIT ?2",
+                model_join(3)
+            ))?
+            .query_map(rusqlite::params![last, BATCH_ROWS, model_id], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })?
+            .collect::<SqlResult<_>>()?;
+        let Some(&(max_rowid, _)) = batch.last() else {
+            break;
+        };
+        last = max_rowid;
+        for (rowid, blob) in batch {
+            match QuantVec::from_bytes(&blob, None) {
+                Ok(v) if v.dim() == dim => {}
+                Ok(v) if v.dim() > dim => {
+                    let truncated = truncate_dim(&v.to_f32(), dim);
+                    tx.prepare_cached("UPDATE chunk_windows SET embedding = ?1 WHERE rowid = ?2")?
+                        .execute(rusqlite::params![
+                            QuantVec::quantize(&truncated, format).to_bytes(),
+                            rowid
+                        ])?;
+                }
+                _ => {
+                    tx.prepare_cached("DELETE FROM chunk_windows WHERE rowid = ?1")?
+                        .execute([rowid])?;
+                }
+            }
+        }
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use crate::MODEL_NAME;
+
+    fn unit(seed: usize, dim: usize) -> Vec<f32> {
+        crate::embedding::l2_normalize(
+            (0..dim)
+                .map(|i| ((seed * 7 + i) % 5) as f32 + 0.5)
+                .collect(),
+        )
+    }
+
+    fn stored_dims(conn: &Connection) -> Vec<usize> {
+        conn.prepare("SELECT embedding FROM chunk_embeddings ORDER BY chunk_id")
+            .unwrap()
+            .query_map([], |r| r.get::<_, Vec<u8>>(0))
+            .unwrap()
+            .map(|b| QuantVec::from_bytes(&b.unwrap(), None).unwrap().dim())
+            .collect()
+    }
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |r| r.get(0))
+            .unwrap()
+    }
+
+    fn db(n: usize) -> Connection {
+        let conn = test_support::db();
+        conn.execute(
+            "INSERT INTO files (path, name) VALUES ('/a.txt', 'a.txt')",
+            [],
+        )
+        .unwrap();
+        for i in 0..n {
+            conn.execute(
+                "INSERT INTO chunks (id, file_id, content, chunk_index) VALUES (?1, 1, 'x', ?1)",
+                [i as i64 + 1],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO chunk_embeddings (chunk_id, embedding) VALUES (?1, ?2)",
+                rusqlite::params![
+                    i as i64 + 1,
+                    QuantVec::quantize(&unit(i, 8), VectorFormat::F32).to_bytes()
+                ],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    const FULL: VectorDims = VectorDims {
+        full: 8,
+        stored: 8,
+        rescore: false,
+    };
+
+    #[test]
+    fn truncate_with_rescore_then_untruncate_restores_full_vectors() {
+        let conn = db(3);
+        let truncated = VectorDims {
+            full: 8,
+            stored: 4,
+            rescore: true,
+        };
+        assert_eq!(
+            resize_vectors(&conn, MODEL_NAME, truncated, VectorFormat::F32).unwrap(),
+            (3, 0)
+        );
+        assert_eq!(stored_dims(&conn), vec![4, 4, 4]);
+        assert_eq!(count(&conn, "chunk_embeddings_full"), 3);
+
+        assert_eq!(
+            resize_vectors(&conn, MODEL_NAME, FULL, VectorFormat::F32).unwrap(),
+            (3, 0)
+        );
+        assert_eq!(stored_dims(&conn), vec![8, 8, 8]);
+        assert_eq!(count(&conn, "chunk_embeddings_full"), 0);
+        let first: Vec<u8> = conn
+            .query_row(
+                "SELECT embedding FROM chunk_embeddings WHERE chunk_id = 1",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(
+            QuantVec::from_bytes(&first, Some(8)).unwrap().to_f32(),
+            unit(0, 8)
+        );
+    }
+
+    #[test]
+    fn backups_keep_full_precision_under_int8_storage() {
+        let conn = db(2);
+        let truncated = VectorDims {
+            full: 8,
+            stored: 4,
+            rescore: true,
+        };
+        resize_vectors(&conn, MODEL_NAME, truncated, VectorFormat::Int8).unwrap();
+        let formats: Vec<VectorFormat> = conn
+            .prepare("SELECT embedding FROM chunk_embeddings_full ORDER BY chunk_id")
+            .unwrap()
+            .query_map([], |r| r.get::<_, Vec<u8>>(0))
+            .unwrap()
+            .map(|b| QuantVec::from_bytes(&b.unwrap(), Some(8)).unwrap().format())
+            .collect();
+        assert_eq!(formats, vec![VectorFormat::F32; 2]);
+
+        // 恢复完整维度时从 f32 备份重建，int8 存储没有损失备份的精度
+        resize_vectors(&conn, MODEL_NAME, FULL, VectorFormat::F32).unwrap();
+        let first: Vec<u8> = conn
+            .query_row(
+                "SELECT embedding FROM chunk_embeddings WHERE chunk_id = 1",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(
+            QuantVec::from_bytes(&first, Some(8)).unwrap().to_f32(),
+            unit(0, 8)
+        );
+    }
+
+    #[test]
+    fn untruncate_without_backup_keeps_rows_unresolved() {
+        let conn = db(2);
+        let truncated = VectorDims {
+            full: 8,
+            stored: 4,
+            rescore: false,
+        };
+        assert_eq!(
+            resize_vectors(&conn, MODEL_NAME, truncated, VectorFormat::F32).unwrap(),
+            (2, 0)
+        );
+        assert_eq!(count(&conn, "chunk_embeddings_full"), 0);
+
+        assert_eq!(
+            resize_vectors(&conn, MODEL_NAME, FULL, VectorFormat::F32).unwrap(),
+            (0, 2)
+        );
+        assert_eq!(stored_dims(&conn), vec![4, 4]);
+    }
+
+    #[test]
+    fn other_models_are_left_alone() {
+        let conn = db(2);
+        conn.execute_batch(
+            "
+            INSERT INTO collections (id, name, model_id) VALUES (2, 'Other', 'other');
+            UPDATE files SET collection_id = 2;
+            ",
+        )
+        .unwrap();
+        let truncated = VectorDims {
+            full: 8,
+            stored: 4,
+            rescore: true,
+        };
+        assert_eq!(
+            resize_vectors(&conn, MODEL_NAME, truncated, VectorFormat::F32).unwrap(),
+            (0, 0)
+        );
+        assert_eq!(stored_dims(&conn), vec![8, 8]);
+        assert_eq!(count(&conn, "chunk_embeddings_full"), 0);
+    }
+}
@@ -1,12 +1,19 @@
 //! 文本 embedding 模块（ort 2.0-rc.11 API）
 
+use crate::manifest::{sha256_hex, ModelManifest};
+use ort::session::builder::{GraphOptimizationLevel, SessionBuilder};
 use ort::session::Session;
 use ort::value::Tensor;
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 use tokenizers::Tokenizer;
 
 const MAX_SEQ: usize = 128;
 
+/// 预热推理使用的文本（中英混合，覆盖常见 tokenizer 路径）
+const WARMUP_TEXT: &str = "LocalLens 预热推理：the quick brown fox jumps over the lazy dog.";
+
 // ── EmbeddingModel ────────────────────────────────────────────────────────────
 
 pub struct EmbeddingModel {
@@ -14,13 +21,31 @@ pub struct EmbeddingModel {
     tokenizer: Tokenizer,
     /// 部分 ONNX 导出不含 token_type_ids 输入，加载时自动检测
     has_type_ids: bool,
+    /// 实际生效的会话配置
+    session_config: EffectiveSessionConfig,
+    /// 输出向量维度（完整维度，未截断）
+    dimension: usize,
+    diagnostics: ModelDiagnostics,
 }
 
-unsafe impl Send for EmbeddingModel {}
-unsafe impl Sync for EmbeddingModel {}
+/// 模型加载诊断信息
+#[derive(Clone, Debug, Serialize)]
+pub struct ModelDiagnostics {
+    /// 创建会话与加载 tokenizer 的耗时
+    pub load_ms: f64,
+    /// 加载后第一次推理（预热）的耗时
+    pub first_inference_ms: f64,
+    pub dimension: usize,
+    pub input_names: Vec<String>,
+    pub output_names: Vec<String>,
+}
 
 impl EmbeddingModel {
-    pub fn load(model_path: &Path, tokenizer_path: &Path) -> Result<Self, String> {
+    pub fn load(
+        model_path: &Path,
+        tokenizer_path: &Path,
+        opts: &SessionOptions,
+    ) -> Result<Self, String> {
         if !model_path.exists() {
             return Err(format!("模型文件未找到: {}", model_path.display()));
         }
@@ -28,13 +53,59 @@ impl EmbeddingModel {
             return Err(format!("Tokenizer 未找到: {}", tokenizer_path.display()));
         }
 
+        let started = Instant::now();
+
         // 全局 ORT 初始化（幂等）
         ort::init().with_name("LocalLens").commit();
 
-        let session = Session::builder()
-            .map_err(|e| format!("SessionBuilder 失败: {e}"))?
-            .commit_from_file(model_path)
-            .map_err(|e| format!("模型加载失败: {e}"))?;
+        let resolved = opts.resolve();
+
+        // 优化后模型缓存的键与当前会话参数、ORT 版本及原模型一致时直接加载缓存，跳过图优化
+        let cache_key = resolved
+            .optimized_model_path
+            .as_ref()
+            .map(|_| optimized_cache_key(&resolved, model_path));
+        let cached = resolved.optimized_model_path.clone().filter(|p| {
+            cache_key
+                .as_deref()
+                .is_some_and(|key| cache_matches(p, key))
+        });
+
+        let session = match &cached {
+            Some(cache_path) => {
+                let mut no_opt = resolved.clone();
+                no_opt.optimization_level = OptimizationLevel::Disable;
+                no_opt.optimized_model_path = None;
+                build_session(&no_opt)?
+                    .commit_from_file(cache_path)
+                    .map_err(|e| format!("优化模型缓存加载失败: {e}"))?
+            }
+            None => {
+                // 先删除旧键，写缓存中途失败时不会留下与键匹配的残缺文件
+                if let Some(cache_path) = &resolved.optimized_model_path {
+                    std::fs::remove_file(key_path(cache_path)).ok();
+                }
+                let session = build_session(&resolved)?
+                    .commit_from_file(model_path)
+                    .map_err(|e| format!("模型加载失败: {e}"))?;
+                if let (Some(cache_path), Some(key)) = (&resolved.optimized_model_path, &cache_key)
+                {
+                    if cache_path.exists() {
+                        std::fs::write(key_path(cache_path), key).ok();
+                    }
+                }
+                session
+            }
+        };
+        let session_config = EffectiveSessionConfig {
+            applied_optimization: resolved.optimization_level,
+            options: resolved,
+            loaded_from_cache: cached.is_some(),
+            cache_key,
+            ort_build: ort::info().to_string(),
+            available_cores: available_cores(),
+        };
+        eprintln!("[LocalLens] ORT 会话配置: {:?}", session_config);
 
         // 检测模型是否需要 token_type_ids 输入
         let has_type_ids = session
@@ -49,14 +120,133 @@ impl EmbeddingModel {
         let tokenizer = Tokenizer::from_file(tokenizer_path)
             .map_err(|e| format!("Tokenizer 加载失败: {e}"))?;
 
-        Ok(Self {
+        // 输出维度：优先取静态形状，动态时试推理一次
+        let static_dim = session
+            .outputs()
+            .iter()
+            .find(|o| o.name() == "last_hidden_state")
+            .and_then(|o| o.dtype().tensor_shape())
+            .and_then(|shape| shape.get(2).copied())
+            .filter(|&d| d > 0);
+
+        let diagnostics = ModelDiagnostics {
+            load_ms: started.elapsed().as_secs_f64() * 1000.0,
+            first_inference_ms: 0.0,
+            dimension: 0,
+            input_names: session
+                .inputs()
+                .iter()
+                .map(|i| i.name().to_string())
+                .collect(),
+            output_names: session
+                .outputs()
+                .iter()
+                .map(|o| o.name().to_string())
+                .collect(),
+        };
+        let mut model = Self {
             session,
             tokenizer,
             has_type_ids,
-        })
+            session_config,
+            dimension: static_dim.unwrap_or(0) as usize,
+            diagnostics,
+        };
+
+        // 预热：首次推理会触发 ORT 的内存分配与内核初始化，放在加载阶段完成，
+        // 避免启动后的第一个查询变慢；动态维度的模型同时由此得到输出维度
+        let warmup = Instant::now();
+        let probe = model.encode(WARMUP_TEXT)?;
+        model.diagnostics.first_inference_ms = warmup.elapsed().as_secs_f64() * 1000.0;
+        if model.dimension == 0 {
+            model.dimension = probe.len();
+        }
+        model.diagnostics.dimension = model.dimension;
+        eprintln!(
+            "[LocalLens] 模型加载 {:.0} ms，预热推理 {:.0} ms",
+            model.diagnostics.load_ms, model.diagnostics.first_inference_ms
+        );
+        Ok(model)
+    }
+
+    pub fn diagnostics(&self) -> &ModelDiagnostics {
+        &self.diagnostics
+    }
+
+    pub fn session_config(&self) -> &EffectiveSessionConfig {
+        &self.session_config
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
     }
 
-    /// 将文本编码为 L2-normalized 向量
+    /// 检查 tokenizer、模型与清单是否相互匹配（输入输出形状、向量维度、词表范围）
+    pub fn check_consistency(&mut self, manifest: Option<&ModelManifest>) -> Result<(), String> {
+        for name in ["input_ids", "attention_mask"] {
+            let input = self
+                .session
+                .inputs()
+                .iter()
+                .find(|i| i.name() == name)
+                .ok_or_else(|| format!("模型缺少输入 {name}"))?;
+            match input.dtype().tensor_shape() {
+                Some(shape) if shape.len() == 2 => {
+                    // -1 为动态维度；固定长度小于 MAX_SEQ 时长文本会推理失败
+                    if shape[1] > 0 && (shape[1] as usize) < MAX_SEQ {
+                        return Err(format!(
+                            "模型输入 {name} 的序列长度固定为 {}，小于 {MAX_SEQ}",
+                            shape[1]
+                        ));
+                    }
+                }
+                _ => return Err(format!("模型输入 {name} 不是 [batch, seq] 形状")),
+            }
+        }
+
+        let hidden_dim = self
+            .session
+            .outputs()
+            .iter()
+            .find(|o| o.name() == "last_hidden_state")
+            .ok_or("模型缺少输出 last_hidden_state")?
+            .dtype()
+            .tensor_shape()
+            .filter(|shape| shape.len() == 3)
+            .ok_or("模型输出 last_hidden_state 不是 [batch, seq, hidden] 形状")?[2];
+
+        let vocab = self.tokenizer.get_vocab_size(true);
+        if let Some(expected) = manifest.and_then(|m| m.vocab_size) {
+            if vocab != expected {
+                return Err(format!(
+                    "tokenizer 词表大小为 {vocab}，清单记录为 {expected}（tokenizer.json 与模型不匹配）"
+                ));
+            }
+        }
+
+        // 用词表中最大的 token id 试推理：模型词嵌入表小于词表时会越界报错
+        let probe = self
+            .run_pooled(&[vocab.saturating_sub(1) as u32], &[1], &[0])
+            .map_err(|e| format!("tokenizer 词表（{vocab}）超出模型词嵌入范围: {e}"))?;
+
+        if hidden_dim > 0 && probe.len() != hidden_dim as usize {
+            return Err(format!(
+                "模型输出维度 {} 与声明的 {hidden_dim} 不一致",
+                probe.len()
+            ));
+        }
+        if let Some(expected) = manifest.and_then(|m| m.dimension) {
+            if probe.len() != expected {
+                return Err(format!(
+                    "模型输出维度为 {}，清单记录为 {expected}",
+                    probe.len()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// 将文本编码为 L2-normalized 向量（超过 MAX_SEQ 的部分被截断）
     pub fn encode(&mut self, text: &str) -> Result<Vec<f32>, String> {
         let enc = self
             .tokenizer
@@ -64,16 +254,101 @@ impl EmbeddingModel {
             .map_err(|e| e.to_string())?;
 
         let seq_len = enc.get_ids().len().min(MAX_SEQ);
+        self.run_pooled(
+            &enc.get_ids()[..seq_len],
+            &enc.get_attention_mask()[..seq_len],
+            &enc.get_type_ids()[..seq_len],
+        )
+    }
 
-        let input_ids: Vec<i64> = enc.get_ids()[..seq_len].iter().map(|&x| x as i64).collect();
-        let attn_mask: Vec<i64> = enc.get_attention_mask()[..seq_len]
-            .iter()
-            .map(|&x| x as i64)
-            .collect();
-        let mask_f32: Vec<f32> = enc.get_attention_mask()[..seq_len]
-            .iter()
-            .map(|&x| x as f32)
-            .collect();
+    /// 滑动窗口编码：按 token 切成重叠窗口分别推理，再合并为一个向量。
+    ///
+    /// 文本不超过一个窗口时结果与 `encode` 一致，`windows` 只含一项。
+    pub fn encode_windowed(
+        &mut self,
+        text: &str,
+        opts: &WindowOptions,
+    ) -> Result<WindowedEmbedding, String> {
+        // tokenizer.json 可能自带截断/填充配置，窗口切分需要完整 token 序列
+        let truncation = self.tokenizer.get_truncation().cloned();
+        let padding = self.tokenizer.get_padding().cloned();
+        self.tokenizer
+            .with_truncation(None)
+            .map_err(|e| e.to_string())?
+            .with_padding(None);
+        let enc = self.tokenizer.encode(text, true);
+        self.tokenizer
+            .with_truncation(truncation)
+            .map_err(|e| e.to_string())?
+            .with_padding(padding);
+        let enc = enc.map_err(|e| e.to_string())?;
+
+        let ids = enc.get_ids();
+        let type_ids = enc.get_type_ids();
+        let offsets = enc.get_offsets();
+        let special = enc.get_special_tokens_mask();
+
+        // 首尾的特殊 token（如 [CLS]/[SEP]、<s>/</s>）在每个窗口中重复添加
+        let head = special.iter().take_while(|&&s| s == 1).count();
+        let tail = special[head..].iter().rev().take_while(|&&s| s == 1).count();
+        let body = head..ids.len() - tail;
+
+        let budget = opts.window_tokens.min(MAX_SEQ).saturating_sub(head + tail);
+        if budget == 0 || opts.overlap_tokens >= budget {
+            return Err(format!(
+                "窗口参数无效: window_tokens={} overlap_tokens={}",
+                opts.window_tokens, opts.overlap_tokens
+            ));
+        }
+        let step = budget - opts.overlap_tokens;
+
+        let mut windows = Vec::new();
+        let mut start = body.start;
+        loop {
+            let end = (start + budget).min(body.end);
+
+            let mut w_ids = Vec::with_capacity(end - start + head + tail);
+            let mut w_types = Vec::with_capacity(w_ids.capacity());
+            for i in (0..head).chain(start..end).chain(body.end..ids.len()) {
+                w_ids.push(ids[i]);
+                w_types.push(type_ids[i]);
+            }
+            let w_mask = vec![1u32; w_ids.len()];
+            let vector = self.run_pooled(&w_ids, &w_mask, &w_types)?;
+
+            let (byte_start, byte_end) = if start < end {
+                (offsets[start].0, offsets[end - 1].1)
+            } else {
+                (0, 0)
+            };
+            windows.push(WindowVector {
+                byte_start,
+                byte_end,
+                vector,
+            });
+
+            if end >= body.end {
+                break;
+            }
+            start += step;
+        }
+
+        let combined = combine_windows(&windows, opts.pooling);
+        Ok(WindowedEmbedding { combined, windows })
+    }
+
+    /// 对一段 token 序列推理并做 attention mask 加权平均池化
+    fn run_pooled(
+        &mut self,
+        ids: &[u32],
+        mask: &[u32],
+        type_ids: &[u32],
+    ) -> Result<Vec<f32>, String> {
+        let seq_len = ids.len();
+
+        let input_ids: Vec<i64> = ids.iter().map(|&x| x as i64).collect();
+        let attn_mask: Vec<i64> = mask.iter().map(|&x| x as i64).collect();
+        let mask_f32: Vec<f32> = mask.iter().map(|&x| x as f32).collect();
 
         let ids_ort = Tensor::<i64>::from_array(([1_usize, seq_len], input_ids))
             .map_err(|e| e.to_string())?;
@@ -81,10 +356,7 @@ impl EmbeddingModel {
             .map_err(|e| e.to_string())?;
 
         let outputs = if self.has_type_ids {
-            let type_ids: Vec<i64> = enc.get_type_ids()[..seq_len]
-                .iter()
-                .map(|&x| x as i64)
-                .collect();
+            let type_ids: Vec<i64> = type_ids.iter().map(|&x| x as i64).collect();
             let types_ort = Tensor::<i64>::from_array(([1_usize, seq_len], type_ids))
                 .map_err(|e| e.to_string())?;
             self.session
@@ -130,9 +402,225 @@ impl EmbeddingModel {
     }
 }
 
+// ── 会话配置 ──────────────────────────────────────────────────────────────────
+
+/// 图优化级别（对应 ORT GraphOptimizationLevel）
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OptimizationLevel {
+    Disable,
+    Basic,
+    Extended,
+    All,
+}
+
+impl From<OptimizationLevel> for GraphOptimizationLevel {
+    fn from(level: OptimizationLevel) -> Self {
+        match level {
+            OptimizationLevel::Disable => GraphOptimizationLevel::Disable,
+            OptimizationLevel::Basic => GraphOptimizationLevel::Level1,
+            OptimizationLevel::Extended => GraphOptimizationLevel::Level2,
+            OptimizationLevel::All => GraphOptimizationLevel::All,
+        }
+    }
+}
+
+/// ONNX Runtime 会话参数，线程数为 0 时按本机核数自动决定
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionOptions {
+    /// 算子内并行线程数；自动 = 逻辑核数的一半（至少 1），给 UI 线程留出余量
+    pub intra_threads: usize,
+    /// 算子间并行线程数，仅 parallel_execution 开启时有意义；自动 = 1
+    pub inter_threads: usize,
+    pub parallel_execution: bool,
+    pub optimization_level: OptimizationLevel,
+    /// CPU 内存 arena：复用大块内存，推理更快但常驻内存更高
+    pub cpu_arena: bool,
+    /// 按首次推理的内存分配模式预分配，适合输入形状固定的场景
+    pub memory_pattern: bool,
+    /// 优化后模型的缓存文件；首次加载时写出，之后直接加载
+    pub optimized_model_path: Option<PathBuf>,
+    /// 推理工作线程数，每个线程持有独立会话（内存占用随之成倍增加）；自动 = 1
+    pub workers: usize,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self {
+            intra_threads: 0,
+            inter_threads: 0,
+            parallel_execution: false,
+            optimization_level: OptimizationLevel::All,
+            cpu_arena: true,
+            memory_pattern: true,
+            optimized_model_path: None,
+            workers: 0,
+        }
+    }
+}
+
+impl SessionOptions {
+    /// 把自动线程数替换为具体值
+    pub fn resolve(&self) -> Self {
+        let cores = available_cores();
+        let mut out = self.clone();
+        if out.intra_threads == 0 {
+            out.intra_threads = (cores / 2).max(1);
+        }
+        if out.inter_threads == 0 {
+            out.inter_threads = 1;
+        }
+        if out.workers == 0 {
+            out.workers = 1;
+        }
+        out
+    }
+}
+
+/// 模型加载后实际生效的会话配置
+#[derive(Clone, Debug, Serialize)]
+pub struct EffectiveSessionConfig {
+    /// 请求的参数（自动线程数已解析）
+    #[serde(flatten)]
+    pub options: SessionOptions,
+    /// 模型图实际经过的优化级别（加载缓存时为写出缓存时的级别，缓存键保证与请求一致）
+    pub applied_optimization: OptimizationLevel,
+    /// 本次是否直接加载了优化模型缓存
+    pub loaded_from_cache: bool,
+    /// 优化模型缓存的键；未配置缓存路径时为 None
+    pub cache_key: Option<String>,
+    /// ONNX Runtime 构建信息（版本与提交）
+    pub ort_build: String,
+    pub available_cores: usize,
+}
+
+fn available_cores() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+pub(crate) fn build_session(opts: &SessionOptions) -> Result<SessionBuilder, String> {
+    let err = |e: ort::Error| format!("SessionBuilder 失败: {e}");
+    let mut builder = Session::builder()
+        .map_err(err)?
+        .with_intra_threads(opts.intra_threads)
+        .map_err(err)?
+        .with_inter_threads(opts.inter_threads)
+        .map_err(err)?
+        .with_parallel_execution(opts.parallel_execution)
+        .map_err(err)?
+        .with_optimization_level(opts.optimization_level.into())
+        .map_err(err)?
+        .with_memory_pattern(opts.memory_pattern)
+        .map_err(err)?
+        .with_execution_providers([ort::ep::CPU::default()
+            .with_arena_allocator(opts.cpu_arena)
+            .build()])
+        .map_err(err)?;
+    if let Some(path) = &opts.optimized_model_path {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).ok();
+        }
+        builder = builder.with_optimized_model_path(path).map_err(err)?;
+    }
+    Ok(builder)
+}
+
+/// 优化模型缓存的键：会话参数、ORT 构建信息以及原模型的大小与修改时间，任一变化都需要重新优化
+fn optimized_cache_key(opts: &SessionOptions, model: &Path) -> String {
+    let mut opts = opts.clone();
+    opts.optimized_model_path = None;
+    let model_stamp = std::fs::metadata(model).ok().map(|m| {
+        let modified = m
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos());
+        (m.len(), modified)
+    });
+    let options = serde_json::to_string(&opts).unwrap_or_default();
+    sha256_hex(format!("{options}\0{}\0{model_stamp:?}", ort::info()).as_bytes())
+}
+
+/// 缓存键文件：与缓存同名，追加 .key 后缀
+fn key_path(cache: &Path) -> PathBuf {
+    let mut path = cache.as_os_str().to_owned();
+    path.push(".key");
+    PathBuf::from(path)
+}
+
+/// 缓存文件存在且写出时记录的键与 `key` 相同
+fn cache_matches(cache: &Path, key: &str) -> bool {
+    cache.exists()
+        && std::fs::read_to_string(key_path(cache)).is_ok_and(|stored| stored.trim() == key)
+}
+
+// ── 滑动窗口 ──────────────────────────────────────────────────────────────────
+
+/// 多个窗口向量合并为一个向量的方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowPooling {
+    Mean,
+    Max,
+}
+
+/// 长文本滑动窗口参数（window_tokens 含特殊 token，上限 MAX_SEQ）
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowOptions {
+    pub window_tokens: usize,
+    pub overlap_tokens: usize,
+    pub pooling: WindowPooling,
+}
+
+impl Default for WindowOptions {
+    fn default() -> Self {
+        Self {
+            window_tokens: MAX_SEQ,
+            overlap_tokens: 32,
+            pooling: WindowPooling::Mean,
+        }
+    }
+}
+
+/// 单个窗口的向量及其在原文中的字节区间
+pub struct WindowVector {
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub vector: Vec<f32>,
+}
+
+pub struct WindowedEmbedding {
+    /// 合并后的 L2-normalized 向量（用于检索）
+    pub combined: Vec<f32>,
+    /// 各窗口向量（用于定位命中位置）
+    pub windows: Vec<WindowVector>,
+}
+
+fn combine_windows(windows: &[WindowVector], pooling: WindowPooling) -> Vec<f32> {
+    let dim = windows.first().map(|w| w.vector.len()).unwrap_or(0);
+    let mut out = match pooling {
+        WindowPooling::Mean => vec![0.0f32; dim],
+        WindowPooling::Max => vec![f32::NEG_INFINITY; dim],
+    };
+    for w in windows {
+        for (o, &x) in out.iter_mut().zip(&w.vector) {
+            match pooling {
+                WindowPooling::Mean => *o += x,
+                WindowPooling::Max => *o = o.max(x),
+            }
+        }
+    }
+    // Mean 时除以窗口数不影响方向，直接归一化即可
+    l2_normalize(out)
+}
+
 // ── 工具函数 ──────────────────────────────────────────────────────────────────
 
-fn l2_normalize(mut v: Vec<f32>) -> Vec<f32> {
+pub fn l2_normalize(mut v: Vec<f32>) -> Vec<f32> {
     let norm: f32 = v.iter().map(|x| x * x).sum::<f32>().sqrt().max(1e-9);
     for x in &mut v {
         *x /= norm;
@@ -142,7 +630,7 @@ fn l2_normalize(mut v: Vec<f32>) -> Vec<f32> {
 
 /// 余弦相似度（两个向量均已 L2 归一化，直接点积）
 pub fn cosine_sim(a: &[f32], b: &[f32]) -> f32 {
-    a.iter().zip(b).map(|(x, y)| x * y).sum()
+    crate::quant::dot_lanes(a, b, |x| x)
 }
 
 /// Vec<f32> → little-endian 字节（SQLite BLOB 存储）
@@ -150,9 +638,25 @@ pub fn vec_to_bytes(v: &[f32]) -> Vec<u8> {
     v.iter().flat_map(|f| f.to_le_bytes()).collect()
 }
 
-/// SQLite BLOB 字节 → Vec<f32>
-pub fn bytes_to_vec(b: &[u8]) -> Vec<f32> {
-    b.chunks_exact(4)
+/// SQLite BLOB 字节 → Vec<f32>，`dim` 给出时校验长度
+pub fn bytes_to_vec(b: &[u8], dim: Option<usize>) -> Result<Vec<f32>, String> {
+    let expected = dim.map(|d| d * 4);
+    if !b.len().is_multiple_of(4) || expected.is_some_and(|n| n != b.len()) {
+        return Err(format!(
+            "向量 BLOB 长度 {} 与期望不符（维度 {}）",
+            b.len(),
+            dim.map_or("?".to_string(), |d| d.to_string())
+        ));
+    }
+    Ok(b.chunks_exact(4)
         .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
-        .collect()
+        .collect())
+}
+
+/// Matryoshka 截断：保留前 `dim` 维并重新归一化（dim 不小于原维度时原样返回）
+pub fn truncate_dim(v: &[f32], dim: usize) -> Vec<f32> {
+    if dim >= v.len() {
+        return v.to_vec();
+    }
+    l2_normalize(v[..dim].to_vec())
 }
@@ -0,0 +1,209 @@
+//! 按内容寻址的 embedding 缓存：重新导入时未修改的段落直接复用向量
+//!
+//! 键为段落文本与窗口参数的 SHA-256，向量为完整维度 f32，与存储格式、截断设置无关。
+//! 每次命中或写入都记录使用时间，导入结束后按最近最少使用淘汰超出上限的条目。
+
+use crate::embedding::{WindowOptions, WindowVector, WindowedEmbedding};
+use crate::manifest::sha256_hex;
+use crate::quant::QuantVec;
+use rusqlite::{Connection, Result as SqlResult};
+
+/// 缓存条目数的下限；库中 chunk 较多时上限放宽到 chunk 数的两倍，保证整库重新导入仍能命中
+pub const CACHE_MIN_ENTRIES: usize = 50_000;
+
+/// 缓存键：段落文本 + 窗口参数（窗口参数不同，合并向量与窗口划分都不同）
+pub fn cache_key(text: &str, windows: Option<&WindowOptions>) -> String {
+    let params = windows
+        .and_then(|w| serde_json::to_string(w).ok())
+        .unwrap_or_default();
+    sha256_hex(format!("{params}\0{text}").as_bytes())
+}
+
+/// 查找指定模型下的缓存向量
+pub fn load(conn: &Connection, model_id: &str, key: &str) -> Option<WindowedEmbedding> {
+    let combined = conn
+        .prepare_cached(
+            "SELECT embedding FROM embedding_cache WHERE model_id = ?1 AND text_hash = ?2",
+        )
+        .ok()?
+        .query_row(rusqlite::params![model_id, key], |r| r.get::<_, Vec<u8>>(0))
+        .ok()
+        .and_then(|b| QuantVec::from_bytes(&b, None).ok())?
+        .to_f32();
+
+    let windows = conn
+        .prepare_cached(
+            "SELECT byte_start, byte_end, embedding FROM embedding_cache_windows
+             WHERE model_id = ?1 AND text_hash = ?2 ORDER BY window_index",
+        )
+        .ok()?
+        .query_map(rusqlite::params![model_id, key], |row| {
+            Ok((
+                row.get::<_, i64>(0)? as usize,
+                row.get::<_, i64>(1)? as usize,
+                row.get::<_, Vec<u8>>(2)?,
+            ))
+        })
+        .ok()?
+        .map(|r| {
+            let (byte_start, byte_end, blob) = r.ok()?;
+            Some(WindowVector {
+                byte_start,
+                byte_end,
+                vector: QuantVec::from_bytes(&blob, Some(combined.len()))
+                    .ok()?
+                    .to_f32(),
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(WindowedEmbedding { combined, windows })
+}
+
+pub fn store(
+    conn: &Connection,
+    model_id: &str,
+    key: &str,
+    emb: &WindowedEmbedding,
+) -> SqlResult<()> {
+    conn.prepare_cached(
+        "INSERT OR REPLACE INTO embedding_cache (model_id, text_hash, embedding, used_at)
+         VALUES (?1, ?2, ?3, unixepoch())",
+    )?
+    .execute(rusqlite::params![
+        model_id,
+        key,
+        QuantVec::F32(emb.combined.clone()).to_bytes()
+    ])?;
+    conn.prepare_cached(
+        "DELETE FROM embedding_cache_windows WHERE model_id = ?1 AND text_hash = ?2",
+    )?
+    .execute(rusqlite::params![model_id, key])?;
+    if emb.windows.len() > 1 {
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO embedding_cache_windows
+             (model_id, text_hash, window_index, byte_start, byte_end, embedding)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        for (wi, w) in emb.windows.iter().enumerate() {
+            stmt.execute(rusqlite::params![
+                model_id,
+                key,
+                wi as i64,
+                w.byte_start as i64,
+                w.byte_end as i64,
+                QuantVec::F32(w.vector.clone()).to_bytes()
+            ])?;
+        }
+    }
+    Ok(())
+}
+
+/// 记录一次命中
+pub fn touch(conn: &Connection, model_id: &str, key: &str) -> SqlResult<()> {
+    conn.prepare_cached(
+        "UPDATE embedding_cache SET used_at = unixepoch() WHERE model_id = ?1 AND text_hash = ?2",
+    )?
+    .execute(rusqlite::params![model_id, key])?;
+    Ok(())
+}
+
+/// 淘汰超出上限的条目，返回淘汰数
+///
+/// 没有集合使用的模型的条目最先淘汰，其余按使用时间从旧到新；窗口向量随条目一起删除
+pub fn prune(conn: &Connection) -> SqlResult<usize> {
+    prune_above(conn, CACHE_MIN_ENTRIES)
+}
+
+fn prune_above(conn: &Connection, min_entries: usize) -> SqlResult<usize> {
+    let tx = conn.unchecked_transaction()?;
+    let (entries, chunks): (i64, i64) = tx.query_row(
+        "SELECT (SELECT COUNT(*) FROM embedding_cache), (SELECT COUNT(*) FROM chunks)",
+        [],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    )?;
+    let limit = min_entries.max(chunks as usize * 2);
+    let excess = (entries as usize).saturating_sub(limit);
+    if excess == 0 {
+        return Ok(0);
+    }
+    tx.execute_batch(
+        "CREATE TEMP TABLE IF NOT EXISTS evicted_cache (model_id TEXT, text_hash TEXT);
+         DELETE FROM temp.evicted_cache;",
+    )?;
+    tx.execute(
+        "INSERT INTO temp.evicted_cache
+         SELECT model_id, text_hash FROM embedding_cache
+         ORDER BY model_id IN (SELECT model_id FROM collections), used_at, rowid LIMIT ?1",
+        [excess as i64],
+    )?;
+    tx.execute_batch(
+        "DELETE FROM embedding_cache_windows
+         WHERE (model_id, text_hash) IN (SELECT model_id, text_hash FROM temp.evicted_cache);
+         DELETE FROM embedding_cache
+         WHERE (model_id, text_hash) IN (SELECT model_id, text_hash FROM temp.evicted_cache);
+         DELETE FROM temp.evicted_cache;",
+    )?;
+    tx.commit()?;
+    Ok(excess)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use crate::MODEL_NAME;
+
+    fn count(conn: &Connection, table: &str) -> usize {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |r| {
+            r.get::<_, i64>(0)
+        })
+        .unwrap() as usize
+    }
+
+    #[test]
+    fn prune_evicts_other_models_then_least_recently_used() {
+        let conn = test_support::db();
+        let emb = |x: f32| WindowedEmbedding {
+            combined: vec![x, 1.0],
+            windows: vec![
+                WindowVector {
+                    byte_start: 0,
+                    byte_end: 1,
+                    vector: vec![x, 0.0],
+                },
+                WindowVector {
+                    byte_start: 1,
+                    byte_end: 2,
+                    vector: vec![0.0, x],
+                },
+            ],
+        };
+        for i in 0..13 {
+            store(&conn, MODEL_NAME, &format!("k{i}"), &emb(i as f32)).unwrap();
+        }
+        conn.execute_batch(
+            "UPDATE embedding_cache SET used_at = 100;
+             UPDATE embedding_cache SET used_at = 50 WHERE text_hash IN ('k7', 'k8');
+             UPDATE embedding_cache SET model_id = 'old-model' WHERE text_hash = 'k9';
+             UPDATE embedding_cache_windows SET model_id = 'old-model' WHERE text_hash = 'k9';",
+        )
+        .unwrap();
+        touch(&conn, MODEL_NAME, "k8").unwrap();
+
+        // 上限 10：先淘汰已无集合使用的旧模型的 k9，再按使用时间淘汰 k7 与最早写入的 k0
+        assert_eq!(prune_above(&conn, 10).unwrap(), 3);
+        assert_eq!(count(&conn, "embedding_cache"), 10);
+        assert_eq!(count(&conn, "embedding_cache_windows"), 20);
+        for (key, kept) in [
+            ("k0", false),
+            ("k7", false),
+            ("k8", true),
+            ("k9", false),
+            ("k10", true),
+        ] {
+            assert_eq!(load(&conn, MODEL_NAME, key).is_some(), kept, "{key}");
+        }
+        assert_eq!(prune_above(&conn, 10).unwrap(), 0);
+    }
+}
@@ -0,0 +1,291 @@
+//! 检索评测：读取带标注的查询集（JSONL），计算 recall@k、MRR、nDCG@k 与延迟分位数
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// 查询集中的一行：`{"query": "...", "expected": ["a.txt", "notes/b.txt"]}`
+///
+/// expected 中的每一项与结果文件路径的结尾或文件名比较
+#[derive(Clone, Debug, Deserialize)]
+pub struct EvalQuery {
+    pub query: String,
+    #[serde(alias = "expected_files")]
+    pub expected: Vec<String>,
+}
+
+/// 单条查询的评测结果
+#[derive(Clone, Debug, Serialize)]
+pub struct QueryOutcome {
+    pub query: String,
+    /// 第一个命中文件在去重后结果列表中的名次（从 1 开始），未命中为 None
+    pub first_hit: Option<usize>,
+    pub recall: f64,
+    pub ndcg: f64,
+    pub latency_ms: f64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct EvalReport {
+    pub model: String,
+    pub queries: usize,
+    pub k: usize,
+    pub recall_at_k: f64,
+    pub mrr: f64,
+    pub ndcg_at_k: f64,
+    pub latency_p50_ms: f64,
+    pub latency_p95_ms: f64,
+    /// 从索引中抽样的 chunk 批量编码吞吐（模型未就绪时为 None）
+    pub encode_chunks_per_sec: Option<f64>,
+    pub encode_sample: usize,
+    pub per_query: Vec<QueryOutcome>,
+}
+
+/// 读取 JSONL 查询集，跳过空行；格式错误时报告行号
+pub fn load_queries(path: &Path) -> Result<Vec<EvalQuery>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("读取 {} 失败: {e}", path.display()))?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| format!("第 {} 行格式错误: {e}", i + 1))
+        })
+        .collect()
+}
+
+/// 结果路径是否对应某个期望文件（按路径后缀匹配，兼容相对路径与文件名）
+fn matches(result_path: &str, expected: &str) -> bool {
+    let result_path = result_path.replace('\\', "/");
+    let expected = expected.replace('\\', "/");
+    result_path == expected || result_path.ends_with(&format!("/{expected}"))
+}
+
+/// 对一条查询按文件粒度评分：`result_paths` 为检索结果的文件路径（按名次，可重复）
+pub fn score_query(
+    query: &EvalQuery,
+    result_paths: &[String],
+    k: usize,
+    latency_ms: f64,
+) -> QueryOutcome {
+    // 同一文件的多个 chunk 只按最靠前的一次计
+    let mut files: Vec<&str> = Vec::new();
+    for p in result_paths {
+        if !files.contains(&p.as_str()) {
+            files.push(p);
+        }
+    }
+
+    let mut found = vec![false; query.expected.len()];
+    let mut first_hit = None;
+    let mut hits_in_k = 0usize;
+    let mut dcg = 0.0;
+    for (rank, path) in files.iter().enumerate() {
+        let hit = query
+            .expected
+            .iter()
+            .enumerate()
+            .find(|(i, e)| !found[*i] && matches(path, e));
+        if let Some((i, _)) = hit {
+            found[i] = true;
+            first_hit.get_or_insert(rank + 1);
+            if rank < k {
+                hits_in_k += 1;
+                dcg += 1.0 / (rank as f64 + 2.0).log2();
+            }
+        }
+    }
+
+    let relevant = query.expected.len();
+    let ideal: f64 = (0..relevant.min(k))
+        .map(|rank| 1.0 / (rank as f64 + 2.0).log2())
+        .sum();
+
+    QueryOutcome {
+        query: query.query.clone(),
+        first_hit,
+        recall: if relevant == 0 {
+            0.0
+        } else {
+            hits_in_k as f64 / relevant as f64
+        },
+        ndcg: if ideal > 0.0 { dcg / ideal } else { 0.0 },
+        latency_ms,
+    }
+}
+
+/// 汇总各查询结果（MRR 只计前 k 名内的命中）
+pub fn summarize(
+    model: &str,
+    k: usize,
+    per_query: Vec<QueryOutcome>,
+    encode_chunks_per_sec: Option<f64>,
+    encode_sample: usize,
+) -> EvalReport {
+    let n = per_query.len().max(1) as f64;
+    let mean = |f: &dyn Fn(&QueryOutcome) -> f64| per_query.iter().map(f).sum::<f64>() / n;
+    let recall_at_k = mean(&|q| q.recall);
+    let mrr = mean(&|q| match q.first_hit {
+        Some(r) if r <= k => 1.0 / r as f64,
+        _ => 0.0,
+    });
+    let ndcg_at_k = mean(&|q| q.ndcg);
+
+    let mut latencies: Vec<f64> = per_query.iter().map(|q| q.latency_ms).collect();
+    latencies.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    EvalReport {
+        model: model.to_string(),
+        queries: per_query.len(),
+        k,
+        recall_at_k,
+        mrr,
+        ndcg_at_k,
+        latency_p50_ms: percentile(&latencies, 0.50),
+        latency_p95_ms: percentile(&latencies, 0.95),
+        encode_chunks_per_sec,
+        encode_sample,
+        per_query,
+    }
+}
+
+/// 最近秩法分位数（输入须已升序）
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(expected: &[&str]) -> EvalQuery {
+        EvalQuery {
+            query: "q".into(),
+            expected: expected.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn paths(results: &[&str]) -> Vec<String> {
+        results.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn score_query_matches_hand_computed_metrics() {
+        // 第 2、3 名的折损：1/log2(3)、1/log2(4) = 0.5
+        let d2 = 1.0 / 3f64.log2();
+        // (期望文件, 结果路径, k, first_hit, recall, ndcg)
+        type Case<'a> = (&'a [&'a str], &'a [&'a str], usize, Option<usize>, f64, f64);
+        let cases: &[Case] = &[
+            // 同一文件的重复 chunk 只计一次：命中名次为 1 和 3
+            (
+                &["a.txt", "b.txt"],
+                &["docs/a.txt", "c.txt", "docs/a.txt", "x/b.txt"],
+                3,
+                Some(1),
+                1.0,
+                (1.0 + 0.5) / (1.0 + d2),
+            ),
+            // 只命中一半，且在第 2 名
+            (
+                &["b.txt", "c.txt"],
+                &["a.txt", "b.txt"],
+                5,
+                Some(2),
+                0.5,
+                d2 / (1.0 + d2),
+            ),
+            // 反斜杠路径可匹配，文件名只按完整路径段匹配（ba.txt 不算 a.txt）
+            (&["a.txt"], &["ba.txt", "dir\\a.txt"], 2, Some(2), 1.0, d2),
+            // 命中在前 k 名之外：名次照记，recall 与 nDCG 为 0
+            (
+                &["b.txt"],
+                &["a.txt", "c.txt", "b.txt"],
+                2,
+                Some(3),
+                0.0,
+                0.0,
+            ),
+            // 未命中
+            (&["z.txt"], &["a.txt", "b.txt"], 10, None, 0.0, 0.0),
+            // 无结果
+            (&["a.txt"], &[], 10, None, 0.0, 0.0),
+            // 期望为空：不产生命中，指标为 0 而不是 NaN
+            (&[], &["a.txt"], 10, None, 0.0, 0.0),
+        ];
+        for (i, &(expected, results, k, first_hit, recall, ndcg)) in cases.iter().enumerate() {
+            let out = score_query(&query(expected), &paths(results), k, 1.5);
+            assert_eq!(out.first_hit, first_hit, "case {i}");
+            assert!(close(out.recall, recall), "case {i}: recall {}", out.recall);
+            assert!(close(out.ndcg, ndcg), "case {i}: ndcg {}", out.ndcg);
+            assert_eq!(out.latency_ms, 1.5);
+        }
+    }
+
+    #[test]
+    fn percentile_uses_nearest_rank() {
+        let ten: Vec<f64> = (1..=10).map(f64::from).collect();
+        let cases: &[(&[f64], f64, f64)] = &[
+            (&ten, 0.50, 5.0),
+            (&ten, 0.95, 10.0),
+            (&ten, 0.0, 1.0),
+            (&ten, 1.0, 10.0),
+            (&[10.0, 20.0, 30.0], 0.50, 20.0),
+            (&[7.0], 0.95, 7.0),
+            (&[], 0.50, 0.0),
+        ];
+        for (i, &(sorted, p, want)) in cases.iter().enumerate() {
+            assert_eq!(percentile(sorted, p), want, "case {i}");
+        }
+    }
+
+    #[test]
+    fn summarize_averages_and_counts_mrr_within_k() {
+        let outcome = |first_hit, recall, ndcg, latency_ms| QueryOutcome {
+            query: "q".into(),
+            first_hit,
+            recall,
+            ndcg,
+            latency_ms,
+        };
+        // k = 2：第 3 名的命中不计入 MRR
+        let report = summarize(
+            "m",
+            2,
+            vec![
+                outcome(Some(1), 1.0, 1.0, 30.0),
+                outcome(Some(2), 0.5, 0.25, 10.0),
+                outcome(Some(3), 0.0, 0.0, 20.0),
+                outcome(None, 0.0, 0.0, 40.0),
+            ],
+            Some(12.0),
+            8,
+        );
+        assert_eq!(report.queries, 4);
+        assert!(close(report.recall_at_k, 1.5 / 4.0));
+        assert!(close(report.mrr, (1.0 + 0.5) / 4.0));
+        assert!(close(report.ndcg_at_k, 1.25 / 4.0));
+        assert_eq!(report.latency_p50_ms, 20.0);
+        assert_eq!(report.latency_p95_ms, 40.0);
+        assert_eq!(report.encode_chunks_per_sec, Some(12.0));
+        assert_eq!(report.encode_sample, 8);
+
+        let empty = summarize("m", 10, Vec::new(), None, 0);
+        assert_eq!(empty.queries, 0);
+        assert_eq!(
+            (
+                empty.recall_at_k,
+                empty.mrr,
+                empty.ndcg_at_k,
+                empty.latency_p95_ms
+            ),
+            (0.0, 0.0, 0.0, 0.0)
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! 混合检索的结果融合：把语义、稀疏、关键词各路排序合并为一个列表
+//!
+//! - RRF（倒数排名融合）只看名次，不受各路分数量纲影响，是默认方式
+//! - Weighted 先把每路分数线性归一化到 0–1 再加权求和，保留分数差距信息
+
+use crate::SearchMode;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// RRF 平滑常数（原论文取 60），越大名次差异的影响越小
+pub const RRF_K: f32 = 60.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FusionMethod {
+    /// Σ weight / (RRF_K + rank)
+    #[default]
+    Rrf,
+    /// Σ weight × 归一化分数
+    Weighted,
+}
+
+/// 各路信号的权重，0 表示不参与混合检索
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SignalWeights {
+    pub semantic: f32,
+    pub sparse: f32,
+    pub keyword: f32,
+}
+
+impl Default for SignalWeights {
+    fn default() -> Self {
+        Self {
+            semantic: 1.0,
+            sparse: 1.0,
+            keyword: 1.0,
+        }
+    }
+}
+
+impl SignalWeights {
+    pub fn get(&self, signal: SearchMode) -> f32 {
+        match signal {
+            SearchMode::Semantic => self.semantic,
+            SearchMode::Sparse => self.sparse,
+            SearchMode::Keyword => self.keyword,
+            SearchMode::Auto | SearchMode::Hybrid => 0.0,
+        }
+    }
+}
+
+/// 某一路信号对融合分数的贡献
+#[derive(Clone, Debug, Serialize)]
+pub struct Contribution {
+    pub signal: SearchMode,
+    /// 该路结果中的名次（从 1 开始）
+    pub rank: usize,
+    /// 该路的原始分数
+    pub score: f32,
+    /// 计入融合分数的部分
+    pub contribution: f32,
+}
+
+/// 一路检索结果：按名次排列的 (key, 原始分数)
+pub struct RankedList<K> {
+    pub signal: SearchMode,
+    pub hits: Vec<(K, f32)>,
+}
+
+/// 融合各路排序，返回按融合分数降序的 (key, 融合分数, 各路贡献)
+pub fn fuse<K: Clone + Eq + Hash>(
+    lists: &[RankedList<K>],
+    method: FusionMethod,
+    weights: &SignalWeights,
+) -> Vec<(K, f32, Vec<Contribution>)> {
+    let mut fused: Vec<(K, f32, Vec<Contribution>)> = Vec::new();
+    let mut index: HashMap<K, usize> = HashMap::new();
+
+    for list in lists {
+        let weight = weights.get(list.signal);
+        if weight <= 0.0 {
+            continue;
+        }
+        let (min, max) = list
+            .hits
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), (_, s)| {
+                (lo.min(*s), hi.max(*s))
+            });
+
+        for (i, (key, score)) in list.hits.iter().enumerate() {
+            let rank = i + 1;
+            let contribution = match method {
+                FusionMethod::Rrf => weight / (RRF_K + rank as f32),
+                // 同一路分数全部相同时（如 LIKE 回退）视为同等相关
+                FusionMethod::Weighted if max > min => weight * (score - min) / (max - min),
+                FusionMethod::Weighted => weight,
+            };
+            let slot = *index.entry(key.clone()).or_insert_with(|| {
+                fused.push((key.clone(), 0.0, Vec::new()));
+                fused.len() - 1
+            });
+            let entry = &mut fused[slot];
+            // 同一路中重复出现的 key 只计最靠前的一次
+            if entry.2.iter().any(|c| c.signal == list.signal) {
+                continue;
+            }
+            entry.1 += contribution;
+            entry.2.push(Contribution {
+                signal: list.signal,
+                rank,
+                score: *score,
+                contribution,
+            });
+        }
+    }
+
+    fused.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    fused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(signal: SearchMode, hits: &[(&'static str, f32)]) -> RankedList<&'static str> {
+        RankedList {
+            signal,
+            hits: hits.to_vec(),
+        }
+    }
+
+    #[test]
+    fn rrf_rewards_agreement_between_signals() {
+        let lists = [
+            list(SearchMode::Semantic, &[("a", 0.9), ("b", 0.8), ("c", 0.7)]),
+            list(SearchMode::Keyword, &[("c", 12.0), ("d", 3.0)]),
+        ];
+        let fused = fuse(&lists, FusionMethod::Rrf, &SignalWeights::default());
+        let keys: Vec<&str> = fused.iter().map(|f| f.0).collect();
+        assert_eq!(keys, vec!["c", "a", "b", "d"]);
+
+        let c = &fused[0].2;
+        assert_eq!(c.len(), 2);
+        assert_eq!((c[0].signal, c[0].rank), (SearchMode::Semantic, 3));
+        assert_eq!((c[1].signal, c[1].rank), (SearchMode::Keyword, 1));
+        let total: f32 = c.iter().map(|c| c.contribution).sum();
+        assert!((total - fused[0].1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn weighted_normalizes_each_signal() {
+        let weights = SignalWeights {
+            semantic: 1.0,
+            sparse: 0.0,
+            keyword: 0.5,
+        };
+        let lists = [
+            list(SearchMode::Semantic, &[("a", 0.9), ("b", 0.5)]),
+            list(SearchMode::Sparse, &[("z", 40.0)]),
+            list(SearchMode::Keyword, &[("b", 30.0), ("a", 10.0)]),
+        ];
+        let fused = fuse(&lists, FusionMethod::Weighted, &weights);
+        // 权重为 0 的信号不参与
+        assert!(fused.iter().all(|f| f.0 != "z"));
+        assert_eq!(fused[0].0, "a");
+        assert!((fused[0].1 - 1.0).abs() < 1e-6);
+        assert_eq!(fused[1].0, "b");
+        assert!((fused[1].1 - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn duplicate_keys_count_once_per_signal() {
+        let lists = [list(SearchMode::Keyword, &[("a", 2.0), ("a", 1.0)])];
+        let fused = fuse(&lists, FusionMethod::Rrf, &SignalWeights::default());
+        assert_eq!(fused.len(), 1);
+        assert_eq!(fused[0].2.len(), 1);
+        assert!((fused[0].1 - 1.0 / (RRF_K + 1.0)).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,791 @@
+//! HNSW 近似最近邻索引：分层小世界图，检索复杂度约为 O(log n)
+//!
+//! 节点只保存 chunk_id 与邻接表，相似度直接读取向量文件（VectorStore）中的行计算，
+//! 不再为索引复制一份向量；不在文件中的向量（文件写入后新增的向量）单独存放。
+//! 删除只打墓碑标记，节点仍参与图上导航，墓碑过多时由调用方整体重建。
+//! 索引序列化为 `locallens.hnsw`（只含图结构），与数据库放在一起。
+
+use crate::quant::{cosine_sim_raw, QuantVec, QueryVec, VectorFormat};
+use crate::vector_store::VectorStore;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Weak};
+
+/// 每层的邻居上限（第 0 层为 2M）
+const M: usize = 16;
+/// 构建时每层搜索的候选数
+const EF_CONSTRUCTION: usize = 100;
+/// 层数上限（n = 10^7 时期望层数约 6）
+const MAX_LEVEL: usize = 16;
+
+const MAGIC: &[u8; 8] = b"LLHNSW\x00\x02";
+
+/// 节点没有可用的向量：墓碑的向量已随向量文件合并移除，只作为图上的中转节点
+const NO_ROW: u32 = u32::MAX;
+
+struct Node {
+    chunk_id: i64,
+    /// links[l] 为第 l 层的邻居
+    links: Vec<Vec<u32>>,
+    deleted: bool,
+}
+
+pub struct Hnsw {
+    dim: usize,
+    format: VectorFormat,
+    nodes: Vec<Node>,
+    /// 节点向量所在的行：小于 `store_len` 时为向量文件中的行，否则为 `extra` 中的行
+    rows: Vec<u32>,
+    /// 绑定的向量文件。弱引用不妨碍缓存释放映射（替换文件前须解除映射），
+    /// 同时保留分配，可按指针判断是否仍是同一个文件
+    store: Weak<VectorStore>,
+    store_len: usize,
+    /// 不在向量文件中的节点向量（去掉格式标记），按行连续存放
+    extra: Vec<u8>,
+    /// 未删除节点：chunk_id → 节点下标
+    live: HashMap<i64, u32>,
+    entry: Option<u32>,
+    max_chunk_id: i64,
+    rng: u64,
+}
+
+/// 按相似度排序的候选（相似度越大越近）
+#[derive(Clone, Copy, PartialEq)]
+struct Scored(f32, u32);
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+impl Hnsw {
+    /// 绑定到 `store` 的空索引，维度与格式取自向量文件
+    pub fn new(store: &Arc<VectorStore>) -> Self {
+        Self {
+            dim: store.dim(),
+            format: store.format(),
+            nodes: Vec::new(),
+            rows: Vec::new(),
+            store: Arc::downgrade(store),
+            store_len: store.len(),
+            extra: Vec::new(),
+            live: HashMap::new(),
+            entry: None,
+            max_chunk_id: 0,
+            rng: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn format(&self) -> VectorFormat {
+        self.format
+    }
+
+    /// 未删除的节点数
+    pub fn len(&self) -> usize {
+        self.live.len()
+    }
+
+    /// 已删除（墓碑）节点数
+    pub fn deleted(&self) -> usize {
+        self.nodes.len() - self.live.len()
+    }
+
+    /// 曾插入过的最大 chunk_id（chunks 使用 AUTOINCREMENT，更大的 id 即新增向量）
+    pub fn max_chunk_id(&self) -> i64 {
+        self.max_chunk_id
+    }
+
+    pub fn chunk_ids(&self) -> impl Iterator<Item = i64> + '_ {
+        self.live.keys().copied()
+    }
+
+    /// 索引是否绑定在 `store` 上；向量文件被替换后需先 rebind 才能检索与插入
+    pub fn is_bound(&self, store: &Arc<VectorStore>) -> bool {
+        std::ptr::eq(self.store.as_ptr(), Arc::as_ptr(store))
+    }
+
+    /// 改为绑定到新的向量文件（向量缓存重建或合并之后）
+    ///
+    /// 节点向量优先取新文件中的行，其次沿用原来的行（旧文件仍被映射时），
+    /// 再由 `fetch` 从数据库读取；未删除节点的向量都找不到时返回 Err，需重建索引
+    pub fn rebind(
+        &mut self,
+        store: &Arc<VectorStore>,
+        mut fetch: impl FnMut(i64) -> Option<QuantVec>,
+    ) -> Result<(), String> {
+        if store.dim() != self.dim || store.format() != self.format {
+            return Err(format!(
+                "向量文件为 {} 维 {}，索引为 {} 维 {}",
+                store.dim(),
+                store.format().as_str(),
+                self.dim,
+                self.format.as_str()
+            ));
+        }
+        let old = self.store.upgrade();
+        let stride = self.format.row_bytes(self.dim);
+        let mut rows = Vec::with_capacity(self.nodes.len());
+        let mut extra = Vec::new();
+        for (idx, node) in self.nodes.iter().enumerate() {
+            if let Some(row) = store.find(node.chunk_id) {
+                rows.push(row as u32);
+                continue;
+            }
+            let fetched;
+            let row = match self.stored_row(old.as_deref(), idx as u32) {
+                Some(row) => Some(row),
+                None if node.deleted => None,
+                None => {
+                    fetched = fetch(node.chunk_id)
+                        .filter(|v| v.dim() == self.dim && v.format() == self.format)
+                        .ok_or_else(|| format!("找不到 chunk {} 的向量", node.chunk_id))?
+                        .to_bytes();
+                    Some(&fetched[1..])
+                }
+            };
+            rows.push(match row {
+                Some(row) => {
+                    extra.extend_from_slice(row);
+                    (store.len() + extra.len() / stride - 1) as u32
+                }
+                None => NO_ROW,
+            });
+        }
+        self.rows = rows;
+        self.extra = extra;
+        self.store = Arc::downgrade(store);
+        self.store_len = store.len();
+        Ok(())
+    }
+
+    /// 插入向量；同一 chunk_id 已存在时先删除旧节点
+    ///
+    /// 向量文件中已有这一行时直接引用，否则另行保存
+    pub fn insert(
+        &mut self,
+        store: &Arc<VectorStore>,
+        chunk_id: i64,
+        vector: &QuantVec,
+    ) -> Result<(), String> {
+        if !self.is_bound(store) {
+            return Err("ANN 索引未绑定当前的向量文件".into());
+        }
+        if vector.dim() != self.dim || vector.format() != self.format {
+            return Err(format!(
+                "向量为 {} 维 {}，索引为 {} 维 {}",
+                vector.dim(),
+                vector.format().as_str(),
+                self.dim,
+                self.format.as_str()
+            ));
+        }
+        self.remove(chunk_id);
+
+        let bytes = vector.to_bytes();
+        let row = match store
+            .find(chunk_id)
+            .filter(|&r| store.row(r) == &bytes[1..])
+        {
+            Some(r) => r as u32,
+            None => {
+                let r = self.store_len + self.extra.len() / self.format.row_bytes(self.dim);
+                self.extra.extend_from_slice(&bytes[1..]);
+                r as u32
+            }
+        };
+
+        let level = self.random_level();
+        let idx = self.nodes.len() as u32;
+        let query = QueryVec::new(vector.to_f32());
+        self.nodes.push(Node {
+            chunk_id,
+            links: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+        self.rows.push(row);
+        self.live.insert(chunk_id, idx);
+        self.max_chunk_id = self.max_chunk_id.max(chunk_id);
+
+        let Some(entry) = self.entry else {
+            self.entry = Some(idx);
+            return Ok(());
+        };
+        let top = self.nodes[entry as usize].links.len() - 1;
+
+        // 高于新节点层数的部分贪心下降，只保留最近的一个入口
+        let mut eps = vec![Scored(self.sim(store, &query, entry), entry)];
+        for l in (level + 1..=top).rev() {
+            eps = self.search_layer(store, &query, &eps, 1, l);
+        }
+        for l in (0..=level.min(top)).rev() {
+            let candidates = self.search_layer(store, &query, &eps, EF_CONSTRUCTION, l);
+            let neighbours: Vec<u32> = candidates
+                .iter()
+                .filter(|s| s.0 > f32::NEG_INFINITY)
+                .take(M)
+                .map(|s| s.1)
+                .collect();
+            for &n in &neighbours {
+                self.link(store, n, idx, l);
+            }
+            self.nodes[idx as usize].links[l] = neighbours;
+            eps = candidates;
+        }
+
+        if level > top {
+            self.entry = Some(idx);
+        }
+        Ok(())
+    }
+
+    /// 删除 chunk_id 对应的节点（打墓碑），返回是否存在
+    pub fn remove(&mut self, chunk_id: i64) -> bool {
+        match self.live.remove(&chunk_id) {
+            Some(idx) => {
+                self.nodes[idx as usize].deleted = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 近似 Top-k：返回 (chunk_id, 相似度)，按相似度降序；`ef` 越大召回越高、越慢
+    pub fn search(
+        &self,
+        store: &Arc<VectorStore>,
+        query: &QueryVec,
+        k: usize,
+        ef: usize,
+    ) -> Vec<(i64, f32)> {
+        self.search_filtered(store, query, k, ef, |_| true)
+    }
+
+    /// 同 search，只返回 `accept` 接受的 chunk；图遍历不受过滤影响，
+    /// 接受的比例较低时需要相应加大 `ef`。未绑定 `store` 时返回空
+    pub fn search_filtered(
+        &self,
+        store: &Arc<VectorStore>,
+        query: &QueryVec,
+        k: usize,
+        ef: usize,
+        accept: impl Fn(i64) -> bool,
+    ) -> Vec<(i64, f32)> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        if query.dim() != self.dim || k == 0 || !self.is_bound(store) {
+            return Vec::new();
+        }
+        let top = self.nodes[entry as usize].links.len() - 1;
+        let mut eps = vec![Scored(self.sim(store, query, entry), entry)];
+        for l in (1..=top).rev() {
+            eps = self.search_layer(store, query, &eps, 1, l);
+        }
+        self.search_layer(store, query, &eps, ef.max(k), 0)
+            .into_iter()
+            .filter(|s| !self.nodes[s.1 as usize].deleted)
+            .map(|s| (self.nodes[s.1 as usize].chunk_id, s.0))
+            .filter(|&(id, _)| accept(id))
+            .take(k)
+            .collect()
+    }
+
+    /// 节点 `idx` 的向量数据；`store` 为绑定的向量文件（为 None 时只能取 extra 中的行）
+    fn stored_row<'a>(&'a self, store: Option<&'a VectorStore>, idx: u32) -> Option<&'a [u8]> {
+        let row = *self.rows.get(idx as usize)? as usize;
+        if row == NO_ROW as usize {
+            None
+        } else if row < self.store_len {
+            store.map(|s| s.row(row))
+        } else {
+            let stride = self.format.row_bytes(self.dim);
+            let at = (row - self.store_len) * stride;
+            self.extra.get(at..at + stride)
+        }
+    }
+
+    /// 查询与节点的相似度；没有向量的节点排在最后
+    fn sim(&self, store: &VectorStore, query: &QueryVec, idx: u32) -> f32 {
+        self.stored_row(Some(store), idx)
+            .map_or(f32::NEG_INFINITY, |row| {
+                cosine_sim_raw(query, self.format, row)
+            })
+    }
+
+    /// 在第 `level` 层从 `eps` 出发做 beam search，返回最近的至多 `ef` 个节点（降序）
+    fn search_layer(
+        &self,
+        store: &VectorStore,
+        query: &QueryVec,
+        eps: &[Scored],
+        ef: usize,
+        level: usize,
+    ) -> Vec<Scored> {
+        let mut visited: HashSet<u32> = eps.iter().map(|s| s.1).collect();
+        // candidates：最大堆（先扩展最近的）；found：以 Reverse 实现的最小堆（堆顶为最远）
+        let mut candidates: BinaryHeap<Scored> = eps.iter().copied().collect();
+        let mut found: BinaryHeap<std::cmp::Reverse<Scored>> =
+            eps.iter().copied().map(std::cmp::Reverse).collect();
+        while found.len() > ef {
+            found.pop();
+        }
+
+        while let Some(current) = candidates.pop() {
+            let worst = found.peek().map_or(f32::NEG_INFINITY, |r| r.0 .0);
+            if current.0 < worst && found.len() >= ef {
+                break;
+            }
+            let links = match self.nodes[current.1 as usize].links.get(level) {
+                Some(links) => links,
+                None => continue,
+            };
+            for &n in links {
+                if !visited.insert(n) {
+                    continue;
+                }
+                let s = Scored(self.sim(store, query, n), n);
+                let worst = found.peek().map_or(f32::NEG_INFINITY, |r| r.0 .0);
+                if found.len() < ef || s.0 > worst {
+                    candidates.push(s);
+                    found.push(std::cmp::Reverse(s));
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+
+        let mut out: Vec<Scored> = found.into_iter().map(|r| r.0).collect();
+        out.sort_by(|a, b| b.cmp(a));
+        out
+    }
+
+    /// 在 `from` 的第 `level` 层邻居中加入 `to`，超出上限时只保留最近的
+    fn link(&mut self, store: &VectorStore, from: u32, to: u32, level: usize) {
+        let max = max_links(level);
+        let links = &mut self.nodes[from as usize].links[level];
+        links.push(to);
+        if links.len() <= max {
+            return;
+        }
+        let Some(base) = self
+            .stored_row(Some(store), from)
+            .map(|row| row_query(self.format, self.dim, row))
+        else {
+            self.nodes[from as usize].links[level].truncate(max);
+            return;
+        };
+        let mut scored: Vec<Scored> = self.nodes[from as usize].links[level]
+            .iter()
+            .map(|&n| Scored(self.sim(store, &base, n), n))
+            .collect();
+        scored.sort_by(|a, b| b.cmp(a));
+        self.nodes[from as usize].links[level] =
+            scored.into_iter().take(max).map(|s| s.1).collect();
+    }
+
+    /// 层数服从以 1/ln(M) 为尺度的指数分布
+    fn random_level(&mut self) -> usize {
+        // xorshift64*
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let bits = self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11;
+        let u = (bits as f64 + 1.0) / (1u64 << 53) as f64;
+        ((-u.ln() / (M as f64).ln()) as usize).min(MAX_LEVEL)
+    }
+
+    // ── 持久化 ────────────────────────────────────────────────────────────────
+
+    /// 只写图结构（向量在向量文件中）；写入临时文件后改名，写到一半中断不会留下损坏的索引
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let tmp = path.with_extension("hnsw.tmp");
+        let write = || -> std::io::Result<()> {
+            let mut w = BufWriter::new(std::fs::File::create(&tmp)?);
+            w.write_all(MAGIC)?;
+            put_u32(&mut w, self.dim as u32)?;
+            let format = self.format.as_str().as_bytes();
+            w.write_all(&[format.len() as u8])?;
+            w.write_all(format)?;
+            put_u32(&mut w, self.entry.unwrap_or(u32::MAX))?;
+            w.write_all(&self.max_chunk_id.to_le_bytes())?;
+            w.write_all(&self.rng.to_le_bytes())?;
+            put_u32(&mut w, self.nodes.len() as u32)?;
+            for node in &self.nodes {
+                w.write_all(&node.chunk_id.to_le_bytes())?;
+                w.write_all(&[node.deleted as u8, node.links.len() as u8])?;
+                for links in &node.links {
+                    put_u32(&mut w, links.len() as u32)?;
+                    for &n in links {
+                        put_u32(&mut w, n)?;
+                    }
+                }
+            }
+            w.into_inner().map_err(|e| e.into_error())?.sync_all()
+        };
+        write()
+            .and_then(|_| std::fs::rename(&tmp, path))
+            .map_err(|e| format!("写入 ANN 索引 {} 失败: {e}", path.display()))
+    }
+
+    /// 读取图结构并绑定到 `store`（见 rebind）；文件损坏或与向量文件不符时返回 Err，需重建
+    pub fn load(
+        path: &Path,
+        store: &Arc<VectorStore>,
+        fetch: impl FnMut(i64) -> Option<QuantVec>,
+    ) -> Result<Self, String> {
+        let read = || -> std::io::Result<Result<Self, String>> {
+            let mut r = BufReader::new(std::fs::File::open(path)?);
+            let mut magic = [0u8; 8];
+            r.read_exact(&mut magic)?;
+            if &magic != MAGIC {
+                return Ok(Err("文件标记不符".into()));
+            }
+            let dim = get_u32(&mut r)? as usize;
+            let format_len = get_u8(&mut r)? as usize;
+            let mut format = vec![0u8; format_len];
+            r.read_exact(&mut format)?;
+            let Some(format) = std::str::from_utf8(&format)
+                .ok()
+                .and_then(VectorFormat::parse)
+            else {
+                return Ok(Err("未知的向量格式".into()));
+            };
+            let entry = Some(get_u32(&mut r)?).filter(|&e| e != u32::MAX);
+            let max_chunk_id = get_i64(&mut r)?;
+            let rng = get_i64(&mut r)? as u64;
+            let count = get_u32(&mut r)? as usize;
+
+            // 长度字段来自文件，不可信：预分配设上限，逐项校验后再读取
+            let mut nodes = Vec::with_capacity(count.min(1 << 20));
+            let mut live = HashMap::with_capacity(count.min(1 << 20));
+            for idx in 0..count {
+                let chunk_id = get_i64(&mut r)?;
+                let deleted = get_u8(&mut r)? != 0;
+                let levels = get_u8(&mut r)? as usize;
+                if levels == 0 || levels > MAX_LEVEL + 1 {
+                    return Ok(Err(format!("节点 {idx} 的层数 {levels} 无效")));
+                }
+                let mut links = Vec::with_capacity(levels);
+                for level in 0..levels {
+                    let n = get_u32(&mut r)? as usize;
+                    if n > max_links(level) {
+                        return Ok(Err(format!("节点 {idx} 第 {level} 层有 {n} 个邻居")));
+                    }
+                    let mut level = Vec::with_capacity(n);
+                    for _ in 0..n {
+                        level.push(get_u32(&mut r)?);
+                    }
+                    links.push(level);
+                }
+                if !deleted {
+                    live.insert(chunk_id, idx as u32);
+                }
+                nodes.push(Node {
+                    chunk_id,
+                    links,
+                    deleted,
+                });
+            }
+
+            let dangling = nodes
+                .iter()
+                .flat_map(|n| n.links.iter().flatten())
+                .any(|&n| n as usize >= count);
+            if dangling || entry.is_some_and(|e| e as usize >= count) {
+                return Ok(Err("邻接表引用了不存在的节点".into()));
+            }
+            Ok(Ok(Self {
+                dim,
+                format,
+                rows: vec![NO_ROW; nodes.len()],
+                nodes,
+                store: Weak::new(),
+                store_len: 0,
+                extra: Vec::new(),
+                live,
+                entry,
+                max_chunk_id,
+                rng,
+            }))
+        };
+        read()
+            .map_err(|e| e.to_string())
+            .and_then(|r| r)
+            .and_then(|mut index| index.rebind(store, fetch).map(|_| index))
+            .map_err(|e| format!("读取 ANN 索引 {} 失败: {e}", path.display()))
+    }
+}
+
+/// 第 `level` 层的邻居上限
+fn max_links(level: usize) -> usize {
+    if level == 0 {
+        2 * M
+    } else {
+        M
+    }
+}
+
+/// 把向量文件中的一行还原为查询向量
+fn row_query(format: VectorFormat, dim: usize, row: &[u8]) -> QueryVec {
+    let mut blob = Vec::with_capacity(row.len() + 1);
+    blob.push(format.tag());
+    blob.extend_from_slice(row);
+    QueryVec::new(
+        QuantVec::from_bytes(&blob, Some(dim))
+            .map(|v| v.to_f32())
+            .unwrap_or_default(),
+    )
+}
+
+fn put_u32(w: &mut impl Write, v: u32) -> std::io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn get_u8(r: &mut impl Read) -> std::io::Result<u8> {
+    let mut b = [0u8; 1];
+    r.read_exact(&mut b)?;
+    Ok(b[0])
+}
+
+fn get_u32(r: &mut impl Read) -> std::io::Result<u32> {
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+fn get_i64(r: &mut impl Read) -> std::io::Result<i64> {
+    let mut b = [0u8; 8];
+    r.read_exact(&mut b)?;
+    Ok(i64::from_le_bytes(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_path;
+
+    /// 确定性的伪随机单位向量
+    fn random_unit(seed: u64, dim: usize) -> Vec<f32> {
+        let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+        let v: Vec<f32> = (0..dim)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+            })
+            .collect();
+        let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        v.into_iter().map(|x| x / norm).collect()
+    }
+
+    /// 把 (chunk_id, 向量) 写成向量文件并映射（文件映射后即删除）
+    fn store_of(name: &str, vectors: &[(i64, Vec<f32>)], dim: usize) -> Arc<VectorStore> {
+        let path = temp_path(name, "vectors");
+        let rows = vectors
+            .iter()
+            .map(|(id, v)| (*id, QuantVec::quantize(v, VectorFormat::F32).to_bytes()));
+        let last_id = vectors.last().map_or(0, |(id, _)| *id);
+        VectorStore::write_rows(&path, dim, VectorFormat::F32, 1, last_id, rows).unwrap();
+        let store = VectorStore::open(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        Arc::new(store)
+    }
+
+    fn build(name: &str, n: usize, dim: usize) -> (Hnsw, Arc<VectorStore>, Vec<Vec<f32>>) {
+        let vectors: Vec<Vec<f32>> = (0..n).map(|i| random_unit(i as u64, dim)).collect();
+        let ids: Vec<(i64, Vec<f32>)> = vectors
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i as i64 + 1, v.clone()))
+            .collect();
+        let store = store_of(name, &ids, dim);
+        let mut index = Hnsw::new(&store);
+        for (id, v) in &ids {
+            index
+                .insert(&store, *id, &QuantVec::quantize(v, VectorFormat::F32))
+                .unwrap();
+        }
+        assert!(index.extra.is_empty());
+        (index, store, vectors)
+    }
+
+    fn exact_top(vectors: &[Vec<f32>], query: &[f32], k: usize) -> Vec<i64> {
+        let mut scored: Vec<(i64, f32)> = vectors
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i as i64 + 1, v.iter().zip(query).map(|(a, b)| a * b).sum()))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.into_iter().take(k).map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn recall_close_to_brute_force() {
+        let (index, store, vectors) = build("recall", 2000, 32);
+        let mut hits = 0;
+        for q in 0..50 {
+            let query = random_unit(10_000 + q, 32);
+            let exact = exact_top(&vectors, &query, 10);
+            let found: Vec<i64> = index
+                .search(&store, &QueryVec::new(query), 10, 64)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect();
+            hits += exact.iter().filter(|id| found.contains(id)).count();
+        }
+        let recall = hits as f64 / 500.0;
+        assert!(recall > 0.9, "recall@10 = {recall}");
+    }
+
+    #[test]
+    fn removed_chunks_are_not_returned() {
+        let (mut index, store, vectors) = build("removed", 300, 16);
+        let query = vectors[41].clone();
+        assert_eq!(
+            index.search(&store, &QueryVec::new(query.clone()), 1, 32)[0].0,
+            42
+        );
+
+        assert!(index.remove(42));
+        assert!(!index.remove(42));
+        assert_eq!(index.len(), 299);
+        assert_eq!(index.deleted(), 1);
+        let results = index.search(&store, &QueryVec::new(query.clone()), 10, 32);
+        assert!(results.iter().all(|(id, _)| *id != 42));
+        assert_eq!(results.len(), 10);
+
+        let even = index.search_filtered(&store, &QueryVec::new(query), 5, 64, |id| id % 2 == 0);
+        assert_eq!(even.len(), 5);
+        assert!(even.iter().all(|(id, _)| id % 2 == 0 && *id != 42));
+    }
+
+    #[test]
+    fn rebinds_to_compacted_store() {
+        // 文件只有前 150 个向量，其余插入时另行保存；合并后的文件去掉了删除的 chunk
+        let dim = 16;
+        let vectors: Vec<(i64, Vec<f32>)> = (1..=200)
+            .map(|id| (id, random_unit(id as u64, dim)))
+            .collect();
+        let first = store_of("rebind-first", &vectors[..150], dim);
+        let mut index = Hnsw::new(&first);
+        for (id, v) in &vectors {
+            index
+                .insert(&first, *id, &QuantVec::quantize(v, VectorFormat::F32))
+                .unwrap();
+        }
+        assert_eq!(index.extra.len(), 50 * VectorFormat::F32.row_bytes(dim));
+        for id in [3, 77, 160] {
+            index.remove(id);
+        }
+        let query = QueryVec::new(random_unit(9_999, dim));
+        let before = index.search(&first, &query, 10, 64);
+
+        let live: Vec<(i64, Vec<f32>)> = vectors
+            .iter()
+            .filter(|(id, _)| ![3, 77, 160].contains(id))
+            .cloned()
+            .collect();
+        let compacted = store_of("rebind-compacted", &live, dim);
+        assert!(index.search(&compacted, &query, 10, 64).is_empty());
+        let path = temp_path("rebind", "hnsw");
+        index.save(&path).unwrap();
+        index.rebind(&compacted, |_| None).unwrap();
+        assert!(index.is_bound(&compacted));
+        // 墓碑的向量仍取自旧文件
+        assert_eq!(index.extra.len(), 3 * VectorFormat::F32.row_bytes(dim));
+        assert_eq!(index.search(&compacted, &query, 10, 64), before);
+
+        // 从磁盘加载时没有旧文件，墓碑没有向量，只作为中转节点
+        let loaded = Hnsw::load(&path, &compacted, |_| None).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert!(loaded.extra.is_empty());
+        assert_eq!(loaded.search(&compacted, &query, 10, 64), before);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let (mut index, store, vectors) = build("round-trip", 200, 8);
+        index.remove(7);
+        let path = temp_path("round-trip", "hnsw");
+        index.save(&path).unwrap();
+        let loaded = Hnsw::load(&path, &store, |_| None).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.len(), index.len());
+        assert_eq!(loaded.max_chunk_id(), 200);
+        let query = QueryVec::new(vectors[99].clone());
+        assert_eq!(
+            loaded.search(&store, &query, 5, 32),
+            index.search(&store, &query, 5, 32)
+        );
+
+        // 向量文件中缺少未删除节点的向量时需要重建
+        let partial: Vec<(i64, Vec<f32>)> = vectors[..100]
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i as i64 + 1, v.clone()))
+            .collect();
+        let partial = store_of("round-trip-partial", &partial, 8);
+        index.save(&path).unwrap();
+        assert!(Hnsw::load(&path, &partial, |_| None).is_err());
+        let fetched = Hnsw::load(&path, &partial, |id| {
+            Some(QuantVec::quantize(
+                &vectors[id as usize - 1],
+                VectorFormat::F32,
+            ))
+        })
+        .unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(
+            fetched.search(&partial, &query, 5, 32),
+            index.search(&store, &query, 5, 32)
+        );
+    }
+
+    #[test]
+    fn load_rejects_corrupt_nodes() {
+        let store = store_of("corrupt", &[(1, vec![1.0, 0.0])], 2);
+        let header = |count: u32| {
+            let mut b = MAGIC.to_vec();
+            b.extend(2u32.to_le_bytes());
+            b.extend([3]);
+            b.extend(b"f32");
+            b.extend(0u32.to_le_bytes());
+            b.extend(1i64.to_le_bytes());
+            b.extend(1u64.to_le_bytes());
+            b.extend(count.to_le_bytes());
+            b
+        };
+        let path = temp_path("corrupt", "hnsw");
+        let mut zero_levels = header(1);
+        zero_levels.extend(1i64.to_le_bytes());
+        zero_levels.extend([0, 0]);
+        // 邻居数远超上限：须在分配之前拒绝
+        let mut huge_links = header(1);
+        huge_links.extend(1i64.to_le_bytes());
+        huge_links.extend([0, 1]);
+        huge_links.extend(u32::MAX.to_le_bytes());
+        for bytes in [zero_levels, huge_links, header(u32::MAX)] {
+            std::fs::write(&path, bytes).unwrap();
+            assert!(Hnsw::load(&path, &store, |_| None).is_err());
+        }
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,113 @@
+//! 检索结果回填：排序得到的 chunk_id 一次查询取回正文、文件信息、原文偏移与相邻 chunk
+//!
+//! 各检索方式只负责打分排序，展示所需的字段统一在这里读取，避免逐条查询。
+
+use crate::{FileMeta, Neighbours, SourceRange, FILE_META_COLUMNS};
+use rusqlite::{Connection, Result as SqlResult};
+
+/// 一个命中 chunk 的全部展示信息
+pub struct ChunkRecord {
+    pub chunk_id: i64,
+    pub content: String,
+    pub file_name: String,
+    pub file_path: String,
+    pub chunk_index: i64,
+    pub source_range: Option<SourceRange>,
+    pub neighbours: Neighbours,
+    pub file_meta: FileMeta,
+}
+
+/// 按 `ids` 的顺序返回对应的 chunk；已不存在的 id（如检索期间文件被移除）跳过
+pub fn hydrate(conn: &Connection, ids: &[i64]) -> SqlResult<Vec<ChunkRecord>> {
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+    // 命中以 VALUES 形式参与 JOIN，rank 记录传入顺序；相邻 chunk 走 (file_id, chunk_index) 索引
+    let values = vec!["(?, ?)"; ids.len()].join(", ");
+    let sql = format!(
+        "WITH hits(chunk_id, rank) AS (VALUES {values})
+         SELECT c.id, c.content, f.name, f.path, c.chunk_index, c.byte_start, c.byte_end,
+                (SELECT p.content FROM chunks p
+                 WHERE p.file_id = c.file_id AND p.chunk_index = c.chunk_index - 1),
+                (SELECT n.content FROM chunks n
+                 WHERE n.file_id = c.file_id AND n.chunk_index = c.chunk_index + 1),
+                {FILE_META_COLUMNS}
+         FROM hits h
+         JOIN chunks c ON c.id = h.chunk_id
+         JOIN files f ON f.id = c.file_id
+         ORDER BY h.rank"
+    );
+    let params: Vec<i64> = ids
+        .iter()
+        .enumerate()
+        .flat_map(|(rank, &id)| [id, rank as i64])
+        .collect();
+
+    let mut stmt = conn.prepare(&sql)?;
+    let records = stmt
+        .query_map(rusqlite::params_from_iter(params), |row| {
+            let start: Option<i64> = row.get(5)?;
+            let end: Option<i64> = row.get(6)?;
+            Ok(ChunkRecord {
+                chunk_id: row.get(0)?,
+                content: row.get(1)?,
+                file_name: row.get(2)?,
+                file_path: row.get(3)?,
+                chunk_index: row.get(4)?,
+                source_range: start.zip(end).map(|(s, e)| SourceRange {
+                    byte_start: s as usize,
+                    byte_end: e as usize,
+                }),
+                neighbours: Neighbours {
+                    prev: row.get(7)?,
+                    next: row.get(8)?,
+                },
+                file_meta: FileMeta::from_row(row, 9)?,
+            })
+        })?
+        .collect::<SqlResult<_>>()?;
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn preserves_order_and_reads_context() {
+        let conn = test_support::db();
+        conn.execute_batch(
+            "
+            INSERT INTO files (path, name) VALUES ('/docs/a.txt', 'a.txt'), ('/docs/b.txt', 'b.txt');
+            INSERT INTO chunks (id, file_id, content, chunk_index, byte_start, byte_end) VALUES
+                (1, 1, 'a0', 0, 0, 2),
+                (2, 1, 'a1', 1, 4, 6),
+                (3, 1, 'a2', 2, 8, 10),
+                (4, 2, 'b0', 0, NULL, NULL);
+            ",
+        )
+        .unwrap();
+
+        let records = hydrate(&conn, &[3, 99, 4, 2]).unwrap();
+        let ids: Vec<i64> = records.iter().map(|r| r.chunk_id).collect();
+        assert_eq!(ids, vec![3, 4, 2]);
+
+        let a2 = &records[0];
+        assert_eq!((a2.file_name.as_str(), a2.chunk_index), ("a.txt", 2));
+        assert_eq!(a2.neighbours.prev.as_deref(), Some("a1"));
+        assert_eq!(a2.neighbours.next, None);
+        let range = a2.source_range.as_ref().unwrap();
+        assert_eq!((range.byte_start, range.byte_end), (8, 10));
+
+        let b0 = &records[1];
+        assert!(b0.source_range.is_none());
+        assert!(b0.neighbours.prev.is_none() && b0.neighbours.next.is_none());
+
+        let a1 = &records[2];
+        assert_eq!(a1.neighbours.prev.as_deref(), Some("a0"));
+        assert_eq!(a1.neighbours.next.as_deref(), Some("a2"));
+
+        assert!(hydrate(&conn, &[]).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+//! 推理调度：模型由专用工作线程独占，查询请求总是先于批量索引执行
+//!
+//! 嵌入模型与稀疏模型各有一组工作线程，各自维护优先级队列。
+
+use crate::embedding::{
+    EffectiveSessionConfig, EmbeddingModel, ModelDiagnostics, WindowOptions, WindowedEmbedding,
+};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+
+/// 任务优先级（值越大越先执行）
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// 导入等批量任务
+    Bulk,
+    /// 交互式查询
+    Query,
+}
+
+type Task<M> = Box<dyn FnOnce(&mut M) + Send>;
+
+struct Job<M> {
+    priority: Priority,
+    /// 同优先级内按提交顺序执行
+    seq: u64,
+    task: Task<M>,
+}
+
+impl<M> PartialEq for Job<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority && self.seq == other.seq
+    }
+}
+
+impl<M> Eq for Job<M> {}
+
+impl<M> PartialOrd for Job<M> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<M> Ord for Job<M> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+struct Queue<M> {
+    heap: BinaryHeap<Job<M>>,
+    next_seq: u64,
+    closed: bool,
+}
+
+struct Shared<M> {
+    queue: Mutex<Queue<M>>,
+    ready: Condvar,
+}
+
+/// 已提交任务的结果句柄
+pub struct Pending<R>(mpsc::Receiver<R>);
+
+impl<R> Pending<R> {
+    /// 阻塞等待任务完成
+    pub fn wait(self) -> Result<R, String> {
+        self.0.recv().map_err(|_| "推理线程已退出".to_string())
+    }
+
+    /// 任务已完成时取出结果（不阻塞），尚未完成时原样返还句柄
+    pub fn try_wait(self) -> Result<Result<R, String>, Self> {
+        match self.0.try_recv() {
+            Ok(r) => Ok(Ok(r)),
+            Err(mpsc::TryRecvError::Disconnected) => Ok(Err("推理线程已退出".to_string())),
+            Err(mpsc::TryRecvError::Empty) => Err(self),
+        }
+    }
+}
+
+// ── Workers ──────────────────────────────────────────────────────────────────
+
+/// 一组工作线程，每个线程独占一个模型实例，共享同一个优先级队列
+pub struct Workers<M> {
+    shared: Arc<Shared<M>>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl<M: Send + 'static> Workers<M> {
+    /// 为每个模型实例启动一个名为 `{name}-{i}` 的工作线程
+    pub fn start(name: &str, models: Vec<M>) -> Result<Self, String> {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                heap: BinaryHeap::new(),
+                next_seq: 0,
+                closed: false,
+            }),
+            ready: Condvar::new(),
+        });
+
+        let handles = models
+            .into_iter()
+            .enumerate()
+            .map(|(i, model)| {
+                let shared = shared.clone();
+                std::thread::Builder::new()
+                    .name(format!("{name}-{i}"))
+                    .spawn(move || worker_loop(shared, model))
+                    .map_err(|e| format!("推理线程启动失败: {e}"))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Self { shared, handles })
+    }
+
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// 提交任务，由任一空闲工作线程执行
+    pub fn submit<R, F>(&self, priority: Priority, f: F) -> Pending<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut M) -> R + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let task: Task<M> = Box::new(move |model| {
+            tx.send(f(model)).ok();
+        });
+        {
+            let mut q = self.shared.queue.lock().unwrap();
+            let seq = q.next_seq;
+            q.next_seq += 1;
+            q.heap.push(Job {
+                priority,
+                seq,
+                task,
+            });
+        }
+        self.shared.ready.notify_one();
+        Pending(rx)
+    }
+}
+
+impl<M> Drop for Workers<M> {
+    /// 关闭队列：已提交的任务执行完后工作线程退出
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().closed = true;
+        self.shared.ready.notify_all();
+        for h in self.handles.drain(..) {
+            h.join().ok();
+        }
+    }
+}
+
+fn worker_loop<M>(shared: Arc<Shared<M>>, mut model: M) {
+    loop {
+        let job = {
+            let mut q = shared.queue.lock().unwrap();
+            loop {
+                if let Some(job) = q.heap.pop() {
+                    break job;
+                }
+                if q.closed {
+                    return;
+                }
+                q = shared.ready.wait(q).unwrap();
+            }
+        };
+        (job.task)(&mut model);
+    }
+}
+
+// ── InferenceEngine ──────────────────────────────────────────────────────────
+
+/// 嵌入模型的推理引擎：每个工作线程持有一个独立的模型会话
+pub struct InferenceEngine {
+    workers: Workers<EmbeddingModel>,
+    session_config: EffectiveSessionConfig,
+    dimension: usize,
+    diagnostics: ModelDiagnostics,
+}
+
+impl InferenceEngine {
+    pub fn start(models: Vec<EmbeddingModel>) -> Result<Self, String> {
+        let first = models.first().ok_or("至少需要一个模型实例")?;
+        let session_config = first.session_config().clone();
+        let dimension = first.dimension();
+        let diagnostics = first.diagnostics().clone();
+        Ok(Self {
+            workers: Workers::start("locallens-infer", models)?,
+            session_config,
+            dimension,
+            diagnostics,
+        })
+    }
+
+    pub fn session_config(&self) -> &EffectiveSessionConfig {
+        &self.session_config
+    }
+
+    /// 模型输出的完整向量维度
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    /// 第一个工作线程模型的加载诊断
+    pub fn diagnostics(&self) -> &ModelDiagnostics {
+        &self.diagnostics
+    }
+
+    pub fn worker_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// 提交任务，由任一空闲工作线程执行
+    pub fn submit<R, F>(&self, priority: Priority, f: F) -> Pending<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut EmbeddingModel) -> R + Send + 'static,
+    {
+        self.workers.submit(priority, f)
+    }
+
+    /// 编码单条文本并等待结果
+    pub fn encode(&self, priority: Priority, text: &str) -> Result<Vec<f32>, String> {
+        let text = text.to_string();
+        self.submit(priority, move |m| m.encode(&text)).wait()?
+    }
+
+    /// 提交一条文本的编码任务（不等待），`windows` 非空时按滑动窗口编码
+    pub fn submit_encode(
+        &self,
+        priority: Priority,
+        text: String,
+        windows: Option<WindowOptions>,
+    ) -> Pending<Result<WindowedEmbedding, String>> {
+        self.submit(priority, move |m| match &windows {
+            Some(opts) => m.encode_windowed(&text, opts),
+            None => m.encode(&text).map(|v| WindowedEmbedding {
+                combined: v,
+                windows: vec![],
+            }),
+        })
+    }
+}
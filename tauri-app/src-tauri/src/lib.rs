@@ -1,37 +1,124 @@
+// quant、topk、vector_store 公开供 benches/ 中的检索基准使用
+mod bundle;
+mod collections;
+mod db;
+mod dims;
 mod embedding;
-
-use embedding::{bytes_to_vec, cosine_sim, vec_to_bytes, EmbeddingModel};
-use rusqlite::{Connection, Result as SqlResult};
-use serde::Serialize;
+mod embedding_cache;
+mod eval;
+mod fusion;
+mod hnsw;
+mod hydrate;
+mod inference;
+mod manifest;
+mod migrations;
+mod models;
+pub mod quant;
+mod rerank;
+mod sparse;
+#[cfg(test)]
+mod test_support;
+pub mod topk;
+mod vector_cache;
+pub mod vector_store;
+
+use bundle::{Bundle, BundleInfo, MergeTarget, BUNDLE_EXTENSION};
+use collections::{ChunkScope, ChunkSettings, Collection};
+use db::{DbPool, PooledConn};
+use dims::VectorDims;
+use embedding::{truncate_dim, EmbeddingModel, SessionOptions, WindowOptions, WindowedEmbedding};
+use eval::EvalReport;
+use fusion::{Contribution, FusionMethod, RankedList, SignalWeights};
+use hnsw::Hnsw;
+use hydrate::ChunkRecord;
+use inference::{InferenceEngine, Pending, Priority};
+use manifest::{sha256_hex, ModelManifest, MANIFEST_FILE};
+use quant::{cosine_sim, QuantVec, QueryVec, VectorFormat};
+use rerank::{Reranker, RERANKER_DIR};
+use sparse::{SparseEncoder, SparseEngine, DOC_MAX_TERMS, QUERY_MAX_TERMS, SPARSE_DIR};
+use rusqlite::{Connection, Result as SqlResult, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::HashSet;
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 use tauri::{Emitter, Manager};
 use tauri_plugin_dialog::DialogExt;
+use vector_cache::{CacheDelta, VectorCache};
+use vector_store::VectorStore;
 use walkdir::WalkDir;
 
-// ── 当前嵌入模型标识（版本变更时自动清除旧向量）────────────────────────────────
+// ── 内置嵌入模型标识（版本变更时自动清除旧向量）────────────────────────────────
 
+/// 内置模型：新集合未指定模型时使用，也是升级前所有集合共用的模型
 const MODEL_NAME: &str = "paraphrase-multilingual-MiniLM-L12-v2";
 
-// ── 全局模型实例（静态，避免把非 Send 类型放进 Tauri managed state） ──────────
+/// 截断存储时，用完整向量重新打分的候选数
+const RESCORE_CANDIDATES: usize = 100;
 
-static MODEL: OnceLock<Mutex<Option<EmbeddingModel>>> = OnceLock::new();
+/// 导入时累计写入多少个 chunk 后提交一次事务（单个文件不会跨事务拆分）
+const IMPORT_COMMIT_CHUNKS: usize = 500;
 
-fn model_lock() -> &'static Mutex<Option<EmbeddingModel>> {
-    MODEL.get_or_init(|| Mutex::new(None))
-}
+/// 导入事务最长持有写锁的时间，超过后在文件边界提交，避免其他写操作等到 busy_timeout
+const IMPORT_COMMIT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 向量数达到该值后在后台构建 ANN 索引，此前暴力检索已经足够快
+const ANN_MIN_VECTORS: usize = 20_000;
+
+/// ANN 检索时第 0 层的候选宽度下限
+const ANN_EF_SEARCH: usize = 128;
+
+/// 内存中的索引持写锁原地增量更新的上限
+///
+/// 每插入一个节点都要做一次 ef_construction 宽度的搜索：2 万个 384 维节点时约 1 ms（release），
+/// 因此写锁最多持有十几毫秒；新增更多时在锁外从磁盘索引补齐后替换，检索不被阻塞
+const ANN_INPLACE_MAX: usize = 16;
 
 // ── 应用状态 ──────────────────────────────────────────────────────────────────
 
-/// 模型加载状态（存入 managed state 供命令查询）
+/// 数据库连接池（setup 中打开并完成迁移）
+#[derive(Clone)]
+struct DbState(Arc<DbPool>);
+
+/// 各嵌入模型的推理引擎，按模型名索引（加载完成前不在表中，重新加载时整体替换）
+#[derive(Clone)]
+struct EngineState(Arc<RwLock<HashMap<String, Arc<InferenceEngine>>>>);
+
+impl EngineState {
+    fn get(&self, model_id: &str) -> Option<Arc<InferenceEngine>> {
+        self.0.read().unwrap().get(model_id).cloned()
+    }
+
+    /// 已加载的模型名
+    fn models(&self) -> Vec<String> {
+        self.0.read().unwrap().keys().cloned().collect()
+    }
+}
+
+/// 各嵌入模型的加载状态（存入 managed state 供命令查询）
 #[derive(Clone)]
-struct ModelStatusState(Arc<Mutex<ModelStatus>>);
+struct ModelStatusState(Arc<Mutex<HashMap<String, ModelStatus>>>);
+
+impl ModelStatusState {
+    /// 从未加载过的模型视为不可用
+    fn get(&self, model_id: &str) -> ModelStatus {
+        self.0
+            .lock()
+            .unwrap()
+            .get(model_id)
+            .cloned()
+            .unwrap_or(ModelStatus::Unavailable)
+    }
+}
 
 #[derive(Clone, PartialEq)]
 enum ModelStatus {
     Loading,
     Ready,
     Failed(String),
+    /// 模型文件校验失败（截断、被替换或与 tokenizer 不匹配），附带处理建议
+    Corrupted(String),
     Unavailable, // 资源文件不存在
 }
 
@@ -41,55 +128,249 @@ impl ModelStatus {
             ModelStatus::Loading => "loading".into(),
             ModelStatus::Ready => "ready".into(),
             ModelStatus::Failed(e) => format!("failed:{e}"),
+            ModelStatus::Corrupted(e) => format!("corrupted:{e}"),
             ModelStatus::Unavailable => "unavailable".into(),
         }
     }
 }
 
-/// 内存中的向量缓存（避免每次搜索都查 DB）
-struct VectorCache {
-    /// (chunk_id, embedding)
-    entries: Vec<(i64, Vec<f32>)>,
-    /// false 表示导入后缓存失效，下次搜索时重建
-    valid: bool,
-}
-
-impl VectorCache {
-    fn new() -> Self {
-        Self {
-            entries: vec![],
-            valid: false,
-        }
+/// 各嵌入模型的内存向量缓存，按模型名索引
+#[derive(Clone)]
+struct CacheState(Arc<Mutex<HashMap<String, Arc<RwLock<VectorCache>>>>>);
+
+impl CacheState {
+    /// 模型的向量缓存，首次使用时创建（空缓存在检索前从向量文件或数据库加载）
+    fn get(&self, model_id: &str) -> Arc<RwLock<VectorCache>> {
+        self.0
+            .lock()
+            .unwrap()
+            .entry(model_id.to_string())
+            .or_insert_with(|| Arc::new(RwLock::new(VectorCache::new())))
+            .clone()
     }
-    fn invalidate(&mut self) {
-        self.valid = false;
-        self.entries.clear();
+
+    fn all(&self) -> Vec<(String, Arc<RwLock<VectorCache>>)> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(m, c)| (m.clone(), c.clone()))
+            .collect()
     }
 }
 
+/// 各嵌入模型的 ANN 索引，按模型名索引（向量数不足 ANN_MIN_VECTORS 或尚未构建完成时
+/// 不在表中，检索走暴力路径）
+#[derive(Clone)]
+struct AnnState {
+    index: Arc<RwLock<HashMap<String, Hnsw>>>,
+    /// 串行化后台同步任务
+    sync: Arc<Mutex<()>>,
+}
+
+/// 交叉编码器重排模型（可选资源，未安装或加载失败时为 None）
+#[derive(Clone)]
+struct RerankerState(Arc<Mutex<Option<Reranker>>>);
+
+/// 稀疏（SPLADE 类）编码模型的工作线程（可选资源，未安装或加载失败时为 None）
 #[derive(Clone)]
-struct CacheState(Arc<RwLock<VectorCache>>);
+struct SparseState(Arc<RwLock<Option<Arc<SparseEngine>>>>);
+
+impl SparseState {
+    fn get(&self) -> Option<Arc<SparseEngine>> {
+        self.0.read().unwrap().clone()
+    }
+}
 
 // ── 数据结构 ──────────────────────────────────────────────────────────────────
 
+/// 每个模型独立的向量存储设置（app_meta 中按模型名保存）
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelSettings {
+    /// Matryoshka 截断后保存的维度；None = 完整维度
+    pub stored_dim: Option<usize>,
+    /// 截断存储时另存完整向量，检索时对前 RESCORE_CANDIDATES 个候选用完整向量重新打分
+    pub rescore: bool,
+}
+
+/// 检索方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// 依次尝试语义 → 稀疏 → 关键词，取第一个有结果的
+    #[default]
+    Auto,
+    /// 稠密向量余弦相似度
+    Semantic,
+    /// 稀疏 term 权重倒排检索（擅长罕见标识符、型号、人名）
+    Sparse,
+    /// FTS5 全文索引（trigram 子串匹配，BM25 排序）
+    Keyword,
+    /// 语义与词法检索并行执行，按 `fusion` 融合排序
+    Hybrid,
+}
+
+/// 单次查询的可选参数（前端不传时全部取默认值）
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SearchOptions {
+    pub mode: SearchMode,
+    /// 是否用交叉编码器重排（重排模型未加载时忽略）
+    pub rerank: bool,
+    /// 参与重排的候选数
+    pub rerank_top_n: usize,
+    /// 重排耗时上限（毫秒），超时后剩余候选保持向量排序
+    pub rerank_budget_ms: u64,
+    /// 混合检索的融合方式
+    pub fusion: FusionMethod,
+    /// 混合检索中各路信号的权重
+    pub weights: SignalWeights,
+    /// 只在这些集合中检索；为空时检索全部集合
+    pub collections: Vec<i64>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            mode: SearchMode::Auto,
+            rerank: true,
+            rerank_top_n: 50,
+            rerank_budget_ms: 300,
+            fusion: FusionMethod::Rrf,
+            weights: SignalWeights::default(),
+            collections: vec![],
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub struct ImportResult {
     pub files_imported: usize,
     pub chunks_created: usize,
     pub skipped: usize,
+    /// 本次新编码的 chunk 数（不含缓存命中）
     pub embeddings_generated: usize,
+    /// 命中 embedding 缓存、无需重新编码的 chunk 数
+    pub embeddings_cached: usize,
 }
 
 #[derive(Serialize)]
 pub struct SearchResult {
+    pub chunk_id: i64,
     pub content: String,
     pub file_name: String,
     pub file_path: String,
     pub chunk_index: i64,
-    /// 语义相似度 0.0–1.0（关键词模式下为 0.0）
+    /// 语义相似度 0.0–1.0（稀疏模式下为 term 权重点积，关键词模式下为 BM25 相关度，越大越相关）
     pub score: f32,
-    /// true = 语义搜索，false = 关键词回退
+    /// true = 语义搜索，false = 稀疏或关键词检索
     pub is_semantic: bool,
+    /// 实际使用的检索方式（不会是 Auto）
+    pub mode: SearchMode,
+    /// 滑动窗口编码的长段落中与查询最相近的窗口
+    pub matched_span: Option<MatchedSpan>,
+    /// 交叉编码器相关度 0.0–1.0（未重排或超出时间预算时为 None）
+    pub rerank_score: Option<f32>,
+    /// 全文索引标出的命中位置（关键词模式，或混合检索中有关键词命中）
+    pub highlight: Option<TextHighlight>,
+    /// 混合检索中各路信号的贡献（其他模式为空）
+    pub contributions: Vec<Contribution>,
+    /// chunk 在原文件中的字节区间（早于记录偏移的版本导入、尚未重新导入时为 None）
+    pub source_range: Option<SourceRange>,
+    pub neighbours: Neighbours,
+    pub file_meta: FileMeta,
+}
+
+impl SearchResult {
+    /// 由回填的 chunk 构造，检索方式特有的字段（窗口、高亮、贡献等）由调用方补充
+    fn from_record(record: ChunkRecord, score: f32, mode: SearchMode) -> Self {
+        Self {
+            chunk_id: record.chunk_id,
+            content: record.content,
+            file_name: record.file_name,
+            file_path: record.file_path,
+            chunk_index: record.chunk_index,
+            score,
+            is_semantic: mode == SearchMode::Semantic,
+            mode,
+            matched_span: None,
+            rerank_score: None,
+            highlight: None,
+            contributions: Vec::new(),
+            source_range: record.source_range,
+            neighbours: record.neighbours,
+            file_meta: record.file_meta,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SourceRange {
+    pub byte_start: usize,
+    pub byte_end: usize,
+}
+
+/// 同一文件中前后相邻 chunk 的正文（便于展示上下文）
+#[derive(Serialize)]
+pub struct Neighbours {
+    pub prev: Option<String>,
+    pub next: Option<String>,
+}
+
+/// FTS5 snippet()/highlight() 输出，命中片段以 HIGHLIGHT_START/HIGHLIGHT_END 包围
+#[derive(Serialize)]
+pub struct TextHighlight {
+    /// 命中附近的摘要，截断处以 … 表示
+    pub snippet: String,
+    /// 标出全部命中的完整正文
+    pub content: String,
+}
+
+/// 文件元数据（随搜索结果返回，便于前端展示与过滤）
+#[derive(Clone, Serialize)]
+pub struct FileMeta {
+    pub size_bytes: Option<i64>,
+    /// 文件修改时间（UTC，`YYYY-MM-DD HH:MM:SS`）
+    pub modified_at: Option<String>,
+    /// 导入时文件内容的 SHA-256
+    pub content_hash: Option<String>,
+    /// 小写扩展名，如 "txt"
+    pub format: String,
+    pub chunk_count: i64,
+}
+
+/// 与 FileMeta::from_row 顺序一致的列（files 表别名为 f）
+const FILE_META_COLUMNS: &str =
+    "f.size_bytes, f.modified_at, f.content_hash, f.format, f.chunk_count";
+
+impl FileMeta {
+    /// 从查询结果第 `start` 列起读取 FILE_META_COLUMNS
+    fn from_row(row: &rusqlite::Row, start: usize) -> SqlResult<Self> {
+        Ok(Self {
+            size_bytes: row.get(start)?,
+            modified_at: row.get(start + 1)?,
+            content_hash: row.get(start + 2)?,
+            format: row.get(start + 3)?,
+            chunk_count: row.get(start + 4)?,
+        })
+    }
+}
+
+/// 高亮标记：Unicode 私有区字符，正文中几乎不会出现，前端转义 HTML 后替换为 <mark>
+pub const HIGHLIGHT_START: &str = "\u{E000}";
+pub const HIGHLIGHT_END: &str = "\u{E001}";
+
+/// snippet() 摘要的 token 数（trigram 下约等于字符数，FTS5 上限 64）
+const SNIPPET_TOKENS: i64 = 48;
+
+#[derive(Serialize)]
+pub struct MatchedSpan {
+    /// content 中的字节区间
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub text: String,
+    pub score: f32,
 }
 
 // ── 数据库 ────────────────────────────────────────────────────────────────────
@@ -100,46 +381,22 @@ fn db_path(app: &tauri::AppHandle) -> PathBuf {
     dir.join("locallens.db")
 }
 
-fn open_db(app: &tauri::AppHandle) -> Result<Connection, String> {
-    Connection::open(db_path(app))
-        .map_err(|e| e.to_string())
-        .and_then(|conn| {
-            init_schema(&conn).map_err(|e| e.to_string())?;
-            Ok(conn)
-        })
+/// 模型的 ANN 索引文件，与数据库放在同一目录
+fn ann_path(app: &tauri::AppHandle, model_id: &str) -> PathBuf {
+    db_path(app).with_extension(format!("{model_id}.hnsw"))
 }
 
-fn init_schema(conn: &Connection) -> SqlResult<()> {
-    conn.execute_batch(
-        "
-        CREATE TABLE IF NOT EXISTS app_meta (
-            key   TEXT PRIMARY KEY,
-            value TEXT NOT NULL
-        );
-        CREATE TABLE IF NOT EXISTS files (
-            id           INTEGER PRIMARY KEY AUTOINCREMENT,
-            path         TEXT NOT NULL UNIQUE,
-            name         TEXT NOT NULL,
-            imported_at  DATETIME DEFAULT CURRENT_TIMESTAMP
-        );
-        CREATE TABLE IF NOT EXISTS chunks (
-            id           INTEGER PRIMARY KEY AUTOINCREMENT,
-            file_id      INTEGER NOT NULL REFERENCES files(id),
-            content      TEXT NOT NULL,
-            chunk_index  INTEGER NOT NULL
-        );
-        -- 向量存储：BLOB = hidden_dim × f32 little-endian
-        CREATE TABLE IF NOT EXISTS chunk_embeddings (
-            chunk_id  INTEGER PRIMARY KEY REFERENCES chunks(id),
-            embedding BLOB NOT NULL
-        );
-        CREATE INDEX IF NOT EXISTS idx_chunks_file    ON chunks(file_id);
-        CREATE INDEX IF NOT EXISTS idx_chunks_content ON chunks(content);
-        ",
-    )
+/// 模型的暴力检索用内存映射向量文件，与数据库放在同一目录
+fn vectors_path(app: &tauri::AppHandle, model_id: &str) -> PathBuf {
+    db_path(app).with_extension(format!("{model_id}.vectors"))
+}
+
+/// 从连接池借出一个连接
+fn open_db(app: &tauri::AppHandle) -> Result<PooledConn, String> {
+    app.state::<DbState>().0.get()
 }
 
-/// 检查模型版本，若与上次不同则清除所有旧向量并更新记录
+/// 检查内置模型版本，若与上次不同则清除使用旧模型的集合的向量，这些集合改用新的内置模型
 /// 返回 true 表示发生了模型切换（用户需要重新导入以生成新向量）
 fn check_model_version(conn: &Connection) -> bool {
     let stored: Option<String> = conn
@@ -160,7 +417,23 @@ fn check_model_version(conn: &Connection) -> bool {
                 "[LocalLens] 检测到模型切换: {} → {}，清除旧 embedding 向量（请重新导入文件以生成新向量）",
                 old_name, MODEL_NAME
             );
-            conn.execute("DELETE FROM chunk_embeddings", []).ok();
+            for table in ["chunk_embeddings", "chunk_embeddings_full", "chunk_windows"] {
+                conn.execute(
+                    &format!(
+                        "DELETE FROM {table} WHERE chunk_id IN
+                         (SELECT e.chunk_id FROM {table} e {})",
+                        collections::model_join(1)
+                    ),
+                    [old_name],
+                )
+                .ok();
+            }
+            conn.execute(
+                "UPDATE collections SET model_id = ?1 WHERE model_id = ?2",
+                [MODEL_NAME, old_name],
+            )
+            .ok();
+            bump_vector_generation(conn, MODEL_NAME).ok();
             conn.execute(
                 "INSERT OR REPLACE INTO app_meta (key, value) VALUES ('model_name', ?1)",
                 rusqlite::params![MODEL_NAME],
@@ -180,6 +453,227 @@ fn check_model_version(conn: &Connection) -> bool {
     }
 }
 
+fn vector_generation_key(model_id: &str) -> String {
+    format!("vector_generation:{model_id}")
+}
+
+/// 模型的向量变更代数：每个修改该模型向量的事务在提交前加一
+///
+/// 内存缓存与向量文件记录各自对应的代数，与数据库不一致即说明漏掉了修改
+fn bump_vector_generation(conn: &Connection, model_id: &str) -> SqlResult<u64> {
+    conn.query_row(
+        "INSERT INTO app_meta (key, value) VALUES (?1, '1')
+         ON CONFLICT(key) DO UPDATE SET value = CAST(value AS INTEGER) + 1
+         RETURNING CAST(value AS INTEGER)",
+        [vector_generation_key(model_id)],
+        |r| r.get::<_, i64>(0),
+    )
+    .map(|g| g as u64)
+}
+
+fn load_vector_generation(conn: &Connection, model_id: &str) -> u64 {
+    conn.query_row(
+        "SELECT CAST(value AS INTEGER) FROM app_meta WHERE key = ?1",
+        [vector_generation_key(model_id)],
+        |r| r.get::<_, i64>(0),
+    )
+    .map_or(0, |g| g as u64)
+}
+
+/// 一次写入对各模型向量的增量，按模型名索引
+type VectorChanges = HashMap<String, CacheDelta>;
+
+/// 提交事务；有向量增量的模型先在同一事务中递增代数，提交后把增量应用到各自的缓存
+///
+/// 返回向量有变化的模型，供调用方同步 ANN 索引
+fn commit_vector_changes(
+    tx: rusqlite::Transaction,
+    cache_st: &CacheState,
+    changes: VectorChanges,
+) -> Result<Vec<String>, String> {
+    let mut bumped = Vec::new();
+    for (model_id, delta) in changes {
+        if !delta.is_empty() {
+            let generation = bump_vector_generation(&tx, &model_id).map_err(|e| e.to_string())?;
+            bumped.push((model_id, delta, generation));
+        }
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(bumped
+        .into_iter()
+        .map(|(model_id, delta, generation)| {
+            cache_st
+                .get(&model_id)
+                .write()
+                .unwrap()
+                .apply(delta, generation);
+            model_id
+        })
+        .collect())
+}
+
+/// 当前向量存储格式（默认 f32）
+fn load_vector_format(conn: &Connection) -> VectorFormat {
+    conn.query_row(
+        "SELECT value FROM app_meta WHERE key = 'vector_format'",
+        [],
+        |r| r.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|s| VectorFormat::parse(&s))
+    .unwrap_or(VectorFormat::F32)
+}
+
+/// 向量格式转换尚未完成（上次切换失败或中途退出）
+fn vector_conversion_pending(conn: &Connection) -> bool {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM app_meta WHERE key = 'vector_format_pending')",
+        [],
+        |r| r.get(0),
+    )
+    .unwrap_or(false)
+}
+
+/// 把 chunk_embeddings 与 chunk_windows 中的向量统一转换为 `format`，返回转换的条数
+///
+/// 新格式与进行中标记先在同一事务中写入 app_meta，之后写入的向量直接使用新格式；
+/// 已有向量按 rowid 分批转换，每批一个事务，避免一次性把全部向量读入内存。
+/// 标记与最后一批在同一事务中清除，中途失败或退出时保留，启动时据此接着转换。
+/// chunk_embeddings_full 是重新打分用的完整精度备份，始终保存为 f32，不参与转换。
+fn convert_vectors(conn: &Connection, format: VectorFormat) -> SqlResult<usize> {
+    // 每批先读后写，立即取得写锁，避免读到一半被其他写事务抢先、升级写锁失败
+    let begin = || rusqlite::Transaction::new_unchecked(conn, TransactionBehavior::Immediate);
+    let tx = begin()?;
+    tx.execute(
+        "INSERT OR REPLACE INTO app_meta (key, value) VALUES ('vector_format', ?1)",
+        rusqlite::params![format.as_str()],
+    )?;
+    tx.execute(
+        "INSERT OR REPLACE INTO app_meta (key, value) VALUES ('vector_format_pending', '1')",
+        [],
+    )?;
+    tx.commit()?;
+
+    let tables = ["chunk_embeddings", "chunk_windows"];
+    let mut converted = 0usize;
+    for (ti, table) in tables.iter().enumerate() {
+        let select = format!(
+            "SELECT rowid, embedding FROM {table} WHERE rowid > ?1 ORDER BY rowid LIMIT 1000"
+        );
+        let update = format!("UPDATE {table} SET embedding = ?1 WHERE rowid = ?2");
+        let mut last = 0i64;
+        loop {
+            let tx = begin()?;
+            let batch: Vec<(i64, Vec<u8>)> = tx
+                .prepare(&select)?
+                .query_map(rusqlite::params![last], |r| Ok((r.get(0)?, r.get(1)?)))?
+                .collect::<SqlResult<_>>()?;
+            let Some(&(max_rowid, _)) = batch.last() else {
+                if ti + 1 == tables.len() {
+                    tx.execute(
+                        "DELETE FROM app_meta WHERE key = 'vector_format_pending'",
+                        [],
+                    )?;
+                    tx.commit()?;
+                }
+                break;
+            };
+            last = max_rowid;
+            let mut changed = 0usize;
+            for (rowid, blob) in batch {
+                let v = match QuantVec::from_bytes(&blob, None) {
+                    Ok(v) if v.format() != format => v,
+                    _ => continue,
+                };
+                let bytes = QuantVec::quantize(&v.to_f32(), format).to_bytes();
+                tx.execute(&update, rusqlite::params![bytes, rowid])?;
+                changed += 1;
+            }
+            // 不按行区分所属模型，每批推进所有在用模型的代数
+            if changed > 0 {
+                for model_id in collections::models_in_use(&tx)? {
+                    bump_vector_generation(&tx, &model_id)?;
+                }
+            }
+            tx.commit()?;
+            converted += changed;
+        }
+    }
+    Ok(converted)
+}
+
+/// 串行化向量格式转换（切换命令与启动时的续转）
+static VECTOR_CONVERSION: Mutex<()> = Mutex::new(());
+
+/// 转换全部向量到 `format`；无论成败都使各模型的向量缓存失效并重建 ANN 索引
+fn run_vector_conversion(app: &tauri::AppHandle, format: VectorFormat) -> Result<usize, String> {
+    let _guard = VECTOR_CONVERSION.lock().unwrap();
+    let conn = open_db(app)?;
+    let result = convert_vectors(&conn, format).map_err(|e| e.to_string());
+    for (_, cache) in app.state::<CacheState>().all() {
+        cache.write().unwrap().invalidate();
+    }
+    for model_id in app.state::<EngineState>().models() {
+        spawn_ann_sync(app, &model_id, true);
+    }
+    let converted = result?;
+    eprintln!(
+        "[LocalLens] 向量格式切换为 {}，转换 {converted} 条",
+        format.as_str()
+    );
+    Ok(converted)
+}
+
+/// 上次的格式转换未完成时，在后台按已记录的新格式接着转换
+fn resume_vector_conversion(app: &tauri::AppHandle) {
+    let Ok(conn) = open_db(app) else {
+        return;
+    };
+    if !vector_conversion_pending(&conn) {
+        return;
+    }
+    let format = load_vector_format(&conn);
+    drop(conn);
+    eprintln!(
+        "[LocalLens] 上次向量格式转换未完成，继续转换为 {}",
+        format.as_str()
+    );
+    let app = app.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = run_vector_conversion(&app, format) {
+            eprintln!("[LocalLens] 向量格式转换失败，下次启动时重试: {e}");
+        }
+    });
+}
+
+fn model_settings_key(model_id: &str) -> String {
+    format!("model_settings:{model_id}")
+}
+
+/// 读取模型的向量存储设置
+fn load_model_settings(conn: &Connection, model_id: &str) -> ModelSettings {
+    conn.query_row(
+        "SELECT value FROM app_meta WHERE key = ?1",
+        rusqlite::params![model_settings_key(model_id)],
+        |r| r.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|json| serde_json::from_str(&json).ok())
+    .unwrap_or_default()
+}
+
+/// 读取保存的 ORT 会话参数（未设置或解析失败时用默认值）
+fn load_session_options(conn: &Connection) -> SessionOptions {
+    conn.query_row(
+        "SELECT value FROM app_meta WHERE key = 'session_options'",
+        [],
+        |r| r.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|json| serde_json::from_str(&json).ok())
+    .unwrap_or_default()
+}
+
 // ── 资源路径解析 ──────────────────────────────────────────────────────────────
 
 /// 开发模式用编译期 CARGO_MANIFEST_DIR，生产模式用 resource_dir()
@@ -199,37 +693,62 @@ fn resource_dir(app: &tauri::AppHandle) -> PathBuf {
 
 // ── 文本分段 ──────────────────────────────────────────────────────────────────
 
-fn segment_text(text: &str) -> Vec<String> {
-    const MAX: usize = 500;
-    const MIN: usize = 30;
+/// segment_text 切出的段落
+struct Segment {
+    text: String,
+    /// 在原文中的字节区间（长段落按句子合并时为首句开头到末句结尾）
+    byte_start: usize,
+    byte_end: usize,
+}
+
+fn segment_text(text: &str, settings: &ChunkSettings) -> Vec<Segment> {
+    let (max, min) = (settings.max_bytes, settings.min_bytes);
+    // split/trim 得到的都是 text 的子串，指针差即字节偏移
+    let offset = |s: &str| s.as_ptr() as usize - text.as_ptr() as usize;
     let mut chunks = Vec::new();
     for para in text.split("\n\n") {
         let para = para.trim();
-        if para.len() < MIN {
+        if para.len() < min {
             continue;
         }
-        if para.len() <= MAX {
-            chunks.push(para.to_string());
+        if para.len() <= max {
+            chunks.push(Segment {
+                text: para.to_string(),
+                byte_start: offset(para),
+                byte_end: offset(para) + para.len(),
+            });
         } else {
             let mut buf = String::new();
+            let mut span = (0, 0);
             for sent in para.split(". ") {
                 let sent = sent.trim();
                 if sent.is_empty() {
                     continue;
                 }
-                if !buf.is_empty() && buf.len() + sent.len() + 2 > MAX {
-                    if buf.len() >= MIN {
-                        chunks.push(buf.trim().to_string());
+                if !buf.is_empty() && buf.len() + sent.len() + 2 > max {
+                    if buf.len() >= min {
+                        chunks.push(Segment {
+                            text: buf.trim().to_string(),
+                            byte_start: span.0,
+                            byte_end: span.1,
+                        });
                     }
                     buf.clear();
                 }
-                if !buf.is_empty() {
+                if buf.is_empty() {
+                    span.0 = offset(sent);
+                } else {
                     buf.push_str(". ");
                 }
                 buf.push_str(sent);
+                span.1 = offset(sent) + sent.len();
             }
-            if buf.len() >= MIN {
-                chunks.push(buf.trim().to_string());
+            if buf.len() >= min {
+                chunks.push(Segment {
+                    text: buf.trim().to_string(),
+                    byte_start: span.0,
+                    byte_end: span.1,
+                });
             }
         }
     }
@@ -238,19 +757,403 @@ fn segment_text(text: &str) -> Vec<String> {
 
 // ── Tauri 命令 ────────────────────────────────────────────────────────────────
 
-/// 返回当前模型加载状态字符串
+/// 返回模型加载状态字符串（未指定模型时为内置模型）
+#[tauri::command]
+async fn get_model_status(
+    state: tauri::State<'_, ModelStatusState>,
+    model_id: Option<String>,
+) -> Result<String, String> {
+    Ok(state
+        .get(model_id.as_deref().unwrap_or(MODEL_NAME))
+        .as_str())
+}
+
+/// 返回模型的加载诊断（加载耗时、预热推理耗时、维度、输入输出名）与实际生效的会话配置
+///
+/// 未指定模型时为内置模型；模型未就绪时 diagnostics 为 null，status 说明原因
+#[tauri::command]
+async fn get_model_info(
+    model_st: tauri::State<'_, ModelStatusState>,
+    engine_st: tauri::State<'_, EngineState>,
+    model_id: Option<String>,
+) -> Result<serde_json::Value, String> {
+    let model_id = model_id.as_deref().unwrap_or(MODEL_NAME);
+    let status = model_st.get(model_id).as_str();
+    let engine = engine_st.get(model_id);
+    Ok(serde_json::json!({
+        "model": model_id,
+        "status": status,
+        "workers": engine.as_ref().map(|e| e.worker_count()),
+        "diagnostics": engine.as_ref().map(|e| e.diagnostics().clone()),
+        "session": engine.as_ref().map(|e| e.session_config().clone()),
+    }))
+}
+
+/// 列出已安装的嵌入模型及其加载状态和使用它的集合，供新建集合时选择
+#[tauri::command]
+async fn list_models(
+    app: tauri::AppHandle,
+    model_st: tauri::State<'_, ModelStatusState>,
+) -> Result<serde_json::Value, String> {
+    let all = collections::list(&*open_db(&app)?).map_err(|e| e.to_string())?;
+    let models: Vec<serde_json::Value> = models::installed(&resource_dir(&app))
+        .into_iter()
+        .map(|model_id| {
+            let used_by: Vec<i64> = all
+                .iter()
+                .filter(|c| c.model_id == model_id)
+                .map(|c| c.id)
+                .collect();
+            serde_json::json!({
+                "model": model_id,
+                "builtin": model_id == MODEL_NAME,
+                "status": model_st.get(&model_id).as_str(),
+                "collections": used_by,
+            })
+        })
+        .collect();
+    Ok(serde_json::json!(models))
+}
+
+/// 返回已加载的重排模型标识（未安装或加载失败时为 null）
+#[tauri::command]
+async fn get_reranker_status(
+    state: tauri::State<'_, RerankerState>,
+) -> Result<Option<String>, String> {
+    Ok(state
+        .0
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|r| r.model_id().to_string()))
+}
+
+/// 返回保存的 ORT 会话参数及内置模型实际生效的配置（模型未加载时为 null）
+#[tauri::command]
+async fn get_session_config(app: tauri::AppHandle) -> Result<serde_json::Value, String> {
+    let configured = load_session_options(&*open_db(&app)?);
+    let effective = app
+        .state::<EngineState>()
+        .get(MODEL_NAME)
+        .map(|e| e.session_config().clone());
+    Ok(serde_json::json!({ "configured": configured, "effective": effective }))
+}
+
+/// 保存 ORT 会话参数并在后台重新加载各模型使其生效
+#[tauri::command]
+async fn set_session_options(app: tauri::AppHandle, options: SessionOptions) -> Result<(), String> {
+    let json = serde_json::to_string(&options).map_err(|e| e.to_string())?;
+    open_db(&app)?
+        .execute(
+            "INSERT OR REPLACE INTO app_meta (key, value) VALUES ('session_options', ?1)",
+            rusqlite::params![json],
+        )
+        .map_err(|e| e.to_string())?;
+    spawn_model_loaders(&app);
+    Ok(())
+}
+
+/// 切换向量存储格式（f32 / f16 / int8），并把已有向量转换为新格式，返回转换的条数
+///
+/// 转换中途失败时新格式已生效，未转换的向量在下次启动时接着转换
+#[tauri::command]
+async fn set_vector_format(app: tauri::AppHandle, format: VectorFormat) -> Result<usize, String> {
+    run_vector_conversion(&app, format)
+}
+
+/// 返回模型的向量存储设置（未指定模型时为内置模型）
+#[tauri::command]
+async fn get_model_settings(
+    app: tauri::AppHandle,
+    model_id: Option<String>,
+) -> Result<ModelSettings, String> {
+    Ok(load_model_settings(
+        &*open_db(&app)?,
+        model_id.as_deref().unwrap_or(MODEL_NAME),
+    ))
+}
+
+/// 修改模型（未指定时为内置模型）的存储维度 / 重新打分设置，并按新维度重写使用该模型的
+/// 集合中已有的向量
+///
+/// 截断维度须列在模型清单的 matryoshka_dims 中；返回转换与无法转换（需重新导入）的条数
 #[tauri::command]
-async fn get_model_status(state: tauri::State<'_, ModelStatusState>) -> Result<String, String> {
-    Ok(state.0.lock().unwrap().as_str())
+async fn set_model_settings(
+    app: tauri::AppHandle,
+    engine_st: tauri::State<'_, EngineState>,
+    cache_st: tauri::State<'_, CacheState>,
+    settings: ModelSettings,
+    model_id: Option<String>,
+) -> Result<serde_json::Value, String> {
+    let model_id = model_id.as_deref().unwrap_or(MODEL_NAME);
+    let engine = engine_st
+        .get(model_id)
+        .ok_or("模型未加载，无法确定向量维度")?;
+    let full = engine.dimension();
+    if let Some(dim) = settings.stored_dim {
+        if dim == 0 || dim > full {
+            return Err(format!("存储维度须在 1–{full} 之间"));
+        }
+        if dim < full {
+            let allowed = ModelManifest::load(&models::model_dir(&resource_dir(&app), model_id))?
+                .map(|m| m.matryoshka_dims)
+                .unwrap_or_default();
+            if !allowed.contains(&dim) {
+                return Err(format!(
+                    "{model_id} 未声明支持截断到 {dim} 维（模型清单 matryoshka_dims: {allowed:?}）"
+                ));
+            }
+        }
+    }
+
+    let conn = open_db(&app)?;
+    let json = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_meta (key, value) VALUES (?1, ?2)",
+        rusqlite::params![model_settings_key(model_id), json],
+    )
+    .map_err(|e| e.to_string())?;
+
+    let dims = VectorDims::new(&settings, full);
+    let (converted, unresolved) =
+        dims::resize_vectors(&conn, model_id, dims, load_vector_format(&conn))
+            .map_err(|e| e.to_string())?;
+    cache_st.get(model_id).write().unwrap().invalidate();
+    spawn_ann_sync(&app, model_id, true);
+    if unresolved > 0 {
+        app.emit("reindex-required", model_id).ok();
+    }
+    Ok(serde_json::json!({ "converted": converted, "unresolved": unresolved }))
+}
+
+/// 导入时待写入的文件记录
+struct FileRecord {
+    collection_id: i64,
+    path: String,
+    name: String,
+    size_bytes: Option<i64>,
+    modified_secs: Option<i64>,
+    content_hash: String,
+    format: String,
+}
+
+/// 一个 chunk 写库前已算好的全部数据
+struct PreparedChunk {
+    text: String,
+    /// 在原文中的字节区间
+    byte_start: usize,
+    byte_end: usize,
+    embedding: Option<WindowedEmbedding>,
+    /// embedding 缓存键；未命中时新编码的向量写回缓存，命中时刷新使用时间
+    cache_key: String,
+    cache_hit: bool,
+    sparse: Vec<(u32, f32)>,
+}
+
+/// 写入一个文件的记录、chunk 与派生数据（替换该文件的旧数据），返回写入的向量数
+///
+/// `model_id` 为文件所属集合的模型；调用方负责事务边界；语句经 prepare_cached 在同一连接内复用
+fn write_file(
+    conn: &Connection,
+    model_id: &str,
+    file: &FileRecord,
+    chunks: &[PreparedChunk],
+    dims: Option<VectorDims>,
+    vector_format: VectorFormat,
+) -> SqlResult<CacheDelta> {
+    let file_id: i64 = conn.query_row(
+        "INSERT INTO files (collection_id, path, name, size_bytes, modified_at, content_hash,
+                            format, chunk_count)
+         VALUES (?1, ?2, ?3, ?4, datetime(?5, 'unixepoch'), ?6, ?7, ?8)
+         ON CONFLICT(collection_id, path) DO UPDATE SET
+             imported_at  = CURRENT_TIMESTAMP,
+             size_bytes   = excluded.size_bytes,
+             modified_at  = excluded.modified_at,
+             content_hash = excluded.content_hash,
+             format       = excluded.format,
+             chunk_count  = excluded.chunk_count
+         RETURNING id",
+        rusqlite::params![
+            file.collection_id,
+            file.path,
+            file.name,
+            file.size_bytes,
+            file.modified_secs,
+            file.content_hash,
+            file.format,
+            chunks.len() as i64
+        ],
+        |r| r.get(0),
+    )?;
+
+    // 删旧数据（支持重新导入），向量、窗口与稀疏 term 随 chunk 级联删除
+    let mut delta = CacheDelta {
+        removed: conn
+            .prepare_cached("SELECT id FROM chunks WHERE file_id = ?1")?
+            .query_map(rusqlite::params![file_id], |r| r.get(0))?
+            .collect::<SqlResult<_>>()?,
+        ..Default::default()
+    };
+    conn.execute(
+        "DELETE FROM chunks WHERE file_id = ?1",
+        rusqlite::params![file_id],
+    )?;
+
+    let mut insert_chunk = conn.prepare_cached(
+        "INSERT INTO chunks (file_id, content, chunk_index, byte_start, byte_end)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+    let mut insert_sparse = conn.prepare_cached(
+        "INSERT INTO chunk_sparse (term_id, chunk_id, weight) VALUES (?1, ?2, ?3)",
+    )?;
+    let mut insert_embedding =
+        conn.prepare_cached("INSERT INTO chunk_embeddings (chunk_id, embedding) VALUES (?1, ?2)")?;
+    let mut insert_full = conn.prepare_cached(
+        "INSERT INTO chunk_embeddings_full (chunk_id, embedding) VALUES (?1, ?2)",
+    )?;
+    let mut insert_window = conn.prepare_cached(
+        "INSERT INTO chunk_windows (chunk_id, window_index, byte_start, byte_end, embedding)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+
+    for (ci, chunk) in chunks.iter().enumerate() {
+        let chunk_id = insert_chunk.insert(rusqlite::params![
+            file_id,
+            chunk.text,
+            ci as i64,
+            chunk.byte_start as i64,
+            chunk.byte_end as i64
+        ])?;
+
+        for &(term, weight) in &chunk.sparse {
+            insert_sparse.execute(rusqlite::params![term as i64, chunk_id, weight as f64])?;
+        }
+
+        let (Some(emb), Some(dims)) = (&chunk.embedding, dims) else {
+            continue;
+        };
+        if chunk.cache_hit {
+            embedding_cache::touch(conn, model_id, &chunk.cache_key)?;
+        } else {
+            embedding_cache::store(conn, model_id, &chunk.cache_key, emb)?;
+        }
+        let stored = QuantVec::quantize(&truncate_dim(&emb.combined, dims.stored), vector_format);
+        insert_embedding.execute(rusqlite::params![chunk_id, stored.to_bytes()])?;
+        if dims.rescore {
+            insert_full.execute(rusqlite::params![
+                chunk_id,
+                QuantVec::F32(emb.combined.clone()).to_bytes()
+            ])?;
+        }
+        // 单窗口的窗口向量与整体向量相同，不重复存储
+        if emb.windows.len() > 1 {
+            for (wi, w) in emb.windows.iter().enumerate() {
+                insert_window.execute(rusqlite::params![
+                    chunk_id,
+                    wi as i64,
+                    w.byte_start as i64,
+                    w.byte_end as i64,
+                    QuantVec::quantize(&truncate_dim(&w.vector, dims.stored), vector_format)
+                        .to_bytes()
+                ])?;
+            }
+        }
+        delta.added.push((chunk_id, stored));
+    }
+    Ok(delta)
+}
+
+/// 导入中尚未提交的写事务：多个文件的写入合并提交
+///
+/// 累计写入的 chunk 达到批量大小或持有时间达到上限后在文件边界提交；
+/// 等待推理时若结果尚未就绪先提交，等待期间不占用写锁
+struct ImportBatch<'c> {
+    tx: Option<rusqlite::Transaction<'c>>,
+    started: Instant,
+    /// 事务中已写入的 chunk 数
+    chunks: usize,
+    /// 导入目标集合的模型
+    model_id: String,
+    /// 事务提交后应用到该模型向量缓存的增量
+    delta: CacheDelta,
+}
+
+impl<'c> ImportBatch<'c> {
+    fn new(model_id: &str) -> Self {
+        Self {
+            tx: None,
+            started: Instant::now(),
+            chunks: 0,
+            model_id: model_id.to_string(),
+            delta: CacheDelta::default(),
+        }
+    }
+
+    /// 当前事务，没有时开启一个
+    fn tx(&mut self, conn: &'c Connection) -> Result<&mut rusqlite::Transaction<'c>, String> {
+        let tx = match self.tx.take() {
+            Some(tx) => tx,
+            None => {
+                self.started = Instant::now();
+                conn.unchecked_transaction().map_err(|e| e.to_string())?
+            }
+        };
+        Ok(self.tx.insert(tx))
+    }
+
+    fn is_full(&self) -> bool {
+        self.chunks >= IMPORT_COMMIT_CHUNKS || self.started.elapsed() >= IMPORT_COMMIT_INTERVAL
+    }
+
+    fn commit(&mut self, cache_st: &CacheState) -> Result<(), String> {
+        if let Some(tx) = self.tx.take() {
+            let delta = std::mem::take(&mut self.delta);
+            commit_vector_changes(
+                tx,
+                cache_st,
+                HashMap::from([(self.model_id.clone(), delta)]),
+            )?;
+            self.chunks = 0;
+        }
+        Ok(())
+    }
+
+    /// 等待一个推理任务；尚未完成时先提交当前事务再阻塞等待
+    ///
+    /// 外层错误为提交失败，内层错误为推理线程退出
+    fn wait<R>(
+        &mut self,
+        cache_st: &CacheState,
+        job: Pending<R>,
+    ) -> Result<Result<R, String>, String> {
+        match job.try_wait() {
+            Ok(result) => Ok(result),
+            Err(job) => {
+                self.commit(cache_st)?;
+                Ok(job.wait())
+            }
+        }
+    }
 }
 
-/// 选择文件夹、导入 TXT、生成 embedding，实时发送进度事件
+/// 选择文件夹导入到集合 `collection_id`（未指定时为默认集合）：导入 TXT、用集合的模型生成
+/// embedding，实时发送进度事件，文件夹记为该集合的根目录
+///
+/// 分段按集合的设置；`long_text` 非空时覆盖集合的长段落设置，
+/// 超过一个窗口的段落按滑动窗口编码并保留各窗口向量
 #[tauri::command]
 async fn select_and_import_folder(
     app: tauri::AppHandle,
     model_st: tauri::State<'_, ModelStatusState>,
+    engine_st: tauri::State<'_, EngineState>,
     cache_st: tauri::State<'_, CacheState>,
+    sparse_st: tauri::State<'_, SparseState>,
+    collection_id: Option<i64>,
+    long_text: Option<WindowOptions>,
 ) -> Result<ImportResult, String> {
+    let collection = collections::resolve(&*open_db(&app)?, collection_id)?;
+    let long_text = long_text.or_else(|| collection.chunking.long_text.clone());
+
     let selected = app.dialog().file().blocking_pick_folder();
     let folder_path = match selected {
         Some(tauri_plugin_dialog::FilePath::Path(p)) => p,
@@ -259,8 +1162,21 @@ async fn select_and_import_folder(
         _ => return Err("Unsupported path type".to_string()),
     };
 
-    let model_ready = *model_st.0.lock().unwrap() == ModelStatus::Ready;
+    let model_id = collection.model_id.as_str();
+    let engine = if model_st.get(model_id) == ModelStatus::Ready {
+        engine_st.get(model_id)
+    } else {
+        None
+    };
+    let sparse_engine = sparse_st.get();
     let conn = open_db(&app)?;
+    collections::add_root(&conn, collection.id, &folder_path.to_string_lossy())
+        .map_err(|e| e.to_string())?;
+    let vector_format = load_vector_format(&conn);
+    let model_settings = load_model_settings(&conn, model_id);
+    let dims = engine
+        .as_ref()
+        .map(|e| VectorDims::new(&model_settings, e.dimension()));
 
     // 先收集所有 TXT 文件，得到总数用于进度
     let txt_files: Vec<_> = WalkDir::new(&folder_path)
@@ -283,6 +1199,11 @@ async fn select_and_import_folder(
     let mut chunks_created = 0usize;
     let mut skipped = 0usize;
     let mut embeddings_generated = 0usize;
+    let mut embeddings_cached = 0usize;
+
+    // 多个文件合并到一个事务中提交以减少 fsync；每个文件在独立的 savepoint 中写入，
+    // 出错时只回滚该文件
+    let mut batch = ImportBatch::new(model_id);
 
     for (idx, entry) in txt_files.iter().enumerate() {
         let path = entry.path();
@@ -313,72 +1234,90 @@ async fn select_and_import_folder(
             }
         };
 
-        // 查找或插入文件记录
-        let file_id: i64 = {
-            let existing: Option<i64> = conn
-                .query_row(
-                    "SELECT id FROM files WHERE path = ?1",
-                    rusqlite::params![path_str],
-                    |r| r.get(0),
-                )
-                .ok();
-            if let Some(id) = existing {
-                conn.execute(
-                    "UPDATE files SET imported_at = CURRENT_TIMESTAMP WHERE id = ?1",
-                    rusqlite::params![id],
-                )
-                .map_err(|e| e.to_string())?;
-                id
-            } else {
-                conn.execute(
-                    "INSERT INTO files (path, name) VALUES (?1, ?2)",
-                    rusqlite::params![path_str, file_name],
-                )
-                .map_err(|e| e.to_string())?;
-                conn.last_insert_rowid()
-            }
+        // 文件元数据：修改时间以 Unix 秒传入，由 SQLite 转为 UTC 时间文本
+        let meta = entry.metadata().ok();
+        let record = FileRecord {
+            collection_id: collection.id,
+            path: path_str,
+            name: file_name.clone(),
+            size_bytes: meta.as_ref().map(|m| m.len() as i64),
+            modified_secs: meta
+                .and_then(|m| m.modified().ok())
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs() as i64),
+            content_hash: sha256_hex(content.as_bytes()),
+            format: path
+                .extension()
+                .and_then(|x| x.to_str())
+                .unwrap_or("")
+                .to_lowercase(),
         };
 
-        // 删旧数据（支持重新导入）
-        conn.execute(
-            "DELETE FROM chunk_embeddings WHERE chunk_id IN (SELECT id FROM chunks WHERE file_id=?1)",
-            rusqlite::params![file_id],
-        )
-        .map_err(|e| e.to_string())?;
-        conn.execute(
-            "DELETE FROM chunks WHERE file_id = ?1",
-            rusqlite::params![file_id],
-        )
-        .map_err(|e| e.to_string())?;
-
-        let chunks = segment_text(&content);
+        let chunks = segment_text(&content, &collection.chunking);
         let chunk_count = chunks.len();
 
-        for (ci, chunk_text) in chunks.into_iter().enumerate() {
-            conn.execute(
-                "INSERT INTO chunks (file_id, content, chunk_index) VALUES (?1, ?2, ?3)",
-                rusqlite::params![file_id, &chunk_text, ci as i64],
-            )
-            .map_err(|e| e.to_string())?;
-            let chunk_id = conn.last_insert_rowid();
-            chunks_created += 1;
-
-            // 生成 embedding
-            if model_ready {
-                let emb_opt: Option<Vec<f32>> = {
-                    let mut guard = model_lock().lock().unwrap();
-                    guard.as_mut().and_then(|m| m.encode(&chunk_text).ok())
-                };
+        // 先查 embedding 缓存；未命中的编码任务整批入队（低优先级），
+        // 多个工作线程可并行处理，期间到达的查询仍会插队
+        let cache_keys: Vec<String> = chunks
+            .iter()
+            .map(|c| embedding_cache::cache_key(&c.text, long_text.as_ref()))
+            .collect();
+        let mut cached: Vec<Option<WindowedEmbedding>> = match &engine {
+            Some(_) => cache_keys
+                .iter()
+                .map(|k| embedding_cache::load(&conn, model_id, k))
+                .collect(),
+            None => vec![],
+        };
+        let mut pending: Vec<_> = match &engine {
+            Some(engine) => chunks
+                .iter()
+                .zip(&cached)
+                .map(|(c, hit)| {
+                    hit.is_none().then(|| {
+                        engine.submit_encode(Priority::Bulk, c.text.clone(), long_text.clone())
+                    })
+                })
+                .collect(),
+            None => vec![],
+        };
+        // 稀疏 term 由稀疏模型的工作线程并行编码（未安装稀疏模型时为空）
+        let mut sparse_pending: Vec<_> = match &sparse_engine {
+            Some(sparse) => chunks
+                .iter()
+                .map(|c| Some(sparse.submit_encode(Priority::Bulk, c.text.clone(), DOC_MAX_TERMS)))
+                .collect(),
+            None => vec![],
+        };
 
-                if let Some(emb) = emb_opt {
-                    conn.execute(
-                        "INSERT INTO chunk_embeddings (chunk_id, embedding) VALUES (?1, ?2)",
-                        rusqlite::params![chunk_id, vec_to_bytes(&emb)],
-                    )
-                    .map_err(|e| e.to_string())?;
-                    embeddings_generated += 1;
-                }
-            }
+        // 编码全部完成后再写库，写入阶段不等待推理
+        let mut prepared = Vec::with_capacity(chunk_count);
+        for (ci, (segment, cache_key)) in chunks.into_iter().zip(cache_keys).enumerate() {
+            // 生成 embedding（缓存命中时直接复用）
+            let (embedding, cache_hit) = match cached.get_mut(ci).and_then(Option::take) {
+                Some(hit) => (Some(hit), true),
+                None => match pending.get_mut(ci).and_then(Option::take) {
+                    Some(job) => (batch.wait(&cache_st, job)?.ok().and_then(Result::ok), false),
+                    None => (None, false),
+                },
+            };
+            let sparse = match sparse_pending.get_mut(ci).and_then(Option::take) {
+                Some(job) => batch
+                    .wait(&cache_st, job)?
+                    .ok()
+                    .and_then(Result::ok)
+                    .unwrap_or_default(),
+                None => vec![],
+            };
+            prepared.push(PreparedChunk {
+                text: segment.text,
+                byte_start: segment.byte_start,
+                byte_end: segment.byte_end,
+                embedding,
+                cache_key,
+                cache_hit,
+                sparse,
+            });
 
             // 每处理 5 个 chunk 发一次进度（减少事件量）
             if ci % 5 == 0 || ci == chunk_count - 1 {
@@ -397,11 +1336,50 @@ async fn select_and_import_folder(
             }
         }
 
-        files_imported += 1;
-    }
-
-    // 导入完成，使向量缓存失效
-    cache_st.0.write().unwrap().invalidate();
+        // 整个文件要么全部写入，要么保持导入前的状态
+        let savepoint = batch.tx(&conn)?.savepoint().map_err(|e| e.to_string())?;
+        match write_file(
+            &savepoint,
+            model_id,
+            &record,
+            &prepared,
+            dims,
+            vector_format,
+        ) {
+            Ok(delta) => {
+                savepoint.commit().map_err(|e| e.to_string())?;
+                files_imported += 1;
+                chunks_created += chunk_count;
+                for c in prepared.iter().filter(|c| c.embedding.is_some()) {
+                    if c.cache_hit {
+                        embeddings_cached += 1;
+                    } else {
+                        embeddings_generated += 1;
+                    }
+                }
+                batch.chunks += chunk_count;
+                batch.delta.extend(delta);
+            }
+            Err(e) => {
+                drop(savepoint); // 回滚该文件的写入
+                eprintln!("[LocalLens] 导入 {} 失败，已回滚: {e}", record.path);
+                skipped += 1;
+            }
+        }
+        if batch.is_full() {
+            batch.commit(&cache_st)?;
+        }
+    }
+    batch.commit(&cache_st)?;
+    match embedding_cache::prune(&conn) {
+        Ok(0) => {}
+        Ok(n) => eprintln!("[LocalLens] embedding 缓存已淘汰 {n} 条最久未用的条目"),
+        Err(e) => eprintln!("[LocalLens] 清理 embedding 缓存失败: {e}"),
+    }
+
+    // 向量缓存已随每次提交增量更新，ANN 索引在后台补入新向量
+    spawn_ann_sync(&app, model_id, false);
+    spawn_cache_compaction(&app);
 
     app.emit(
         "import-progress",
@@ -419,167 +1397,1389 @@ async fn select_and_import_folder(
         chunks_created,
         skipped,
         embeddings_generated,
+        embeddings_cached,
     })
 }
 
-/// 语义搜索（模型可用时）或关键词搜索（模型不可用时回退）
+/// 在调用方的事务中删除满足 `condition`（参数为 `params`）的文件，chunk 等派生数据级联删除；
+/// 返回删除的文件数与待提交后应用到各模型缓存的增量
+fn delete_files_in(
+    tx: &Connection,
+    condition: &str,
+    params: &[&dyn rusqlite::ToSql],
+) -> Result<(usize, VectorChanges), String> {
+    let removed_chunks: Vec<(i64, String)> = tx
+        .prepare(&format!(
+            "SELECT c.id, k.model_id FROM chunks c JOIN files f ON c.file_id = f.id
+             JOIN collections k ON k.id = f.collection_id WHERE {condition}"
+        ))
+        .and_then(|mut stmt| {
+            stmt.query_map(params, |r| Ok((r.get(0)?, r.get(1)?)))?
+                .collect()
+        })
+        .map_err(|e| e.to_string())?;
+    let removed = tx
+        .execute(&format!("DELETE FROM files WHERE {condition}"), params)
+        .map_err(|e| e.to_string())?;
+    let mut changes = VectorChanges::new();
+    for (chunk_id, model_id) in removed_chunks {
+        changes.entry(model_id).or_default().removed.push(chunk_id);
+    }
+    Ok((removed, changes))
+}
+
+/// 在一个事务中删除满足 `condition` 的文件；向量缓存按删除的 chunk 增量更新，
+/// ANN 索引在后台同步。返回删除的文件数
+fn delete_files(
+    app: &tauri::AppHandle,
+    cache_st: &CacheState,
+    condition: &str,
+    params: &[&dyn rusqlite::ToSql],
+) -> Result<usize, String> {
+    let mut conn = open_db(app)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let (removed, changes) = delete_files_in(&tx, condition, params)?;
+    for model_id in commit_vector_changes(tx, cache_st, changes)? {
+        spawn_ann_sync(app, &model_id, false);
+    }
+    if removed > 0 {
+        spawn_cache_compaction(app);
+    }
+    Ok(removed)
+}
+
+/// 从索引中移除单个文件及其 chunk、向量等派生数据，返回是否存在该文件
+///
+/// `collection_id` 为空时从所有集合中移除该路径
 #[tauri::command]
-async fn search_text(
+async fn remove_file(
+    app: tauri::AppHandle,
+    cache_st: tauri::State<'_, CacheState>,
+    path: String,
+    collection_id: Option<i64>,
+) -> Result<bool, String> {
+    let removed = delete_files(
+        &app,
+        &cache_st,
+        "path = ?1 AND (?2 IS NULL OR collection_id = ?2)",
+        &[&path, &collection_id],
+    )?;
+    Ok(removed > 0)
+}
+
+/// 从索引中移除文件夹（含子目录）下的全部文件，返回移除的文件数
+///
+/// `collection_id` 为空时从所有集合中移除；该文件夹作为根目录的记录一并移除
+#[tauri::command]
+async fn remove_folder(
+    app: tauri::AppHandle,
+    cache_st: tauri::State<'_, CacheState>,
+    path: String,
+    collection_id: Option<i64>,
+) -> Result<usize, String> {
+    // 按路径前缀匹配，前缀带分隔符，避免 /docs 误删 /docs2
+    let mut prefix = path.trim_end_matches(['/', '\\']).to_string();
+    if prefix.is_empty() {
+        return Err("文件夹路径为空".into());
+    }
+    prefix.push(std::path::MAIN_SEPARATOR);
+    // 文件与根目录记录在同一事务中删除
+    let mut conn = open_db(&app)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let (removed, changes) = delete_files_in(
+        &tx,
+        "substr(path, 1, length(?1)) = ?1 AND (?2 IS NULL OR collection_id = ?2)",
+        &[&prefix, &collection_id],
+    )?;
+    collections::remove_root(&tx, collection_id, &path).map_err(|e| e.to_string())?;
+    for model_id in commit_vector_changes(tx, &cache_st, changes)? {
+        spawn_ann_sync(&app, &model_id, false);
+    }
+    if removed > 0 {
+        spawn_cache_compaction(&app);
+    }
+    eprintln!("[LocalLens] 已从索引移除 {removed} 个文件（{path}）");
+    Ok(removed)
+}
+
+/// 全部集合及其根目录与分段设置
+#[tauri::command]
+async fn list_collections(app: tauri::AppHandle) -> Result<Vec<Collection>, String> {
+    collections::list(&*open_db(&app)?).map_err(|e| e.to_string())
+}
+
+/// 新建集合，使用已安装的嵌入模型 `model_id`（省略时为内置模型）
+///
+/// 使用同一模型的集合共用该模型的向量文件与 ANN 索引；模型尚未加载时在后台加载
+#[tauri::command]
+async fn create_collection(
+    app: tauri::AppHandle,
+    name: String,
+    model_id: Option<String>,
+    chunking: Option<ChunkSettings>,
+) -> Result<Collection, String> {
+    let model_id = model_id.unwrap_or_else(|| MODEL_NAME.to_string());
+    models::check_installed(&resource_dir(&app), &model_id)?;
+    let conn = open_db(&app)?;
+    let collection = collections::create(&conn, &name, &model_id, &chunking.unwrap_or_default())?;
+    if app.state::<ModelStatusState>().get(&model_id) == ModelStatus::Unavailable {
+        spawn_model_loader(&app, &model_id);
+    }
+    eprintln!(
+        "[LocalLens] 已创建集合「{}」（模型 {model_id}）",
+        collection.name
+    );
+    Ok(collection)
+}
+
+#[tauri::command]
+async fn rename_collection(app: tauri::AppHandle, id: i64, name: String) -> Result<(), String> {
+    collections::rename(&*open_db(&app)?, id, &name)
+}
+
+/// 修改集合的分段设置（只影响之后导入的文件，已导入的文件需重新导入）
+#[tauri::command]
+async fn set_collection_chunking(
+    app: tauri::AppHandle,
+    id: i64,
+    chunking: ChunkSettings,
+) -> Result<(), String> {
+    collections::set_chunking(&*open_db(&app)?, id, &chunking)
+}
+
+/// 删除集合及其中的全部文件，返回删除的文件数；至少保留一个集合
+#[tauri::command]
+async fn delete_collection(
+    app: tauri::AppHandle,
+    cache_st: tauri::State<'_, CacheState>,
+    id: i64,
+) -> Result<usize, String> {
+    let mut conn = open_db(&app)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let collection = collections::get(&tx, id)?;
+    let count: i64 = tx
+        .query_row("SELECT COUNT(*) FROM collections", [], |r| r.get(0))
+        .map_err(|e| e.to_string())?;
+    if count <= 1 {
+        return Err("至少需要保留一个集合".into());
+    }
+    // 文件与集合在同一事务中删除（根目录记录级联删除），提交后再增量更新向量缓存
+    let (removed, changes) = delete_files_in(&tx, "collection_id = ?1", &[&id])?;
+    tx.execute("DELETE FROM collections WHERE id = ?1", [id])
+        .map_err(|e| e.to_string())?;
+    for model_id in commit_vector_changes(tx, &cache_st, changes)? {
+        spawn_ann_sync(&app, &model_id, false);
+    }
+    if removed > 0 {
+        spawn_cache_compaction(&app);
+    }
+    eprintln!(
+        "[LocalLens] 已删除集合「{}」及其 {removed} 个文件",
+        collection.name
+    );
+    Ok(removed)
+}
+
+/// 把集合导出为索引包（`collection_ids` 为空或省略时导出整个数据库），返回包的摘要
+#[tauri::command]
+async fn export_bundle(
+    app: tauri::AppHandle,
+    collection_ids: Option<Vec<i64>>,
+) -> Result<BundleInfo, String> {
+    let selected = app
+        .dialog()
+        .file()
+        .add_filter("LocalLens 索引包", &[BUNDLE_EXTENSION])
+        .set_file_name(format!("locallens.{BUNDLE_EXTENSION}"))
+        .blocking_save_file();
+    let dest = match selected {
+        Some(tauri_plugin_dialog::FilePath::Path(p)) => p,
+        None => return Err("cancelled".to_string()),
+        #[allow(unreachable_patterns)]
+        _ => return Err("Unsupported path type".to_string()),
+    };
+
+    let conn = open_db(&app)?;
+    let start = Instant::now();
+    let info = bundle::export(&conn, &dest, &collection_ids.unwrap_or_default())?;
+    eprintln!(
+        "[LocalLens] 已导出索引包 {}：{} 个集合，耗时 {} ms",
+        dest.display(),
+        info.collections.len(),
+        start.elapsed().as_millis()
+    );
+    Ok(info)
+}
+
+/// 选择索引包并返回其路径与摘要（模型、维度、集合及根目录），供导入前确认根目录映射
+#[tauri::command]
+async fn inspect_bundle(app: tauri::AppHandle) -> Result<serde_json::Value, String> {
+    let selected = app
+        .dialog()
+        .file()
+        .add_filter("LocalLens 索引包", &[BUNDLE_EXTENSION])
+        .blocking_pick_file();
+    let path = match selected {
+        Some(tauri_plugin_dialog::FilePath::Path(p)) => p,
+        None => return Err("cancelled".to_string()),
+        #[allow(unreachable_patterns)]
+        _ => return Err("Unsupported path type".to_string()),
+    };
+    let bundle = Bundle::open(&path)?;
+    Ok(serde_json::json!({ "path": path, "bundle": bundle.info }))
+}
+
+/// 导入索引包：校验模型与向量维度后在一个事务中合并到本机数据库
+///
+/// `root_map` 把包中记录的根目录映射到本机路径（未列出的保持原样）；
+/// `collection_id` 给出时全部文件并入该集合（须使用包的模型），否则按包中的集合新建，
+/// 新集合使用包的模型（须已安装）
+#[tauri::command]
+async fn import_bundle(
     app: tauri::AppHandle,
     model_st: tauri::State<'_, ModelStatusState>,
+    engine_st: tauri::State<'_, EngineState>,
     cache_st: tauri::State<'_, CacheState>,
+    path: String,
+    root_map: Option<HashMap<String, String>>,
+    collection_id: Option<i64>,
+) -> Result<serde_json::Value, String> {
+    let bundle = Bundle::open(std::path::Path::new(&path))?;
+    let conn = open_db(&app)?;
+    let model_id = match collection_id {
+        Some(id) => collections::get(&conn, id)?.model_id,
+        None => bundle.info.model_id.clone(),
+    };
+    // 存储维度取决于模型，模型就绪后才能校验
+    let engine = if model_st.get(&model_id) == ModelStatus::Ready {
+        engine_st.get(&model_id)
+    } else {
+        None
+    }
+    .ok_or_else(|| format!("模型 {model_id} 未就绪，无法校验索引包的向量维度"))?;
+
+    let dims = VectorDims::new(&load_model_settings(&conn, &model_id), engine.dimension());
+    let target = MergeTarget {
+        model_id: model_id.clone(),
+        dim: dims.stored,
+        full_dim: dims.full,
+        rescore: dims.rescore,
+        format: load_vector_format(&conn),
+        sparse_model: conn
+            .query_row(
+                "SELECT value FROM app_meta WHERE key = 'sparse_model'",
+                [],
+                |r| r.get(0),
+            )
+            .ok(),
+    };
+    let start = Instant::now();
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let outcome = bundle::merge(
+        &tx,
+        &bundle,
+        &root_map.unwrap_or_default(),
+        collection_id,
+        &target,
+    )?;
+    let embeddings = outcome.added.len();
+    let delta = CacheDelta {
+        added: outcome.added,
+        removed: outcome.removed,
+    };
+    commit_vector_changes(tx, &cache_st, HashMap::from([(model_id.clone(), delta)]))?;
+    spawn_ann_sync(&app, &model_id, false);
+    spawn_cache_compaction(&app);
+    eprintln!(
+        "[LocalLens] 已导入索引包 {path}：{} 个文件，{} 个 chunk，{embeddings} 条向量，耗时 {} ms",
+        outcome.files,
+        outcome.chunks,
+        start.elapsed().as_millis()
+    );
+    Ok(serde_json::json!({
+        "collections": outcome.collections,
+        "files": outcome.files,
+        "chunks": outcome.chunks,
+        "embeddings": embeddings,
+    }))
+}
+
+/// 语义搜索（模型可用时）或关键词搜索（模型不可用时回退）
+#[tauri::command]
+async fn search_text(
+    app: tauri::AppHandle,
     query: String,
+    options: Option<SearchOptions>,
 ) -> Result<Vec<SearchResult>, String> {
-    let q = query.trim().to_string();
+    run_search(&app, &query, &options.unwrap_or_default())
+}
+
+/// 按 `options.mode` 选择检索方式；search_text 与评测命令共用
+fn run_search(
+    app: &tauri::AppHandle,
+    query: &str,
+    options: &SearchOptions,
+) -> Result<Vec<SearchResult>, String> {
+    let q = query.trim();
     if q.is_empty() {
         return Ok(vec![]);
     }
+    let cache_st = app.state::<CacheState>();
+    let reranker_st = app.state::<RerankerState>();
+    let sparse_st = app.state::<SparseState>();
+
+    let scopes = semantic_scopes(app, &options.collections)?;
+    let has_sparse = sparse_st.get().is_some();
+
+    match options.mode {
+        SearchMode::Semantic if scopes.is_empty() => Err("语义模型未就绪".into()),
+        SearchMode::Semantic => semantic_search(app, &scopes, &cache_st, &reranker_st, q, options),
+        SearchMode::Sparse if !has_sparse => Err("未安装稀疏检索模型".into()),
+        SearchMode::Sparse => sparse_search(app, &sparse_st, q, &options.collections),
+        SearchMode::Keyword => keyword_search(app, q, &options.collections),
+        SearchMode::Hybrid => hybrid_search(app, &scopes, has_sparse, q, options),
+        SearchMode::Auto => {
+            if !scopes.is_empty() {
+                match semantic_search(app, &scopes, &cache_st, &reranker_st, q, options) {
+                    Ok(results) if !results.is_empty() => return Ok(results),
+                    Ok(_) => {} // 语义无结果，fall through
+                    Err(e) => eprintln!("[LocalLens] 语义搜索失败，回退: {e}"),
+                }
+            }
+            if has_sparse {
+                match sparse_search(app, &sparse_st, q, &options.collections) {
+                    Ok(results) if !results.is_empty() => return Ok(results),
+                    Ok(_) => {}
+                    Err(e) => eprintln!("[LocalLens] 稀疏检索失败，回退关键词: {e}"),
+                }
+            }
+            keyword_search(app, q, &options.collections)
+        }
+    }
+}
+
+/// 混合检索：语义检索在独立线程中与词法检索（稀疏、关键词）并行执行，再融合排序
+///
+/// 未就绪或权重为 0 的信号不参与；单路失败只记录日志，全部失败才返回错误。
+/// 重排在融合之后对合并列表整体进行。
+fn hybrid_search(
+    app: &tauri::AppHandle,
+    scopes: &[ModelScope],
+    has_sparse: bool,
+    query: &str,
+    options: &SearchOptions,
+) -> Result<Vec<SearchResult>, String> {
+    let cache_st = app.state::<CacheState>();
+    let reranker_st = app.state::<RerankerState>();
+    let sparse_st = app.state::<SparseState>();
+    let weights = &options.weights;
+    let per_signal = SearchOptions {
+        rerank: false,
+        ..options.clone()
+    };
+
+    let outcomes: Vec<(SearchMode, Result<Vec<SearchResult>, String>)> = std::thread::scope(|s| {
+        let semantic = (!scopes.is_empty() && weights.semantic > 0.0).then(|| {
+            s.spawn(|| semantic_search(app, scopes, &cache_st, &reranker_st, query, &per_signal))
+        });
+        let mut outcomes = Vec::new();
+        if has_sparse && weights.sparse > 0.0 {
+            let result = sparse_search(app, &sparse_st, query, &options.collections);
+            outcomes.push((SearchMode::Sparse, result));
+        }
+        if weights.keyword > 0.0 {
+            let result = keyword_search(app, query, &options.collections);
+            outcomes.push((SearchMode::Keyword, result));
+        }
+        if let Some(handle) = semantic {
+            let result = handle
+                .join()
+                .unwrap_or_else(|_| Err("语义检索线程异常退出".into()));
+            outcomes.insert(0, (SearchMode::Semantic, result));
+        }
+        outcomes
+    });
+
+    let mut lists = Vec::new();
+    let mut by_key: HashMap<i64, SearchResult> = HashMap::new();
+    let mut last_err = None;
+    for (signal, outcome) in outcomes {
+        let results = match outcome {
+            Ok(results) => results,
+            Err(e) => {
+                eprintln!("[LocalLens] 混合检索中 {signal:?} 检索失败: {e}");
+                last_err = Some(e);
+                continue;
+            }
+        };
+        let mut hits = Vec::with_capacity(results.len());
+        for r in results {
+            let key = r.chunk_id;
+            hits.push((key, r.score));
+            // 同一 chunk 在多路中出现时合并各路独有的字段
+            match by_key.entry(key) {
+                Entry::Vacant(slot) => {
+                    slot.insert(r);
+                }
+                Entry::Occupied(mut slot) => {
+                    let merged = slot.get_mut();
+                    merged.highlight = merged.highlight.take().or(r.highlight);
+                    merged.matched_span = merged.matched_span.take().or(r.matched_span);
+                }
+            }
+        }
+        lists.push(RankedList { signal, hits });
+    }
+    if lists.is_empty() {
+        return Err(last_err.unwrap_or_else(|| "没有可用的检索信号".into()));
+    }
 
-    if *model_st.0.lock().unwrap() == ModelStatus::Ready {
-        match semantic_search(&app, &cache_st, &q) {
-            Ok(results) if !results.is_empty() => return Ok(results),
-            Ok(_) => {} // 语义无结果，fall through 到关键词
-            Err(e) => eprintln!("[LocalLens] 语义搜索失败，回退关键词: {e}"),
+    let mut results: Vec<SearchResult> = fusion::fuse(&lists, options.fusion, weights)
+        .into_iter()
+        .filter_map(|(key, score, contributions)| {
+            let mut r = by_key.remove(&key)?;
+            r.score = score;
+            r.is_semantic = contributions
+                .iter()
+                .any(|c| c.signal == SearchMode::Semantic);
+            r.mode = SearchMode::Hybrid;
+            r.contributions = contributions;
+            Some(r)
+        })
+        .collect();
+
+    if options.rerank && reranker_st.0.lock().unwrap().is_some() {
+        results.truncate(options.rerank_top_n.max(20));
+        let budget = Duration::from_millis(options.rerank_budget_ms);
+        if let Err(e) = rerank_results(&reranker_st, query, &mut results, budget) {
+            eprintln!("[LocalLens] 重排失败，保持融合排序: {e}");
         }
     }
+    results.truncate(20);
 
-    keyword_search(&app, &q)
+    Ok(results)
 }
 
+/// 稀疏检索：查询 term 与倒排表按权重点积打分，取 `collections` 内的 Top 20（为空时不限）
+fn sparse_search(
+    app: &tauri::AppHandle,
+    sparse_st: &SparseState,
+    query: &str,
+    collections: &[i64],
+) -> Result<Vec<SearchResult>, String> {
+    let terms = sparse_st
+        .get()
+        .ok_or("未安装稀疏检索模型")?
+        .submit_encode(Priority::Query, query.to_string(), QUERY_MAX_TERMS)
+        .wait()??;
+    if terms.is_empty() {
+        return Ok(vec![]);
+    }
+
+    // 查询 term 以 VALUES 形式参与 JOIN，一条 SQL 完成合并与排序
+    let values = vec!["(?, ?)"; terms.len()].join(", ");
+    let scope = match collections::chunk_condition(collections) {
+        Some(condition) => format!("JOIN chunks c ON c.id = s.chunk_id WHERE {condition}"),
+        None => String::new(),
+    };
+    let sql = format!(
+        "WITH q(term_id, weight) AS (VALUES {values})
+         SELECT s.chunk_id, SUM(s.weight * q.weight) AS score
+         FROM chunk_sparse s JOIN q ON s.term_id = q.term_id
+         {scope}
+         GROUP BY s.chunk_id
+         ORDER BY score DESC
+         LIMIT 20"
+    );
+    let params: Vec<rusqlite::types::Value> = terms
+        .iter()
+        .flat_map(|&(term, w)| [(term as i64).into(), (w as f64).into()])
+        .collect();
+
+    let conn = open_db(app)?;
+    let hits: Vec<(i64, f32)> = conn
+        .prepare(&sql)
+        .and_then(|mut stmt| {
+            stmt.query_map(rusqlite::params_from_iter(params), |row| {
+                Ok((row.get(0)?, row.get::<_, f64>(1)? as f32))
+            })?
+            .collect()
+        })
+        .map_err(|e| e.to_string())?;
+    hydrate_results(&conn, &hits, SearchMode::Sparse)
+}
+
+/// 回填排序后的命中 (chunk_id, 分数)，保持原顺序
+fn hydrate_results(
+    conn: &Connection,
+    hits: &[(i64, f32)],
+    mode: SearchMode,
+) -> Result<Vec<SearchResult>, String> {
+    let ids: Vec<i64> = hits.iter().map(|h| h.0).collect();
+    let scores: HashMap<i64, f32> = hits.iter().copied().collect();
+    let records = hydrate::hydrate(conn, &ids).map_err(|e| e.to_string())?;
+    Ok(records
+        .into_iter()
+        .map(|record| {
+            let score = scores[&record.chunk_id];
+            SearchResult::from_record(record, score, mode)
+        })
+        .collect())
+}
+
+/// 检索范围内使用同一个嵌入模型（已就绪）的集合
+struct ModelScope {
+    model_id: String,
+    engine: Arc<InferenceEngine>,
+    collections: Vec<i64>,
+}
+
+/// 把要检索的集合（为空时为全部集合）按模型分组，模型未就绪的组不参与语义检索
+fn semantic_scopes(app: &tauri::AppHandle, collections: &[i64]) -> Result<Vec<ModelScope>, String> {
+    let groups = collections::by_model(&*open_db(app)?, collections).map_err(|e| e.to_string())?;
+    let model_st = app.state::<ModelStatusState>();
+    let engine_st = app.state::<EngineState>();
+    Ok(groups
+        .into_iter()
+        .filter(|(model_id, _)| model_st.get(model_id) == ModelStatus::Ready)
+        .filter_map(|(model_id, collections)| {
+            let engine = engine_st.get(&model_id)?;
+            Some(ModelScope {
+                model_id,
+                engine,
+                collections,
+            })
+        })
+        .collect())
+}
+
+/// 语义检索：每个模型用自己的查询向量检索自己的集合，再合并
+///
+/// 不同模型的余弦相似度不可直接比较，各模型的结果按名次交错合并
 fn semantic_search(
     app: &tauri::AppHandle,
+    scopes: &[ModelScope],
     cache_st: &CacheState,
+    reranker_st: &RerankerState,
     query: &str,
+    options: &SearchOptions,
 ) -> Result<Vec<SearchResult>, String> {
-    // 1. 生成查询向量
-    let query_emb: Vec<f32> = {
-        let mut guard = model_lock().lock().unwrap();
-        guard.as_mut().and_then(|m| m.encode(query).ok())
+    let conn = open_db(app)?;
+    // 需要重排时先多取候选
+    let rerank = options.rerank && reranker_st.0.lock().unwrap().is_some();
+    let hydrate = if rerank {
+        options.rerank_top_n.max(20)
+    } else {
+        20
+    };
+    let mut lists = Vec::with_capacity(scopes.len());
+    for scope in scopes {
+        lists.push(semantic_hits(app, &conn, scope, cache_st, query, hydrate)?);
     }
-    .ok_or("查询向量生成失败")?;
+    let mut results = interleave(lists);
+    results.truncate(hydrate);
+
+    // 交叉编码器重排后截取 Top 20
+    if rerank {
+        let budget = Duration::from_millis(options.rerank_budget_ms);
+        if let Err(e) = rerank_results(reranker_st, query, &mut results, budget) {
+            eprintln!("[LocalLens] 重排失败，保持向量排序: {e}");
+        }
+    }
+    results.truncate(20);
 
-    // 2. 确保缓存有效
-    ensure_cache_valid(app, cache_st)?;
+    Ok(results)
+}
 
-    // 3. 余弦相似度排序，取 Top 20
-    let top_ids: Vec<(i64, f32)> = {
-        let cache = cache_st.0.read().unwrap();
-        let mut scored: Vec<(i64, f32)> = cache
-            .entries
-            .iter()
-            .map(|(id, emb)| (*id, cosine_sim(&query_emb, emb)))
-            .collect();
-        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-        scored.truncate(20);
-        scored
+/// 在一个模型的集合中检索，返回按相似度排序的前 `hydrate` 个结果
+fn semantic_hits(
+    app: &tauri::AppHandle,
+    conn: &Connection,
+    scope: &ModelScope,
+    cache_st: &CacheState,
+    query: &str,
+    hydrate: usize,
+) -> Result<Vec<SearchResult>, String> {
+    // 1. 生成查询向量（高优先级，插队到批量导入任务之前）
+    let full_query = scope
+        .engine
+        .encode(Priority::Query, query)
+        .map_err(|e| format!("查询向量生成失败: {e}"))?;
+    let model_id = scope.model_id.as_str();
+    let dims = VectorDims::new(
+        &load_model_settings(conn, model_id),
+        scope.engine.dimension(),
+    );
+    let query_emb = QueryVec::new(truncate_dim(&full_query, dims.stored));
+
+    // 2. 余弦相似度排序（截断存储且开启重新打分时先多取候选）
+    let keep = if dims.rescore {
+        RESCORE_CANDIDATES.max(hydrate)
+    } else {
+        hydrate
+    };
+    // 大库走 ANN 索引；小库或索引未就绪时在内存缓存上暴力检索。
+    // 限定集合时 ANN 召回不足 keep 个（范围内向量稀少）则改用暴力检索
+    let chunks = ChunkScope::load(conn, model_id, &scope.collections).map_err(|e| e.to_string())?;
+    let mut top_ids: Vec<(i64, f32)> = match ann_search(app, model_id, &query_emb, keep, &chunks)
+        .filter(|hits| chunks.is_all() || hits.len() >= keep)
+    {
+        Some(hits) => hits,
+        None => {
+            let cache = cache_st.get(model_id);
+            ensure_cache_valid(app, model_id, &cache, dims.stored)?;
+            let top = cache.read().unwrap().top_k(&query_emb, keep, &chunks);
+            top
+        }
     };
 
     if top_ids.is_empty() {
         return Ok(vec![]);
     }
 
-    // 4. 批量查询 chunk 内容
-    let conn = open_db(app)?;
-    let mut results = Vec::with_capacity(top_ids.len());
-    for (chunk_id, score) in &top_ids {
-        if let Ok((content, file_name, file_path, chunk_index)) = conn.query_row(
-            "SELECT c.content, f.name, f.path, c.chunk_index
-             FROM chunks c JOIN files f ON c.file_id = f.id
-             WHERE c.id = ?1",
-            rusqlite::params![chunk_id],
+    if dims.rescore {
+        rescore_full(conn, &mut top_ids, &QueryVec::new(full_query), dims.full);
+        top_ids.truncate(hydrate);
+    }
+
+    // 3. 一次查询回填 chunk 内容，长段落再标出最相近的窗口
+    let mut results = hydrate_results(conn, &top_ids, SearchMode::Semantic)?;
+    attach_best_windows(conn, &mut results, &query_emb);
+    Ok(results)
+}
+
+/// 按名次交错合并多个有序列表：依次取各列表的第 1 名、第 2 名……
+fn interleave<T>(lists: Vec<Vec<T>>) -> Vec<T> {
+    let mut lists: Vec<_> = lists.into_iter().map(Vec::into_iter).collect();
+    let mut merged = Vec::new();
+    loop {
+        let before = merged.len();
+        for list in lists.iter_mut() {
+            merged.extend(list.next());
+        }
+        if merged.len() == before {
+            return merged;
+        }
+    }
+}
+
+/// 按向量排序依次重排打分，已打分的按相关度排在前面，
+/// 超出时间预算未打分的保持原顺序排在其后
+///
+/// 预算从进入本函数时算起，等待模型锁的时间也计入；每打分一对释放一次锁，
+/// 并发的搜索交替使用重排模型，不会有一个搜索独占整个预算
+fn rerank_results(
+    reranker_st: &RerankerState,
+    query: &str,
+    results: &mut Vec<SearchResult>,
+    budget: Duration,
+) -> Result<(), String> {
+    let deadline = Instant::now() + budget;
+    let mut scores = Vec::with_capacity(results.len());
+    for r in results.iter() {
+        if Instant::now() >= deadline {
+            break;
+        }
+        let mut guard = reranker_st.0.lock().unwrap();
+        let Some(reranker) = guard.as_mut() else {
+            break;
+        };
+        scores.push(reranker.score_pair(query, &r.content)?);
+    }
+    if scores.len() < results.len() {
+        eprintln!(
+            "[LocalLens] 重排超出 {} ms 预算，仅重排前 {}/{} 条",
+            budget.as_millis(),
+            scores.len(),
+            results.len()
+        );
+    }
+
+    let mut scored: Vec<SearchResult> = results.drain(..scores.len()).collect();
+    for (r, s) in scored.iter_mut().zip(scores) {
+        r.rerank_score = Some(s);
+    }
+    scored.sort_by(|a, b| {
+        b.rerank_score
+            .partial_cmp(&a.rerank_score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    scored.append(results);
+    *results = scored;
+    Ok(())
+}
+
+/// 用完整维度向量为候选重新打分并排序（缺少完整向量的候选保留截断分数）
+fn rescore_full(conn: &Connection, candidates: &mut [(i64, f32)], query: &QueryVec, dim: usize) {
+    let sql = format!(
+        "SELECT chunk_id, embedding FROM chunk_embeddings_full WHERE chunk_id IN ({})",
+        vec!["?"; candidates.len()].join(", ")
+    );
+    let full: HashMap<i64, QuantVec> = conn
+        .prepare(&sql)
+        .and_then(|mut stmt| {
+            stmt.query_map(
+                rusqlite::params_from_iter(candidates.iter().map(|c| c.0)),
+                |r| Ok((r.get::<_, i64>(0)?, r.get::<_, Vec<u8>>(1)?)),
+            )?
+            .collect::<SqlResult<Vec<_>>>()
+        })
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(id, b)| Some((id, QuantVec::from_bytes(&b, Some(dim)).ok()?)))
+        .collect();
+    for candidate in candidates.iter_mut() {
+        if let Some(v) = full.get(&candidate.0) {
+            candidate.1 = cosine_sim(query, v);
+        }
+    }
+    candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+}
+
+/// 在各结果 chunk 的滑动窗口中找出与查询最相近的一段，写入 matched_span
+/// （一次查询取回全部窗口；无窗口记录的 chunk 不变）
+fn attach_best_windows(conn: &Connection, results: &mut [SearchResult], query_emb: &QueryVec) {
+    if results.is_empty() {
+        return;
+    }
+    let sql = format!(
+        "SELECT chunk_id, byte_start, byte_end, embedding FROM chunk_windows
+         WHERE chunk_id IN ({})",
+        vec!["?"; results.len()].join(", ")
+    );
+    let windows = conn.prepare(&sql).and_then(|mut stmt| {
+        stmt.query_map(
+            rusqlite::params_from_iter(results.iter().map(|r| r.chunk_id)),
             |row| {
                 Ok((
-                    row.get::<_, String>(0)?,
-                    row.get::<_, String>(1)?,
-                    row.get::<_, String>(2)?,
-                    row.get::<_, i64>(3)?,
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)? as usize,
+                    row.get::<_, i64>(2)? as usize,
+                    row.get::<_, Vec<u8>>(3)?,
                 ))
             },
-        ) {
-            results.push(SearchResult {
-                content,
-                file_name,
-                file_path,
-                chunk_index,
-                score: *score,
-                is_semantic: true,
+        )?
+        .collect::<SqlResult<Vec<_>>>()
+    });
+    let Ok(windows) = windows else {
+        return;
+    };
+
+    let mut best: HashMap<i64, (usize, usize, f32)> = HashMap::new();
+    for (chunk_id, start, end, blob) in windows {
+        let Ok(v) = QuantVec::from_bytes(&blob, Some(query_emb.dim())) else {
+            continue;
+        };
+        let score = cosine_sim(query_emb, &v);
+        match best.entry(chunk_id) {
+            Entry::Occupied(mut slot) if slot.get().2 < score => {
+                slot.insert((start, end, score));
+            }
+            Entry::Occupied(_) => {}
+            Entry::Vacant(slot) => {
+                slot.insert((start, end, score));
+            }
+        }
+    }
+    for r in results.iter_mut() {
+        if let Some(&(byte_start, byte_end, score)) = best.get(&r.chunk_id) {
+            r.matched_span = r.content.get(byte_start..byte_end).map(|text| MatchedSpan {
+                byte_start,
+                byte_end,
+                text: text.to_string(),
+                score,
             });
         }
     }
+}
 
-    Ok(results)
+/// 模型的 ANN 索引已就绪、维度与查询一致且绑定在该模型当前的向量文件上时返回 `scope` 内的
+/// 近似 Top-k，否则返回 None
+///
+/// 限定范围时候选宽度加大到 4 倍，以补偿被过滤掉的节点
+fn ann_search(
+    app: &tauri::AppHandle,
+    model_id: &str,
+    query: &QueryVec,
+    k: usize,
+    scope: &ChunkScope,
+) -> Option<Vec<(i64, f32)>> {
+    let cache = app.state::<CacheState>().get(model_id);
+    let cache = cache.read().unwrap();
+    let store = cache.store()?;
+    let ann = app.state::<AnnState>();
+    let guard = ann.index.read().unwrap();
+    let index = guard
+        .get(model_id)
+        .filter(|i| i.dim() == query.dim() && i.is_bound(store))?;
+    let ef = k.max(ANN_EF_SEARCH);
+    Some(if scope.is_all() {
+        index.search(store, query, k, ef)
+    } else {
+        index.search_filtered(store, query, k, ef * 4, |id| scope.allows(id))
+    })
 }
 
-/// 确保内存向量缓存与数据库同步
-fn ensure_cache_valid(app: &tauri::AppHandle, cache_st: &CacheState) -> Result<(), String> {
-    // fast path：读锁检查
-    {
-        let cache = cache_st.0.read().unwrap();
-        if cache.valid {
+/// 在后台线程把模型的 ANN 索引与数据库同步；`rebuild` 为 true 时丢弃已有索引重新构建
+///
+/// 导入、删除、向量设置变化及模型加载完成后调用
+fn spawn_ann_sync(app: &tauri::AppHandle, model_id: &str, rebuild: bool) {
+    let app = app.clone();
+    let model_id = model_id.to_string();
+    std::thread::spawn(move || {
+        if let Err(e) = sync_ann_index(&app, &model_id, rebuild) {
+            eprintln!("[LocalLens] {model_id} 的 ANN 索引同步失败，检索使用暴力路径: {e}");
+        }
+    });
+}
+
+fn sync_ann_index(app: &tauri::AppHandle, model_id: &str, rebuild: bool) -> Result<(), String> {
+    let ann = app.state::<AnnState>();
+    let _guard = ann.sync.lock().unwrap();
+    // 存储维度取决于模型，模型未加载时等加载完成后再同步
+    let Some(engine) = app.state::<EngineState>().get(model_id) else {
+        return Ok(());
+    };
+    let conn = open_db(app)?;
+    let dim = VectorDims::new(&load_model_settings(&conn, model_id), engine.dimension()).stored;
+    let format = load_vector_format(&conn);
+    let path = ann_path(app, model_id);
+
+    let live: HashSet<i64> = conn
+        .prepare(&format!(
+            "SELECT e.chunk_id FROM chunk_embeddings e {}",
+            collections::model_join(1)
+        ))
+        .and_then(|mut stmt| stmt.query_map([model_id], |r| r.get(0))?.collect())
+        .map_err(|e| e.to_string())?;
+    if live.len() < ANN_MIN_VECTORS {
+        if ann.index.write().unwrap().remove(model_id).is_some() || path.exists() {
+            std::fs::remove_file(&path).ok();
+            eprintln!(
+                "[LocalLens] {model_id} 的向量数 {} 低于 {ANN_MIN_VECTORS}，改用暴力检索",
+                live.len()
+            );
+        }
+        return Ok(());
+    }
+
+    // 索引直接读取向量缓存映射的向量文件，先确保缓存可用
+    let cache = app.state::<CacheState>().get(model_id);
+    ensure_cache_valid(app, model_id, &cache, dim)?;
+    let store = cache
+        .read()
+        .unwrap()
+        .store()
+        .cloned()
+        .ok_or("向量缓存不可用")?;
+    let fetch = |id: i64| load_vector(&conn, id, dim);
+
+    // 墓碑超过三分之一或向量设置变化时需要重建
+    let usable = |i: &Hnsw| i.dim() == dim && i.format() == format && i.deleted() * 2 <= i.len();
+
+    // 内存中的索引可用且新增不多：持写锁原地增量更新（向量文件已替换时先重新绑定）
+    let pending = {
+        let guard = ann.index.read().unwrap();
+        match guard.get(model_id).filter(|i| !rebuild && usable(i)) {
+            Some(index) => Some(count_new_vectors(&conn, model_id, index.max_chunk_id())?),
+            None => None,
+        }
+    };
+    if pending.is_some_and(|n| n <= ANN_INPLACE_MAX) {
+        let updated = {
+            let mut guard = ann.index.write().unwrap();
+            let index = guard.get_mut(model_id).ok_or("ANN 索引已被移除")?;
+            let bound = if index.is_bound(&store) {
+                Ok(())
+            } else {
+                index.rebind(&store, fetch)
+            };
+            match bound {
+                Ok(()) => Some(apply_ann_delta(&conn, model_id, &store, index, &live)?),
+                Err(e) => {
+                    eprintln!("[LocalLens] ANN 索引无法绑定新的向量文件（{e}），重新构建");
+                    guard.remove(model_id);
+                    None
+                }
+            }
+        };
+        if let Some((added, removed)) = updated {
+            if added + removed > 0 {
+                ann.index
+                    .read()
+                    .unwrap()
+                    .get(model_id)
+                    .map_or(Ok(()), |i| i.save(&path))?;
+                eprintln!(
+                    "[LocalLens] {model_id} 的 ANN 索引增量更新：新增 {added}，删除 {removed}"
+                );
+            }
             return Ok(());
         }
     }
-    // slow path：写锁重建
-    let conn = open_db(app)?;
+
+    // 否则在锁外准备新索引（优先用磁盘上的索引补齐增量），完成后替换
+    let start = Instant::now();
+    let loaded = if rebuild {
+        None
+    } else {
+        Hnsw::load(&path, &store, fetch)
+            .inspect_err(|e| {
+                if path.exists() {
+                    eprintln!("[LocalLens] {e}，重新构建");
+                }
+            })
+            .ok()
+    };
+    let mut index = loaded
+        .filter(|i| usable(i))
+        .unwrap_or_else(|| Hnsw::new(&store));
+    let (added, removed) = apply_ann_delta(&conn, model_id, &store, &mut index, &live)?;
+    index.save(&path)?;
+    eprintln!(
+        "[LocalLens] {model_id} 的 ANN 索引就绪：{} 个向量（新增 {added}，删除 {removed}），耗时 {} ms",
+        index.len(),
+        start.elapsed().as_millis()
+    );
+    ann.index
+        .write()
+        .unwrap()
+        .insert(model_id.to_string(), index);
+    Ok(())
+}
+
+/// 按 chunk_id 读取一条维度相符的向量
+fn load_vector(conn: &Connection, chunk_id: i64, dim: usize) -> Option<QuantVec> {
+    conn.query_row(
+        "SELECT embedding FROM chunk_embeddings WHERE chunk_id = ?1",
+        [chunk_id],
+        |r| r.get::<_, Vec<u8>>(0),
+    )
+    .ok()
+    .and_then(|b| QuantVec::from_bytes(&b, Some(dim)).ok())
+}
+
+fn count_new_vectors(conn: &Connection, model_id: &str, after: i64) -> Result<usize, String> {
+    conn.query_row(
+        &format!(
+            "SELECT COUNT(*) FROM chunk_embeddings e {} WHERE e.chunk_id > ?1",
+            collections::model_join(2)
+        ),
+        rusqlite::params![after, model_id],
+        |r| r.get::<_, i64>(0),
+    )
+    .map(|n| n as usize)
+    .map_err(|e| e.to_string())
+}
+
+/// 删除数据库中已不存在的节点，插入该模型中 id 大于索引已有最大 id 的向量，返回（新增, 删除）数
+///
+/// chunks 使用 AUTOINCREMENT，重新导入的文件总是得到更大的 id；
+/// 维度或格式与索引不符的向量被跳过（暴力检索同样会跳过它们）
+fn apply_ann_delta(
+    conn: &Connection,
+    model_id: &str,
+    store: &Arc<VectorStore>,
+    index: &mut Hnsw,
+    live: &HashSet<i64>,
+) -> Result<(usize, usize), String> {
+    let stale: Vec<i64> = index.chunk_ids().filter(|id| !live.contains(id)).collect();
+    for id in &stale {
+        index.remove(*id);
+    }
+
     let mut stmt = conn
-        .prepare("SELECT chunk_id, embedding FROM chunk_embeddings")
+        .prepare(&format!(
+            "SELECT e.chunk_id, e.embedding FROM chunk_embeddings e {} WHERE e.chunk_id > ?1",
+            collections::model_join(2)
+        ))
         .map_err(|e| e.to_string())?;
-
-    let entries: Vec<(i64, Vec<f32>)> = stmt
-        .query_map([], |row| {
-            let id: i64 = row.get(0)?;
-            let blob: Vec<u8> = row.get(1)?;
-            Ok((id, blob))
+    let rows = stmt
+        .query_map(rusqlite::params![index.max_chunk_id(), model_id], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, Vec<u8>>(1)?))
         })
-        .map_err(|e| e.to_string())?
-        .filter_map(|r| r.ok())
-        .map(|(id, blob)| (id, bytes_to_vec(&blob)))
+        .map_err(|e| e.to_string())?;
+    let mut added = 0;
+    for (id, blob) in rows.filter_map(|r| r.ok()) {
+        let inserted = QuantVec::from_bytes(&blob, Some(index.dim()))
+            .and_then(|v| index.insert(store, id, &v))
+            .is_ok();
+        added += inserted as usize;
+    }
+    Ok((added, stale.len()))
+}
+
+/// 确保模型的向量缓存可用：失效、维度变化或增量过多时把数据库中该模型的向量重写为向量文件，
+/// 维度或格式不符的向量被跳过
+fn ensure_cache_valid(
+    app: &tauri::AppHandle,
+    model_id: &str,
+    cache: &RwLock<VectorCache>,
+    dim: usize,
+) -> Result<(), String> {
+    // fast path：读锁检查
+    if cache.read().unwrap().is_current(dim) {
+        return Ok(());
+    }
+    // slow path：持写锁重建，并发的搜索等待同一次重建
+    let mut cache = cache.write().unwrap();
+    if cache.is_current(dim) {
+        return Ok(());
+    }
+    let conn = open_db(app)?;
+    let path = vectors_path(app, model_id);
+    let tmp = path.with_extension("vectors.tmp");
+    let start = Instant::now();
+    // 代数与向量在同一个读事务中读取，之后提交的修改再以增量应用
+    let snapshot = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let generation = load_vector_generation(&snapshot, model_id);
+    let format = load_vector_format(&snapshot);
+    let skipped = VectorStore::write(&snapshot, &tmp, model_id, dim, format, generation)?;
+    drop(snapshot);
+    if skipped > 0 {
+        eprintln!("[LocalLens] {skipped} 条向量维度不是 {dim} 或格式不符，已跳过（需重新导入）");
+    }
+
+    // 先解除旧映射再替换（Windows 不允许替换仍被映射的文件）
+    cache.invalidate();
+    std::fs::rename(&tmp, &path).map_err(|e| format!("替换向量文件失败: {e}"))?;
+    let store = VectorStore::open(&path)?;
+    eprintln!(
+        "[LocalLens] {model_id} 的向量文件已重建：{} 条，耗时 {} ms",
+        store.len(),
+        start.elapsed().as_millis()
+    );
+    cache.replace(store);
+    drop(cache);
+    rebind_ann_index(app, model_id);
+    Ok(())
+}
+
+/// 向量文件替换后，模型已有的 ANN 索引在后台绑定到新文件（此前检索走暴力路径）
+fn rebind_ann_index(app: &tauri::AppHandle, model_id: &str) {
+    if app
+        .state::<AnnState>()
+        .index
+        .read()
+        .unwrap()
+        .contains_key(model_id)
+    {
+        spawn_ann_sync(app, model_id, false);
+    }
+}
+
+/// 串行化向量缓存的合并（后台合并与退出时的合并）
+static CACHE_COMPACTION: Mutex<()> = Mutex::new(());
+
+/// 增量较多时在后台把它们合并进各模型的向量文件
+///
+/// 导入、删除与导入索引包提交后调用；已有合并在进行时跳过
+fn spawn_cache_compaction(app: &tauri::AppHandle) {
+    let due: Vec<_> = app
+        .state::<CacheState>()
+        .all()
+        .into_iter()
+        .filter(|(_, cache)| cache.read().unwrap().needs_compaction())
         .collect();
+    if due.is_empty() {
+        return;
+    }
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let Ok(_guard) = CACHE_COMPACTION.try_lock() else {
+            return;
+        };
+        for (model_id, cache) in due {
+            if let Err(e) = compact_vector_cache(&app, &model_id, &cache) {
+                eprintln!("[LocalLens] {model_id} 的向量文件合并失败，增量保留在内存中: {e}");
+            }
+        }
+    });
+}
 
-    let mut cache = cache_st.0.write().unwrap();
-    cache.entries = entries;
-    cache.valid = true;
+/// 把模型向量缓存的增量合并进新的向量文件，下次启动可直接映射
+///
+/// 写文件时只持有共享的文件映射，不阻塞检索与增量；
+/// 写完后缓存已变化（又有提交或被整体重建）时放弃本次结果，留给下一次合并
+fn compact_vector_cache(
+    app: &tauri::AppHandle,
+    model_id: &str,
+    cache: &RwLock<VectorCache>,
+) -> Result<(), String> {
+    let Some(compaction) = cache.read().unwrap().compaction() else {
+        return Ok(());
+    };
+    // 最大 chunk_id 须与快照同一代数；数据库已推进（增量尚未应用）时下次再合并
+    let conn = open_db(app)?;
+    let snapshot = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    if load_vector_generation(&snapshot, model_id) != compaction.generation() {
+        return Ok(());
+    }
+    let last_id = vector_store::max_chunk_id(&snapshot, model_id)?;
+    drop(snapshot);
+
+    let path = vectors_path(app, model_id);
+    let tmp = path.with_extension("vectors.compact");
+    let start = Instant::now();
+    compaction.write(&tmp, last_id)?;
+
+    let mut cache = cache.write().unwrap();
+    if !cache.unchanged_since(&compaction) {
+        std::fs::remove_file(&tmp).ok();
+        return Ok(());
+    }
+    // 先解除旧映射再替换（Windows 不允许替换仍被映射的文件）
+    drop(compaction);
+    cache.invalidate();
+    std::fs::rename(&tmp, &path).map_err(|e| format!("替换向量文件失败: {e}"))?;
+    let store = VectorStore::open(&path)?;
+    eprintln!(
+        "[LocalLens] {model_id} 的向量增量已合并进文件：{} 条，耗时 {} ms",
+        store.len(),
+        start.elapsed().as_millis()
+    );
+    cache.replace(store);
+    drop(cache);
+    rebind_ann_index(app, model_id);
     Ok(())
 }
 
-/// 关键词回退搜索（LIKE 查询）
-fn keyword_search(app: &tauri::AppHandle, query: &str) -> Result<Vec<SearchResult>, String> {
+/// 启动时映射各模型上次写入的向量文件，与数据库核对一致后直接使用，不一致时留到首次搜索重建
+///
+/// 增量在后台或退出时合并进文件；上次运行异常退出、尚未合并时文件的代数落后于数据库，需要重建
+fn load_vector_store(app: &tauri::AppHandle) {
+    // 按模型拆分之前所有集合共用的向量文件与索引文件，不再使用
+    for legacy in [
+        db_path(app).with_extension("vectors"),
+        db_path(app).with_extension("hnsw"),
+    ] {
+        if std::fs::remove_file(&legacy).is_ok() {
+            eprintln!("[LocalLens] 已删除旧版共用的 {}", legacy.display());
+        }
+    }
+    let models = match open_db(app)
+        .and_then(|conn| collections::models_in_use(&conn).map_err(|e| e.to_string()))
+    {
+        Ok(models) => models,
+        Err(e) => {
+            eprintln!("[LocalLens] 读取集合的模型失败，首次搜索时重建向量文件: {e}");
+            return;
+        }
+    };
+    for model_id in models {
+        let path = vectors_path(app, &model_id);
+        if !path.exists() {
+            continue;
+        }
+        let start = Instant::now();
+        let store = VectorStore::open(&path).and_then(|store| {
+            let conn = open_db(app)?;
+            store.validate(
+                &conn,
+                &model_id,
+                load_vector_format(&conn),
+                load_vector_generation(&conn, &model_id),
+            )?;
+            Ok(store)
+        });
+        match store {
+            Ok(store) => {
+                eprintln!(
+                    "[LocalLens] 已映射 {model_id} 的向量文件：{} 条，耗时 {} ms",
+                    store.len(),
+                    start.elapsed().as_millis()
+                );
+                let cache = app.state::<CacheState>().get(&model_id);
+                cache.write().unwrap().replace(store);
+            }
+            Err(e) => eprintln!("[LocalLens] {model_id} 的向量文件已过期，首次搜索时重建: {e}"),
+        }
+    }
+}
+
+/// 关键词检索（FTS5 全文索引，BM25 排序），只返回 `collections` 内的 chunk（为空时不限）
+fn keyword_search(
+    app: &tauri::AppHandle,
+    query: &str,
+    collections: &[i64],
+) -> Result<Vec<SearchResult>, String> {
     let conn = open_db(app)?;
-    let like = format!("%{}%", query.trim());
-    let mut stmt = conn
-        .prepare(
-            "SELECT c.content, f.name, f.path, c.chunk_index
-             FROM chunks c JOIN files f ON c.file_id = f.id
-             WHERE c.content LIKE ?1
+    let (phrases, short_terms) = fts_query(query);
+    // 不足 3 个字符的片段 trigram 无法索引，在候选行上用 LIKE 过滤
+    let mut conditions: Vec<String> = (0..short_terms.len())
+        .map(|i| format!("c.content LIKE ?{}", i + 4))
+        .collect();
+    conditions.extend(collections::chunk_condition(collections));
+    let mut params = vec![
+        phrases.clone().unwrap_or_default(),
+        HIGHLIGHT_START.to_string(),
+        HIGHLIGHT_END.to_string(),
+    ];
+    params.extend(short_terms.iter().map(|t| format!("%{t}%")));
+
+    let sql = if phrases.is_some() {
+        conditions.insert(0, "chunks_fts MATCH ?1".into());
+        format!(
+            "SELECT c.id,
+                    -bm25(chunks_fts),
+                    snippet(chunks_fts, 0, ?2, ?3, '…', {SNIPPET_TOKENS}),
+                    highlight(chunks_fts, 0, ?2, ?3)
+             FROM chunks_fts
+             JOIN chunks c ON c.id = chunks_fts.rowid
+             WHERE {}
+             ORDER BY bm25(chunks_fts)
+             LIMIT 30",
+            conditions.join(" AND ")
+        )
+    } else {
+        // 所有片段都太短：退回全表 LIKE，没有 BM25 分数与高亮
+        format!(
+            "SELECT c.id, 0.0, NULL, NULL
+             FROM chunks c
+             WHERE {}
              ORDER BY length(c.content) ASC
              LIMIT 30",
+            conditions.join(" AND ")
         )
+    };
+    let rows: Vec<(i64, f32, Option<TextHighlight>)> = conn
+        .prepare(&sql)
+        .and_then(|mut stmt| {
+            stmt.query_map(rusqlite::params_from_iter(&params), |row| {
+                let snippet: Option<String> = row.get(2)?;
+                let highlighted: Option<String> = row.get(3)?;
+                Ok((
+                    row.get(0)?,
+                    row.get::<_, f64>(1)? as f32,
+                    snippet
+                        .zip(highlighted)
+                        .map(|(snippet, content)| TextHighlight { snippet, content }),
+                ))
+            })?
+            .collect()
+        })
         .map_err(|e| e.to_string())?;
 
-    let results: Vec<SearchResult> = stmt
-        .query_map(rusqlite::params![like], |row| {
-            Ok(SearchResult {
-                content: row.get(0)?,
-                file_name: row.get(1)?,
-                file_path: row.get(2)?,
-                chunk_index: row.get(3)?,
-                score: 0.0,
-                is_semantic: false,
-            })
-        })
-        .map_err(|e| e.to_string())?
-        .filter_map(|r| r.ok())
+    let hits: Vec<(i64, f32)> = rows.iter().map(|(id, score, _)| (*id, *score)).collect();
+    let mut highlights: HashMap<i64, TextHighlight> = rows
+        .into_iter()
+        .filter_map(|(id, _, h)| Some((id, h?)))
         .collect();
-
+    let mut results = hydrate_results(&conn, &hits, SearchMode::Keyword)?;
+    for r in &mut results {
+        r.highlight = highlights.remove(&r.chunk_id);
+    }
     Ok(results)
 }
 
+/// 把用户输入转成 FTS5 查询：按空白切分，每段作为一个短语（双引号内的引号加倍），
+/// 各短语之间为 AND
+///
+/// 返回 (FTS5 查询, 不足 3 个字符的片段)；所有片段都不足 3 个字符时查询为 None
+fn fts_query(query: &str) -> (Option<String>, Vec<String>) {
+    let (long, short): (Vec<&str>, Vec<&str>) = query
+        .split_whitespace()
+        .partition(|t| t.chars().count() >= 3);
+    let phrases = (!long.is_empty()).then(|| {
+        long.iter()
+            .map(|t| format!("\"{}\"", t.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ")
+    });
+    (phrases, short.into_iter().map(str::to_string).collect())
+}
+
+/// 用带标注的查询集（JSONL）评测当前索引的检索质量与性能
+///
+/// 每行 `{"query": "...", "expected": ["文件名或相对路径", ...]}`；
+/// 另从索引中抽取 `encode_sample` 个 chunk 测量批量编码吞吐
+#[tauri::command]
+async fn evaluate_retrieval(
+    app: tauri::AppHandle,
+    queries_path: String,
+    k: Option<usize>,
+    options: Option<SearchOptions>,
+    encode_sample: Option<usize>,
+) -> Result<EvalReport, String> {
+    let queries = eval::load_queries(std::path::Path::new(&queries_path))?;
+    if queries.is_empty() {
+        return Err("查询集为空".into());
+    }
+    let k = k.unwrap_or(10).max(1);
+    let options = options.unwrap_or_default();
+
+    let mut per_query = Vec::with_capacity(queries.len());
+    for (i, q) in queries.iter().enumerate() {
+        let start = Instant::now();
+        let results = run_search(&app, &q.query, &options)?;
+        let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+        let paths: Vec<String> = results.into_iter().map(|r| r.file_path).collect();
+        per_query.push(eval::score_query(q, &paths, k, latency_ms));
+        app.emit(
+            "eval-progress",
+            serde_json::json!({ "current": i + 1, "total": queries.len() }),
+        )
+        .ok();
+    }
+
+    // 评测范围内集合使用的模型（多个时以顿号分隔）
+    let conn = open_db(&app)?;
+    let model = collections::by_model(&conn, &options.collections)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|(model_id, _)| model_id)
+        .collect::<Vec<_>>()
+        .join("、");
+
+    // 编码吞吐：用第一个已就绪的模型编码它的集合中的 chunk，
+    // 整批以 Bulk 优先级入队，与导入时的调度方式一致
+    let sample = encode_sample.unwrap_or(200);
+    let scopes = semantic_scopes(&app, &options.collections)?;
+    let (throughput, sampled) = match scopes.first() {
+        Some(scope) if sample > 0 => {
+            let condition =
+                collections::chunk_condition(&scope.collections).unwrap_or_else(|| "1".into());
+            let engine = &scope.engine;
+            let texts: Vec<String> = conn
+                .prepare(&format!(
+                    "SELECT c.content FROM chunks c WHERE {condition} ORDER BY c.id LIMIT ?1"
+                ))
+                .and_then(|mut stmt| {
+                    stmt.query_map(rusqlite::params![sample as i64], |r| r.get(0))?
+                        .collect()
+                })
+                .map_err(|e| e.to_string())?;
+            let start = Instant::now();
+            let pending: Vec<_> = texts
+                .iter()
+                .map(|t| engine.submit_encode(Priority::Bulk, t.clone(), None))
+                .collect();
+            for job in pending {
+                job.wait()??;
+            }
+            let secs = start.elapsed().as_secs_f64();
+            let rate = (!texts.is_empty() && secs > 0.0).then(|| texts.len() as f64 / secs);
+            (rate, texts.len())
+        }
+        _ => (None, 0),
+    };
+
+    let report = eval::summarize(&model, k, per_query, throughput, sampled);
+    eprintln!(
+        "[LocalLens] 评测 {} 条查询: recall@{k}={:.3} MRR={:.3} nDCG@{k}={:.3} p50={:.1}ms p95={:.1}ms",
+        report.queries,
+        report.recall_at_k,
+        report.mrr,
+        report.ndcg_at_k,
+        report.latency_p50_ms,
+        report.latency_p95_ms
+    );
+    Ok(report)
+}
+
 /// 统计信息
 #[tauri::command]
 async fn get_stats(app: tauri::AppHandle) -> Result<serde_json::Value, String> {
-    if !db_path(&app).exists() {
-        return Ok(serde_json::json!({ "files": 0, "chunks": 0, "embeddings": 0 }));
-    }
     let conn = open_db(&app)?;
     let files: i64 = conn
         .query_row("SELECT COUNT(*) FROM files", [], |r| r.get(0))
@@ -590,71 +2790,387 @@ async fn get_stats(app: tauri::AppHandle) -> Result<serde_json::Value, String> {
     let embeddings: i64 = conn
         .query_row("SELECT COUNT(*) FROM chunk_embeddings", [], |r| r.get(0))
         .unwrap_or(0);
-    Ok(serde_json::json!({ "files": files, "chunks": chunks, "embeddings": embeddings }))
+    let sparse_chunks: i64 = conn
+        .query_row(
+            "SELECT COUNT(DISTINCT chunk_id) FROM chunk_sparse",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap_or(0);
+
+    // 各模型的 ANN 索引状态（未构建的模型不列出，检索使用暴力路径）
+    let ann_index: serde_json::Map<String, serde_json::Value> = app
+        .state::<AnnState>()
+        .index
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(model_id, i)| {
+            (
+                model_id.clone(),
+                serde_json::json!({ "vectors": i.len(), "deleted": i.deleted() }),
+            )
+        })
+        .collect();
+
+    // 按格式分组：文件数、chunk 数、总字节数
+    let mut by_format = serde_json::Map::new();
+    let mut stmt = conn
+        .prepare(
+            "SELECT format, COUNT(*), SUM(chunk_count), SUM(size_bytes)
+             FROM files GROUP BY format ORDER BY format",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                r.get::<_, i64>(1)?,
+                r.get::<_, Option<i64>>(2)?,
+                r.get::<_, Option<i64>>(3)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    for (format, n, chunk_count, bytes) in rows.filter_map(|r| r.ok()) {
+        by_format.insert(
+            format,
+            serde_json::json!({
+                "files": n,
+                "chunks": chunk_count.unwrap_or(0),
+                "size_bytes": bytes.unwrap_or(0),
+            }),
+        );
+    }
+
+    // 按集合分组：文件数、chunk 数、向量数、总字节数，以及模型与根目录
+    let counts: HashMap<i64, (i64, i64, i64, i64)> = conn
+        .prepare(
+            "SELECT f.collection_id, COUNT(*), SUM(f.chunk_count), SUM(f.size_bytes),
+                    SUM((SELECT COUNT(*) FROM chunks c
+                         JOIN chunk_embeddings e ON e.chunk_id = c.id
+                         WHERE c.file_id = f.id))
+             FROM files f GROUP BY f.collection_id",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map([], |r| {
+                Ok((
+                    r.get(0)?,
+                    (
+                        r.get(1)?,
+                        r.get::<_, Option<i64>>(2)?.unwrap_or(0),
+                        r.get::<_, Option<i64>>(3)?.unwrap_or(0),
+                        r.get::<_, Option<i64>>(4)?.unwrap_or(0),
+                    ),
+                ))
+            })?
+            .collect()
+        })
+        .map_err(|e| e.to_string())?;
+    let by_collection: Vec<serde_json::Value> = collections::list(&conn)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|c| {
+            let (n, chunk_count, bytes, vectors) = counts.get(&c.id).copied().unwrap_or_default();
+            serde_json::json!({
+                "id": c.id,
+                "name": c.name,
+                "model": c.model_id,
+                "roots": c.roots,
+                "files": n,
+                "chunks": chunk_count,
+                "embeddings": vectors,
+                "size_bytes": bytes,
+            })
+        })
+        .collect();
+
+    Ok(serde_json::json!({
+        "files": files,
+        "chunks": chunks,
+        "embeddings": embeddings,
+        "sparse_chunks": sparse_chunks,
+        "by_format": by_format,
+        "collections": by_collection,
+        "vector_format": load_vector_format(&conn).as_str(),
+        "ann_index": ann_index,
+    }))
+}
+
+// ── 模型加载 ──────────────────────────────────────────────────────────────────
+
+/// 更新模型状态；前端只关心内置模型，仅内置模型的状态变化才广播
+fn publish_status(handle: &tauri::AppHandle, model_id: &str, status: ModelStatus) {
+    if model_id == MODEL_NAME {
+        handle.emit("model-status", status.as_str()).ok();
+    }
+    handle
+        .state::<ModelStatusState>()
+        .0
+        .lock()
+        .unwrap()
+        .insert(model_id.to_string(), status);
+}
+
+/// 加载内置模型与各集合使用的附加模型
+fn spawn_model_loaders(handle: &tauri::AppHandle) {
+    let mut models = vec![MODEL_NAME.to_string()];
+    if let Ok(conn) = open_db(handle) {
+        models.extend(collections::models_in_use(&conn).unwrap_or_default());
+    }
+    models.sort();
+    models.dedup();
+    for model_id in models {
+        spawn_model_loader(handle, &model_id);
+    }
+}
+
+/// 在后台线程（重新）加载模型，完成后替换该模型的实例并更新状态
+fn spawn_model_loader(handle: &tauri::AppHandle, model_id: &str) {
+    publish_status(handle, model_id, ModelStatus::Loading);
+    let handle = handle.clone();
+    let model_id = model_id.to_string();
+
+    std::thread::spawn(move || {
+        let res = models::model_dir(&resource_dir(&handle), &model_id);
+        let model_path = res.join("model.onnx");
+        let tok_path = res.join("tokenizer.json");
+
+        if !model_path.exists() || !tok_path.exists() {
+            publish_status(&handle, &model_id, ModelStatus::Unavailable);
+            eprintln!(
+                "[LocalLens] 模型 {model_id} 的文件未找到，请将 model.onnx 和 tokenizer.json 放入 {}",
+                res.display()
+            );
+            return;
+        }
+
+        let corrupted = |e: String| {
+            eprintln!("[LocalLens] 模型 {model_id} 校验失败: {e}");
+            let hint = format!(
+                "{e}。请重新安装 LocalLens，或重新下载 model.onnx、tokenizer.json 和 {MANIFEST_FILE} 到 {}",
+                res.display()
+            );
+            publish_status(&handle, &model_id, ModelStatus::Corrupted(hint));
+        };
+
+        // 1. 按清单校验文件完整性
+        let manifest = match ModelManifest::load(&res) {
+            Ok(m) => m,
+            Err(e) => return corrupted(e),
+        };
+        match &manifest {
+            Some(m) if m.model_id != model_id => {
+                return corrupted(format!("模型清单为 {}，应为 {model_id}", m.model_id));
+            }
+            Some(m) => {
+                if let Err(e) = m.verify_files(&res) {
+                    return corrupted(e);
+                }
+            }
+            // 安装包构建时生成清单（见 scripts/gen-manifest.mjs），发布版缺少清单说明安装不完整
+            None if cfg!(debug_assertions) => {
+                eprintln!("[LocalLens] 未找到 {MANIFEST_FILE}，跳过模型 {model_id} 的文件校验")
+            }
+            None => return corrupted(format!("缺少 {MANIFEST_FILE}")),
+        }
+
+        let session_opts = open_db(&handle)
+            .map(|conn| load_session_options(&conn))
+            .unwrap_or_default();
+
+        // 2. 加载模型并检查 tokenizer 与模型是否匹配
+        eprintln!("[LocalLens] 加载嵌入模型: {model_id}");
+        let models = (0..session_opts.resolve().workers)
+            .map(|_| EmbeddingModel::load(&model_path, &tok_path, &session_opts))
+            .collect::<Result<Vec<_>, _>>();
+        let mut models = match models {
+            Ok(models) => models,
+            Err(e) => {
+                eprintln!("[LocalLens] 模型 {model_id} 加载失败: {e}");
+                return publish_status(&handle, &model_id, ModelStatus::Failed(e));
+            }
+        };
+        if let Some(first) = models.first_mut() {
+            if let Err(e) = first.check_consistency(manifest.as_ref()) {
+                return corrupted(e);
+            }
+        }
+
+        match InferenceEngine::start(models) {
+            Ok(engine) => {
+                eprintln!(
+                    "[LocalLens] {model_id} 推理工作线程: {}",
+                    engine.worker_count()
+                );
+                handle
+                    .state::<EngineState>()
+                    .0
+                    .write()
+                    .unwrap()
+                    .insert(model_id.clone(), Arc::new(engine));
+                publish_status(&handle, &model_id, ModelStatus::Ready);
+                eprintln!("[LocalLens] 语义搜索模型加载成功 ({model_id})");
+                // 内置模型升级时清除旧向量（附加模型按目录名区分，换模型即换名）
+                if model_id == MODEL_NAME {
+                    if let Ok(conn) = open_db(&handle) {
+                        if check_model_version(&conn) {
+                            handle.emit("reindex-required", MODEL_NAME).ok();
+                        }
+                    }
+                }
+                spawn_ann_sync(&handle, &model_id, false);
+            }
+            Err(e) => {
+                eprintln!("[LocalLens] 模型 {model_id} 加载失败: {e}");
+                publish_status(&handle, &model_id, ModelStatus::Failed(e));
+            }
+        }
+    });
+}
+
+/// 在后台线程加载重排模型；资源目录中没有重排模型时静默跳过
+fn spawn_reranker_loader(handle: tauri::AppHandle) {
+    std::thread::spawn(move || {
+        let dir = resource_dir(&handle).join(RERANKER_DIR);
+        if !dir.join("model.onnx").exists() {
+            eprintln!("[LocalLens] 未安装重排模型（{}），跳过重排", dir.display());
+            return;
+        }
+        let session_opts = open_db(&handle)
+            .map(|conn| load_session_options(&conn))
+            .unwrap_or_default();
+        match Reranker::load(&dir, &session_opts) {
+            Ok(reranker) => {
+                eprintln!("[LocalLens] 重排模型加载成功 ({})", reranker.model_id());
+                *handle.state::<RerankerState>().0.lock().unwrap() = Some(reranker);
+            }
+            Err(e) => eprintln!("[LocalLens] 重排模型加载失败: {e}"),
+        }
+    });
+}
+
+/// 在后台线程加载稀疏模型；稀疏模型与上次不同时清空倒排表并提示重新导入
+fn spawn_sparse_loader(handle: tauri::AppHandle) {
+    std::thread::spawn(move || {
+        let dir = resource_dir(&handle).join(SPARSE_DIR);
+        if !dir.join("model.onnx").exists() {
+            eprintln!("[LocalLens] 未安装稀疏检索模型（{}），跳过", dir.display());
+            return;
+        }
+        let Ok(conn) = open_db(&handle) else {
+            return;
+        };
+        let engine = match SparseEncoder::load(&dir, &load_session_options(&conn))
+            .and_then(SparseEngine::start)
+        {
+            Ok(engine) => engine,
+            Err(e) => return eprintln!("[LocalLens] 稀疏模型加载失败: {e}"),
+        };
+        let model_id = engine.model_id().to_string();
+        eprintln!("[LocalLens] 稀疏模型加载成功 ({model_id})");
+
+        let stored: Option<String> = conn
+            .query_row(
+                "SELECT value FROM app_meta WHERE key = 'sparse_model'",
+                [],
+                |r| r.get(0),
+            )
+            .ok();
+        if stored.as_deref() != Some(model_id.as_str()) {
+            let had_terms = conn
+                .execute("DELETE FROM chunk_sparse", [])
+                .map(|n| n > 0)
+                .unwrap_or(false);
+            conn.execute(
+                "INSERT OR REPLACE INTO app_meta (key, value) VALUES ('sparse_model', ?1)",
+                rusqlite::params![model_id],
+            )
+            .ok();
+            let has_chunks = conn
+                .query_row("SELECT EXISTS(SELECT 1 FROM chunks)", [], |r| r.get(0))
+                .unwrap_or(false);
+            if had_terms || has_chunks {
+                handle.emit("reindex-required", &model_id).ok();
+            }
+        }
+        *handle.state::<SparseState>().0.write().unwrap() = Some(Arc::new(engine));
+    });
 }
 
 // ── 应用入口 ──────────────────────────────────────────────────────────────────
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let model_status = ModelStatusState(Arc::new(Mutex::new(ModelStatus::Loading)));
-    let cache = CacheState(Arc::new(RwLock::new(VectorCache::new())));
+    let model_status = ModelStatusState(Arc::new(Mutex::new(HashMap::from([(
+        MODEL_NAME.to_string(),
+        ModelStatus::Loading,
+    )]))));
+    let cache = CacheState(Arc::new(Mutex::new(HashMap::new())));
+    let engine = EngineState(Arc::new(RwLock::new(HashMap::new())));
+    let reranker = RerankerState(Arc::new(Mutex::new(None)));
+    let sparse = SparseState(Arc::new(RwLock::new(None)));
+    let ann = AnnState {
+        index: Arc::new(RwLock::new(HashMap::new())),
+        sync: Arc::new(Mutex::new(())),
+    };
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
         .manage(model_status)
         .manage(cache)
+        .manage(engine)
+        .manage(reranker)
+        .manage(sparse)
+        .manage(ann)
         .setup(|app| {
+            // 数据库结构迁移只在启动时执行一次
+            let pool = DbPool::open(&db_path(app.handle()))?;
+            app.manage(DbState(pool));
+            load_vector_store(app.handle());
+            resume_vector_conversion(app.handle());
             // 后台线程加载模型，不阻塞 UI
-            let handle = app.handle().clone();
-            let status_arc = app.state::<ModelStatusState>().0.clone();
-
-            std::thread::spawn(move || {
-                let res = resource_dir(&handle);
-                let model_path = res.join("model.onnx");
-                let tok_path = res.join("tokenizer.json");
-
-                if !model_path.exists() || !tok_path.exists() {
-                    *status_arc.lock().unwrap() = ModelStatus::Unavailable;
-                    handle.emit("model-status", "unavailable").ok();
-                    eprintln!(
-                        "[LocalLens] 模型文件未找到，请将 model.onnx 和 tokenizer.json 放入 {}",
-                        res.display()
-                    );
-                    return;
-                }
-
-                eprintln!("[LocalLens] 当前嵌入模型: {}", MODEL_NAME);
-                match EmbeddingModel::load(&model_path, &tok_path) {
-                    Ok(model) => {
-                        *model_lock().lock().unwrap() = Some(model);
-                        *status_arc.lock().unwrap() = ModelStatus::Ready;
-                        handle.emit("model-status", "ready").ok();
-                        eprintln!("[LocalLens] 语义搜索模型加载成功 ({})", MODEL_NAME);
-                        // 检查模型版本，必要时清除旧向量
-                        if let Ok(conn) = open_db(&handle) {
-                            if check_model_version(&conn) {
-                                handle.emit("reindex-required", MODEL_NAME).ok();
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("[LocalLens] 模型加载失败: {e}");
-                        *status_arc.lock().unwrap() = ModelStatus::Failed(e.clone());
-                        handle.emit("model-status", format!("failed:{e}")).ok();
-                    }
-                }
-            });
-
+            spawn_model_loaders(app.handle());
+            spawn_reranker_loader(app.handle().clone());
+            spawn_sparse_loader(app.handle().clone());
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             get_model_status,
+            get_model_info,
+            list_models,
+            get_reranker_status,
+            get_session_config,
+            set_session_options,
+            set_vector_format,
+            get_model_settings,
+            set_model_settings,
             select_and_import_folder,
+            remove_file,
+            remove_folder,
+            list_collections,
+            create_collection,
+            rename_collection,
+            delete_collection,
+            set_collection_chunking,
+            export_bundle,
+            inspect_bundle,
+            import_bundle,
             search_text,
+            evaluate_retrieval,
             get_stats,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // 退出前把剩余的向量增量合并进文件，下次启动无需从数据库重建
+            if let tauri::RunEvent::Exit = event {
+                let _guard = CACHE_COMPACTION.lock().unwrap();
+                for (model_id, cache) in app.state::<CacheState>().all() {
+                    if let Err(e) = compact_vector_cache(app, &model_id, &cache) {
+                        eprintln!("[LocalLens] 退出时合并 {model_id} 的向量文件失败: {e}");
+                    }
+                }
+            }
+        });
 }
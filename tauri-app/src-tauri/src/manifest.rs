@@ -0,0 +1,89 @@
+//! 模型清单（model.manifest.json）：记录模型文件的 SHA-256 与形状信息，加载前校验
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+
+pub const MANIFEST_FILE: &str = "model.manifest.json";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ModelManifest {
+    pub model_id: String,
+    /// 文件名（相对清单所在目录）→ 校验信息
+    pub files: BTreeMap<String, FileDigest>,
+    /// 输出向量维度
+    pub dimension: Option<usize>,
+    /// tokenizer 词表大小（含特殊 token）
+    pub vocab_size: Option<usize>,
+    /// Matryoshka 训练的模型可截断到的维度（如 [128, 256]）；未列出则不允许截断
+    #[serde(default)]
+    pub matryoshka_dims: Vec<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FileDigest {
+    pub sha256: String,
+    pub size: Option<u64>,
+}
+
+impl ModelManifest {
+    /// 读取 `dir` 下的清单；文件不存在时返回 Ok(None)
+    pub fn load(dir: &Path) -> Result<Option<Self>, String> {
+        let path = dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("读取 {} 失败: {e}", path.display()))?;
+        serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|e| format!("{} 格式错误: {e}", path.display()))
+    }
+
+    /// 逐个校验清单中文件的大小与 SHA-256，返回第一个不一致的说明
+    pub fn verify_files(&self, dir: &Path) -> Result<(), String> {
+        for (name, digest) in &self.files {
+            let path = dir.join(name);
+            let meta = std::fs::metadata(&path).map_err(|_| format!("{name} 缺失"))?;
+            if let Some(size) = digest.size {
+                if meta.len() != size {
+                    return Err(format!(
+                        "{name} 大小为 {} 字节，清单记录为 {size} 字节（文件可能被截断）",
+                        meta.len()
+                    ));
+                }
+            }
+            let actual = sha256_file(&path).map_err(|e| format!("读取 {name} 失败: {e}"))?;
+            if !actual.eq_ignore_ascii_case(&digest.sha256) {
+                return Err(format!("{name} 的 SHA-256 与清单不符（文件已损坏或被替换）"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 流式计算文件 SHA-256（小写十六进制）
+pub fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// 内存数据的 SHA-256（小写十六进制）
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,925 @@
+//! 数据库结构迁移：按版本号顺序执行，当前版本记录在 `PRAGMA user_version`
+//!
+//! 每个迁移在独立事务中执行并同时更新版本号，失败时整体回滚。
+//! 新的结构变更只追加到 `MIGRATIONS` 末尾，已发布的迁移不再修改。
+
+use rusqlite::{Connection, Result as SqlResult, Transaction};
+
+struct Migration {
+    name: &'static str,
+    up: fn(&Transaction) -> SqlResult<()>,
+}
+
+/// 第 i 项迁移完成后 user_version = i + 1
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        name: "baseline",
+        up: v1_baseline,
+    },
+    Migration {
+        name: "file_metadata",
+        up: v2_file_metadata,
+    },
+    Migration {
+        name: "cascade_deletes",
+        up: v3_cascade_deletes,
+    },
+    Migration {
+        name: "chunks_fts",
+        up: v4_chunks_fts,
+    },
+    Migration {
+        name: "chunk_offsets",
+        up: v5_chunk_offsets,
+    },
+    Migration {
+        name: "collections",
+        up: v6_collections,
+    },
+    Migration {
+        name: "tagged_vectors",
+        up: v7_tagged_vectors,
+    },
+    Migration {
+        name: "embedding_cache_lru",
+        up: v8_embedding_cache_lru,
+    },
+    Migration {
+        name: "collection_models",
+        up: v9_collection_models,
+    },
+];
+
+/// 把数据库升级到最新版本，返回本次执行的迁移数
+pub fn migrate(conn: &mut Connection) -> Result<usize, String> {
+    apply(conn, MIGRATIONS)
+}
+
+fn apply(conn: &mut Connection, migrations: &[Migration]) -> Result<usize, String> {
+    let current: u32 = conn
+        .pragma_query_value(None, "user_version", |r| r.get(0))
+        .map_err(|e| e.to_string())?;
+    let latest = migrations.len() as u32;
+    if current > latest {
+        return Err(format!(
+            "数据库结构版本为 {current}，高于当前 LocalLens 支持的 {latest}，请升级应用"
+        ));
+    }
+
+    if current == latest {
+        return Ok(0);
+    }
+
+    // 重建表时删除旧表会触发级联删除，迁移期间关闭外键检查（只能在事务外切换），
+    // 每个迁移提交前用 foreign_key_check 确认没有新增悬空引用；结束后（无论成败）恢复调用方的设置
+    let foreign_keys: bool = conn
+        .pragma_query_value(None, "foreign_keys", |r| r.get(0))
+        .map_err(|e| e.to_string())?;
+    conn.pragma_update(None, "foreign_keys", false)
+        .map_err(|e| e.to_string())?;
+    let result = run(conn, migrations, current);
+    conn.pragma_update(None, "foreign_keys", foreign_keys)
+        .map_err(|e| e.to_string())?;
+    result?;
+    Ok((latest - current) as usize)
+}
+
+/// 依次执行版本 `current` 之后的迁移，每个迁移一个事务
+fn run(conn: &mut Connection, migrations: &[Migration], current: u32) -> Result<(), String> {
+    for (i, m) in migrations.iter().enumerate().skip(current as usize) {
+        let version = i as u32 + 1;
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        foreign_key_violations(&tx)
+            .and_then(|before| {
+                (m.up)(&tx)?;
+                let after = foreign_key_violations(&tx)?;
+                if after > before {
+                    return Err(rusqlite::Error::SqliteFailure(
+                        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY),
+                        Some(format!("新增 {} 行失效的外键引用", after - before)),
+                    ));
+                }
+                Ok(())
+            })
+            .and_then(|_| tx.pragma_update(None, "user_version", version))
+            .and_then(|_| tx.commit())
+            .map_err(|e| format!("数据库迁移 {version}（{}）失败: {e}", m.name))?;
+        eprintln!("[LocalLens] 数据库结构已升级到版本 {version}（{}）", m.name);
+    }
+    Ok(())
+}
+
+fn foreign_key_violations(tx: &Transaction) -> SqlResult<i64> {
+    tx.query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |r| {
+        r.get(0)
+    })
+}
+
+// ── 迁移 ──────────────────────────────────────────────────────────────────────
+
+/// 版本 1：引入迁移前的全部结构
+///
+/// 沿用 CREATE IF NOT EXISTS，旧版 init_schema 建好的数据库（user_version = 0）
+/// 只补齐缺失的表，已有数据不动
+fn v1_baseline(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS app_meta (
+            key   TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS files (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            path         TEXT NOT NULL UNIQUE,
+            name         TEXT NOT NULL,
+            imported_at  DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE IF NOT EXISTS chunks (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            file_id      INTEGER NOT NULL REFERENCES files(id),
+            content      TEXT NOT NULL,
+            chunk_index  INTEGER NOT NULL
+        );
+        -- 向量存储：BLOB = 1 字节格式标记 + 数据（见 quant.rs）
+        CREATE TABLE IF NOT EXISTS chunk_embeddings (
+            chunk_id  INTEGER PRIMARY KEY REFERENCES chunks(id),
+            embedding BLOB NOT NULL
+        );
+        -- Matryoshka 截断存储时另存的完整向量（仅用于重新打分，不进内存缓存）
+        CREATE TABLE IF NOT EXISTS chunk_embeddings_full (
+            chunk_id  INTEGER PRIMARY KEY REFERENCES chunks(id),
+            embedding BLOB NOT NULL
+        );
+        -- 长段落滑动窗口向量（仅多窗口 chunk 写入），区间为 content 中的字节偏移
+        CREATE TABLE IF NOT EXISTS chunk_windows (
+            chunk_id     INTEGER NOT NULL REFERENCES chunks(id),
+            window_index INTEGER NOT NULL,
+            byte_start   INTEGER NOT NULL,
+            byte_end     INTEGER NOT NULL,
+            embedding    BLOB NOT NULL,
+            PRIMARY KEY (chunk_id, window_index)
+        );
+        -- 稀疏向量倒排表：term_id 为稀疏模型词表下标，按 term 聚簇便于查询时合并
+        CREATE TABLE IF NOT EXISTS chunk_sparse (
+            term_id  INTEGER NOT NULL,
+            chunk_id INTEGER NOT NULL REFERENCES chunks(id),
+            weight   REAL    NOT NULL,
+            PRIMARY KEY (term_id, chunk_id)
+        ) WITHOUT ROWID;
+        -- 按内容寻址的 embedding 缓存：重新导入时未修改的段落直接复用向量
+        -- text_hash 覆盖段落文本与窗口参数；向量为完整维度 f32，与存储格式、截断设置无关
+        CREATE TABLE IF NOT EXISTS embedding_cache (
+            model_id  TEXT NOT NULL,
+            text_hash TEXT NOT NULL,
+            embedding BLOB NOT NULL,
+            PRIMARY KEY (model_id, text_hash)
+        );
+        CREATE TABLE IF NOT EXISTS embedding_cache_windows (
+            model_id     TEXT    NOT NULL,
+            text_hash    TEXT    NOT NULL,
+            window_index INTEGER NOT NULL,
+            byte_start   INTEGER NOT NULL,
+            byte_end     INTEGER NOT NULL,
+            embedding    BLOB    NOT NULL,
+            PRIMARY KEY (model_id, text_hash, window_index)
+        );
+        CREATE INDEX IF NOT EXISTS idx_sparse_chunk   ON chunk_sparse(chunk_id);
+        CREATE INDEX IF NOT EXISTS idx_chunks_file    ON chunks(file_id);
+        CREATE INDEX IF NOT EXISTS idx_chunks_content ON chunks(content);
+                ",
+    )
+}
+
+/// 版本 2：文件元数据（大小、修改时间、内容哈希、格式、chunk 数）
+///
+/// 已导入文件的 chunk_count 按现有 chunks 回填；此前只支持 TXT，format 默认为 txt。
+/// 大小、修改时间与哈希在下次导入时补齐
+fn v2_file_metadata(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "
+        ALTER TABLE files ADD COLUMN size_bytes   INTEGER;
+        ALTER TABLE files ADD COLUMN modified_at  TEXT;
+        ALTER TABLE files ADD COLUMN content_hash TEXT;
+        ALTER TABLE files ADD COLUMN format       TEXT    NOT NULL DEFAULT 'txt';
+        ALTER TABLE files ADD COLUMN chunk_count  INTEGER NOT NULL DEFAULT 0;
+        UPDATE files SET chunk_count = (SELECT COUNT(*) FROM chunks WHERE chunks.file_id = files.id);
+        CREATE INDEX IF NOT EXISTS idx_files_format ON files(format);
+        ",
+    )
+}
+
+/// 版本 3：外键改为 ON DELETE CASCADE，删除文件时自动清理派生数据
+///
+/// SQLite 不能修改已有外键，按官方建议重建各表：新表 → 复制 → 删旧表 → 改名。
+/// 复制时丢弃已成为孤儿的行（此前未开启外键检查，手工删除可能有遗漏）。
+/// 迁移期间外键检查关闭（见 apply），删除旧表不会触发级联。
+fn v3_cascade_deletes(tx: &Transaction) -> SqlResult<()> {
+    let chunks_seq: Option<i64> = tx
+        .query_row(
+            "SELECT seq FROM sqlite_sequence WHERE name = 'chunks'",
+            [],
+            |r| r.get(0),
+        )
+        .ok();
+
+    tx.execute_batch(
+        "
+        CREATE TABLE chunks_new (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            file_id      INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+            content      TEXT NOT NULL,
+            chunk_index  INTEGER NOT NULL
+        );
+        INSERT INTO chunks_new (id, file_id, content, chunk_index)
+            SELECT id, file_id, content, chunk_index FROM chunks
+            WHERE file_id IN (SELECT id FROM files);
+
+        CREATE TABLE chunk_embeddings_new (
+            chunk_id  INTEGER PRIMARY KEY REFERENCES chunks(id) ON DELETE CASCADE,
+            embedding BLOB NOT NULL
+        );
+        INSERT INTO chunk_embeddings_new
+            SELECT chunk_id, embedding FROM chunk_embeddings
+            WHERE chunk_id IN (SELECT id FROM chunks_new);
+
+        CREATE TABLE chunk_embeddings_full_new (
+            chunk_id  INTEGER PRIMARY KEY REFERENCES chunks(id) ON DELETE CASCADE,
+            embedding BLOB NOT NULL
+        );
+        INSERT INTO chunk_embeddings_full_new
+            SELECT chunk_id, embedding FROM chunk_embeddings_full
+            WHERE chunk_id IN (SELECT id FROM chunks_new);
+
+        CREATE TABLE chunk_windows_new (
+            chunk_id     INTEGER NOT NULL REFERENCES chunks(id) ON DELETE CASCADE,
+            window_index INTEGER NOT NULL,
+            byte_start   INTEGER NOT NULL,
+            byte_end     INTEGER NOT NULL,
+            embedding    BLOB NOT NULL,
+            PRIMARY KEY (chunk_id, window_index)
+        );
+        INSERT INTO chunk_windows_new
+            SELECT chunk_id, window_index, byte_start, byte_end, embedding FROM chunk_windows
+            WHERE chunk_id IN (SELECT id FROM chunks_new);
+
+        CREATE TABLE chunk_sparse_new (
+            term_id  INTEGER NOT NULL,
+            chunk_id INTEGER NOT NULL REFERENCES chunks(id) ON DELETE CASCADE,
+            weight   REAL    NOT NULL,
+            PRIMARY KEY (term_id, chunk_id)
+        ) WITHOUT ROWID;
+        INSERT INTO chunk_sparse_new
+            SELECT term_id, chunk_id, weight FROM chunk_sparse
+            WHERE chunk_id IN (SELECT id FROM chunks_new);
+
+        DROP TABLE chunk_sparse;
+        DROP TABLE chunk_windows;
+        DROP TABLE chunk_embeddings_full;
+        DROP TABLE chunk_embeddings;
+        DROP TABLE chunks;
+        ALTER TABLE chunks_new                RENAME TO chunks;
+        ALTER TABLE chunk_embeddings_new      RENAME TO chunk_embeddings;
+        ALTER TABLE chunk_embeddings_full_new RENAME TO chunk_embeddings_full;
+        ALTER TABLE chunk_windows_new         RENAME TO chunk_windows;
+        ALTER TABLE chunk_sparse_new          RENAME TO chunk_sparse;
+
+        CREATE INDEX idx_chunks_file    ON chunks(file_id);
+        CREATE INDEX idx_chunks_content ON chunks(content);
+        CREATE INDEX idx_sparse_chunk   ON chunk_sparse(chunk_id);
+        ",
+    )?;
+
+    // 保留自增序列，避免删除末尾 chunk 后 id 被重新分配
+    if let Some(seq) = chunks_seq {
+        tx.execute(
+            "UPDATE sqlite_sequence SET seq = MAX(seq, ?1) WHERE name = 'chunks'",
+            [seq],
+        )?;
+    }
+    Ok(())
+}
+
+/// 版本 4：FTS5 全文索引，取代 `content LIKE '%…%'` 的全表扫描
+///
+/// trigram 分词不依赖空格切词，中日韩文本与英文都能做子串匹配（查询至少 3 个字符）。
+/// 索引为外部内容表，正文只存一份在 chunks 中，由触发器保持同步；
+/// 已有数据通过 'rebuild' 一次性回填。原来的 content B-tree 索引对 LIKE 无用，一并删除。
+fn v4_chunks_fts(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "
+        CREATE VIRTUAL TABLE chunks_fts USING fts5(
+            content,
+            content = 'chunks',
+            content_rowid = 'id',
+            tokenize = 'trigram'
+        );
+
+        CREATE TRIGGER chunks_fts_insert AFTER INSERT ON chunks BEGIN
+            INSERT INTO chunks_fts (rowid, content) VALUES (new.id, new.content);
+        END;
+        CREATE TRIGGER chunks_fts_delete AFTER DELETE ON chunks BEGIN
+            INSERT INTO chunks_fts (chunks_fts, rowid, content)
+                VALUES ('delete', old.id, old.content);
+        END;
+        CREATE TRIGGER chunks_fts_update AFTER UPDATE OF content ON chunks BEGIN
+            INSERT INTO chunks_fts (chunks_fts, rowid, content)
+                VALUES ('delete', old.id, old.content);
+            INSERT INTO chunks_fts (rowid, content) VALUES (new.id, new.content);
+        END;
+
+        INSERT INTO chunks_fts (chunks_fts) VALUES ('rebuild');
+        DROP INDEX IF EXISTS idx_chunks_content;
+        ",
+    )
+}
+
+/// 版本 5：chunk 在原文中的字节区间，以及按 (file_id, chunk_index) 查找相邻 chunk 的索引
+///
+/// 已有 chunk 的区间为 NULL，重新导入该文件时补齐。新索引以 file_id 开头，取代 idx_chunks_file
+fn v5_chunk_offsets(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "
+        ALTER TABLE chunks ADD COLUMN byte_start INTEGER;
+        ALTER TABLE chunks ADD COLUMN byte_end   INTEGER;
+        CREATE INDEX idx_chunks_file_index ON chunks(file_id, chunk_index);
+        DROP INDEX IF EXISTS idx_chunks_file;
+        ",
+    )
+}
+
+/// 版本 6：命名集合。每个文件属于一个集合，同一路径可以分别导入多个集合
+///
+/// 已有文件归入 id 为 1 的“默认”集合；此前没有记录导入的根目录，默认集合的根目录为空，重新导入时补齐。
+/// files 的唯一约束改为 (collection_id, path)，需按 v3 的方式重建表，并保留自增序列。
+fn v6_collections(tx: &Transaction) -> SqlResult<()> {
+    let files_seq: Option<i64> = tx
+        .query_row(
+            "SELECT seq FROM sqlite_sequence WHERE name = 'files'",
+            [],
+            |r| r.get(0),
+        )
+        .ok();
+
+    tx.execute_batch(
+        "
+        CREATE TABLE collections (
+            id         INTEGER PRIMARY KEY AUTOINCREMENT,
+            name       TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            chunking   TEXT NOT NULL DEFAULT '{}',
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE collection_roots (
+            collection_id INTEGER NOT NULL REFERENCES collections(id) ON DELETE CASCADE,
+            path          TEXT    NOT NULL,
+            PRIMARY KEY (collection_id, path)
+        );
+        INSERT INTO collections (id, name) VALUES (1, '默认');
+
+        CREATE TABLE files_new (
+            id            INTEGER PRIMARY KEY AUTOINCREMENT,
+            collection_id INTEGER NOT NULL DEFAULT 1 REFERENCES collections(id) ON DELETE CASCADE,
+            path          TEXT    NOT NULL,
+            name          TEXT    NOT NULL,
+            imported_at   DATETIME DEFAULT CURRENT_TIMESTAMP,
+            size_bytes    INTEGER,
+            modified_at   TEXT,
+            content_hash  TEXT,
+            format        TEXT    NOT NULL DEFAULT 'txt',
+            chunk_count   INTEGER NOT NULL DEFAULT 0,
+            UNIQUE (collection_id, path)
+        );
+        INSERT INTO files_new (id, collection_id, path, name, imported_at,
+                               size_bytes, modified_at, content_hash, format, chunk_count)
+            SELECT id, 1, path, name, imported_at,
+                   size_bytes, modified_at, content_hash, format, chunk_count
+            FROM files;
+        DROP TABLE files;
+        ALTER TABLE files_new RENAME TO files;
+        CREATE INDEX idx_files_format ON files(format);
+        ",
+    )?;
+    if let Some(seq) = files_seq {
+        tx.execute(
+            "UPDATE sqlite_sequence SET seq = MAX(seq, ?1) WHERE name = 'files'",
+            [seq],
+        )?;
+    }
+    Ok(())
+}
+
+/// 版本 7：旧版无格式标记的裸 f32 向量加上 f32 格式标记（首字节 0），数据本身不变
+///
+/// 此前由启动时的一次性转换完成并记入 app_meta 的 vector_blob_version，已转换过的库跳过。
+/// 长度不是 4 的倍数的损坏向量保持原样（检索时被跳过）。改写了向量时递增向量代数，使旧的向量文件失效
+fn v7_tagged_vectors(tx: &Transaction) -> SqlResult<()> {
+    let converted: bool = tx.query_row(
+        "SELECT EXISTS (SELECT 1 FROM app_meta WHERE key = 'vector_blob_version')",
+        [],
+        |r| r.get(0),
+    )?;
+    if !converted {
+        let mut changed = 0usize;
+        // || 的结果是 TEXT，需转回 BLOB
+        for table in ["chunk_embeddings", "chunk_embeddings_full", "chunk_windows"] {
+            changed += tx.execute(
+                &format!(
+                    "UPDATE {table} SET embedding = CAST(x'00' || embedding AS BLOB)
+                     WHERE length(embedding) % 4 = 0"
+                ),
+                [],
+            )?;
+        }
+        if changed > 0 {
+            tx.execute(
+                "INSERT INTO app_meta (key, value) VALUES ('vector_generation', '1')
+                 ON CONFLICT(key) DO UPDATE SET value = CAST(value AS INTEGER) + 1",
+                [],
+            )?;
+        }
+    }
+    tx.execute("DELETE FROM app_meta WHERE key = 'vector_blob_version'", [])?;
+    Ok(())
+}
+
+/// 版本 8：embedding 缓存记录最近使用时间（unix 秒），按最近最少使用淘汰
+///
+/// 已有条目记为 0，最先被淘汰
+fn v8_embedding_cache_lru(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "
+        ALTER TABLE embedding_cache ADD COLUMN used_at INTEGER NOT NULL DEFAULT 0;
+        CREATE INDEX idx_embedding_cache_used ON embedding_cache(used_at);
+        ",
+    )
+}
+
+/// 版本 9：每个集合记录自己的嵌入模型
+///
+/// 此前所有集合共用内置模型：已有集合取 app_meta 中记录的模型名，新库（尚未记录）取当前的内置模型
+fn v9_collection_models(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch("ALTER TABLE collections ADD COLUMN model_id TEXT NOT NULL DEFAULT ''")?;
+    tx.execute(
+        "UPDATE collections SET model_id = COALESCE(
+             (SELECT value FROM app_meta WHERE key = 'model_name'), ?1)",
+        [crate::MODEL_NAME],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use rusqlite::params;
+
+    fn latest_version() -> u32 {
+        MIGRATIONS.len() as u32
+    }
+
+    /// 最早发布版本的 init_schema（向量为无格式标记的裸 f32）
+    const ORIGINAL_SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS app_meta (
+            key   TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS files (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            path         TEXT NOT NULL UNIQUE,
+            name         TEXT NOT NULL,
+            imported_at  DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE IF NOT EXISTS chunks (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            file_id      INTEGER NOT NULL REFERENCES files(id),
+            content      TEXT NOT NULL,
+            chunk_index  INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS chunk_embeddings (
+            chunk_id  INTEGER PRIMARY KEY REFERENCES chunks(id),
+            embedding BLOB NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_chunks_file    ON chunks(file_id);
+        CREATE INDEX IF NOT EXISTS idx_chunks_content ON chunks(content);
+    ";
+
+    fn user_version(conn: &Connection) -> u32 {
+        conn.pragma_query_value(None, "user_version", |r| r.get(0))
+            .unwrap()
+    }
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |r| r.get(0))
+            .unwrap()
+    }
+
+    fn seed(conn: &Connection) {
+        conn.execute_batch(
+            "
+            INSERT INTO app_meta (key, value) VALUES ('model_name', 'm');
+            INSERT INTO files (path, name) VALUES ('/docs/a.txt', 'a.txt'), ('/docs/b.txt', 'b.txt');
+            INSERT INTO chunks (file_id, content, chunk_index) VALUES
+                (1, 'first paragraph of a', 0),
+                (1, 'second paragraph of a', 1),
+                (2, 'only paragraph of b', 0);
+            INSERT INTO chunk_embeddings (chunk_id, embedding) VALUES
+                (1, x'0000803f'), (2, x'00000040'), (3, x'00004040');
+            ",
+        )
+        .unwrap();
+    }
+
+    fn assert_seed_intact(conn: &Connection) {
+        assert_eq!(count(conn, "files"), 2);
+        assert_eq!(count(conn, "chunks"), 3);
+        assert_eq!(count(conn, "chunk_embeddings"), 3);
+        let content: String = conn
+            .query_row("SELECT content FROM chunks WHERE id = 2", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(content, "second paragraph of a");
+        let blob: Vec<u8> = conn
+            .query_row(
+                "SELECT embedding FROM chunk_embeddings WHERE chunk_id = 3",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        // 迁移前写入的是裸 f32，经过 v7 后多出 1 字节格式标记，向量数据不变
+        assert_eq!(blob[blob.len() - 4..], [0x00, 0x00, 0x40, 0x40]);
+        let model: String = conn
+            .query_row(
+                "SELECT value FROM app_meta WHERE key = 'model_name'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(model, "m");
+    }
+
+    #[test]
+    fn fresh_database_reaches_latest_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(migrate(&mut conn).unwrap(), MIGRATIONS.len());
+        assert_eq!(user_version(&conn), latest_version());
+        seed(&conn);
+        assert_seed_intact(&conn);
+    }
+
+    #[test]
+    fn upgrades_original_schema_without_data_loss() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(ORIGINAL_SCHEMA).unwrap();
+        seed(&conn);
+
+        migrate(&mut conn).unwrap();
+        assert_eq!(user_version(&conn), latest_version());
+        assert_seed_intact(&conn);
+        // 后来加入的表已补齐
+        assert_eq!(count(&conn, "chunk_windows"), 0);
+        assert_eq!(count(&conn, "embedding_cache"), 0);
+    }
+
+    #[test]
+    fn upgrades_unversioned_current_schema_without_data_loss() {
+        // 引入迁移前由 init_schema 建立的数据库：结构齐全但 user_version = 0
+        let mut conn = Connection::open_in_memory().unwrap();
+        let tx = conn.transaction().unwrap();
+        v1_baseline(&tx).unwrap();
+        tx.commit().unwrap();
+        seed(&conn);
+        conn.execute(
+            "INSERT INTO chunk_windows (chunk_id, window_index, byte_start, byte_end, embedding)
+             VALUES (1, 0, 0, 5, x'00')",
+            [],
+        )
+        .unwrap();
+        assert_eq!(user_version(&conn), 0);
+
+        migrate(&mut conn).unwrap();
+        assert_eq!(user_version(&conn), latest_version());
+        assert_seed_intact(&conn);
+        assert_eq!(count(&conn, "chunk_windows"), 1);
+    }
+
+    #[test]
+    fn file_metadata_backfills_chunk_count() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(ORIGINAL_SCHEMA).unwrap();
+        seed(&conn);
+
+        migrate(&mut conn).unwrap();
+        let rows: Vec<(String, i64, String)> = conn
+            .prepare("SELECT name, chunk_count, format FROM files ORDER BY id")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap()
+            .collect::<SqlResult<_>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ("a.txt".to_string(), 2, "txt".to_string()),
+                ("b.txt".to_string(), 1, "txt".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn cascade_removes_derived_rows_and_drops_orphans() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(ORIGINAL_SCHEMA).unwrap();
+        seed(&conn);
+        // 旧版手工删除遗留的孤儿向量（未开启外键检查的连接写入）
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        conn.execute(
+            "INSERT INTO chunk_embeddings (chunk_id, embedding) VALUES (99, x'00')",
+            [],
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+        assert_seed_intact(&conn);
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+        let violations: i64 = conn
+            .query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(violations, 0);
+
+        conn.execute(
+            "INSERT INTO chunk_windows (chunk_id, window_index, byte_start, byte_end, embedding)
+             VALUES (1, 0, 0, 5, x'00')",
+            [],
+        )
+        .unwrap();
+        conn.execute("DELETE FROM files WHERE name = 'a.txt'", [])
+            .unwrap();
+        assert_eq!(count(&conn, "chunks"), 1);
+        assert_eq!(count(&conn, "chunk_embeddings"), 1);
+        assert_eq!(count(&conn, "chunk_windows"), 0);
+    }
+
+    fn fts_matches(conn: &Connection, phrase: &str) -> Vec<i64> {
+        conn.prepare("SELECT rowid FROM chunks_fts WHERE chunks_fts MATCH ?1 ORDER BY rowid")
+            .unwrap()
+            .query_map([phrase], |r| r.get(0))
+            .unwrap()
+            .collect::<SqlResult<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn fts_backfills_existing_chunks() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(ORIGINAL_SCHEMA).unwrap();
+        seed(&conn);
+
+        migrate(&mut conn).unwrap();
+        assert_eq!(fts_matches(&conn, "\"paragraph\""), vec![1, 2, 3]);
+        assert_eq!(fts_matches(&conn, "\"of b\""), vec![3]);
+    }
+
+    #[test]
+    fn fts_follows_chunk_changes() {
+        let conn = test_support::db();
+        seed(&conn);
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+        conn.execute(
+            "INSERT INTO chunks (file_id, content, chunk_index) VALUES (2, '本地语义搜索工具', 1)",
+            [],
+        )
+        .unwrap();
+        assert_eq!(fts_matches(&conn, "\"语义搜\""), vec![4]);
+
+        conn.execute(
+            "UPDATE chunks SET content = 'rewritten text' WHERE id = 1",
+            [],
+        )
+        .unwrap();
+        assert_eq!(fts_matches(&conn, "\"paragraph\""), vec![2, 3]);
+        assert_eq!(fts_matches(&conn, "\"rewritten\""), vec![1]);
+
+        // 级联删除同样触发同步
+        conn.execute("DELETE FROM files WHERE name = 'b.txt'", [])
+            .unwrap();
+        assert_eq!(fts_matches(&conn, "\"paragraph\""), vec![2]);
+        assert!(fts_matches(&conn, "\"语义搜\"").is_empty());
+    }
+
+    #[test]
+    fn existing_files_join_default_collection() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(ORIGINAL_SCHEMA).unwrap();
+        seed(&conn);
+
+        migrate(&mut conn).unwrap();
+        assert_seed_intact(&conn);
+        let name: String = conn
+            .query_row("SELECT name FROM collections WHERE id = 1", [], |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(name, "默认");
+        let in_default: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM files WHERE collection_id = 1",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(in_default, 2);
+
+        // 同一路径可以导入另一个集合；删除集合时其文件与 chunk 级联删除
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO collections (id, name) VALUES (2, 'Research');
+            INSERT INTO collection_roots (collection_id, path) VALUES (2, '/docs');
+            INSERT INTO files (collection_id, path, name) VALUES (2, '/docs/a.txt', 'a.txt');
+            INSERT INTO chunks (file_id, content, chunk_index) VALUES (3, 'copy of a', 0);
+            ",
+        )
+        .unwrap();
+        assert!(conn
+            .execute(
+                "INSERT INTO files (collection_id, path, name) VALUES (2, '/docs/a.txt', 'a.txt')",
+                [],
+            )
+            .is_err());
+        conn.execute("DELETE FROM collections WHERE id = 2", [])
+            .unwrap();
+        assert_eq!(count(&conn, "collection_roots"), 0);
+        assert_seed_intact(&conn);
+    }
+
+    fn blobs(conn: &Connection, table: &str) -> Vec<Vec<u8>> {
+        conn.prepare(&format!("SELECT embedding FROM {table} ORDER BY rowid"))
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<SqlResult<_>>()
+            .unwrap()
+    }
+
+    fn vector_generation(conn: &Connection) -> Option<String> {
+        conn.query_row(
+            "SELECT value FROM app_meta WHERE key = 'vector_generation'",
+            [],
+            |r| r.get(0),
+        )
+        .ok()
+    }
+
+    #[test]
+    fn legacy_vectors_gain_format_tag() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(ORIGINAL_SCHEMA).unwrap();
+        seed(&conn);
+        // 长度不是 4 的倍数的损坏向量保持原样
+        conn.execute_batch(
+            "
+            INSERT INTO chunks (file_id, content, chunk_index) VALUES (2, 'broken', 1);
+            INSERT INTO chunk_embeddings (chunk_id, embedding) VALUES (4, x'010203');
+            ",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+        assert_eq!(
+            blobs(&conn, "chunk_embeddings"),
+            vec![
+                vec![0x00, 0x00, 0x00, 0x80, 0x3f],
+                vec![0x00, 0x00, 0x00, 0x00, 0x40],
+                vec![0x00, 0x00, 0x00, 0x40, 0x40],
+                vec![0x01, 0x02, 0x03],
+            ]
+        );
+        let v = crate::quant::QuantVec::from_bytes(&blobs(&conn, "chunk_embeddings")[0], Some(1))
+            .unwrap();
+        assert_eq!(v.to_f32(), vec![1.0]);
+        assert_eq!(vector_generation(&conn).as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn already_tagged_vectors_are_left_alone() {
+        // 由启动时转换处理过的库：向量已带标记，并记有 vector_blob_version
+        let mut conn = Connection::open_in_memory().unwrap();
+        apply(&mut conn, &MIGRATIONS[..6]).unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO app_meta (key, value) VALUES
+                ('vector_blob_version', '1'), ('vector_generation', '4');
+            INSERT INTO files (path, name) VALUES ('/docs/a.txt', 'a.txt');
+            INSERT INTO chunks (file_id, content, chunk_index) VALUES (1, 'a', 0);
+            INSERT INTO chunk_embeddings (chunk_id, embedding) VALUES (1, x'000000803f');
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(migrate(&mut conn).unwrap(), MIGRATIONS.len() - 6);
+        assert_eq!(
+            blobs(&conn, "chunk_embeddings"),
+            vec![vec![0x00, 0x00, 0x00, 0x80, 0x3f]]
+        );
+        assert_eq!(vector_generation(&conn).as_deref(), Some("4"));
+        let marker: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM app_meta WHERE key = 'vector_blob_version'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(marker, 0);
+    }
+
+    #[test]
+    fn collections_take_the_recorded_model() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        apply(&mut conn, &MIGRATIONS[..8]).unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO app_meta (key, value) VALUES ('model_name', 'old-model');
+            INSERT INTO collections (id, name) VALUES (2, 'Research');
+            ",
+        )
+        .unwrap();
+        migrate(&mut conn).unwrap();
+        let models: Vec<String> = conn
+            .prepare("SELECT model_id FROM collections ORDER BY id")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<SqlResult<_>>()
+            .unwrap();
+        assert_eq!(models, vec!["old-model", "old-model"]);
+
+        // 新库尚未记录模型名，默认集合使用当前的内置模型
+        let fresh = test_support::db();
+        let model: String = fresh
+            .query_row("SELECT model_id FROM collections WHERE id = 1", [], |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(model, crate::MODEL_NAME);
+    }
+
+    #[test]
+    fn migrate_is_idempotent() {
+        let mut conn = test_support::db();
+        seed(&conn);
+        assert_eq!(migrate(&mut conn).unwrap(), 0);
+        assert_seed_intact(&conn);
+    }
+
+    #[test]
+    fn rejects_database_from_newer_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+        assert!(migrate(&mut conn).is_err());
+    }
+
+    #[test]
+    fn failed_migration_rolls_back() {
+        fn create_t(tx: &Transaction) -> SqlResult<()> {
+            tx.execute_batch("CREATE TABLE t (x INTEGER)")
+        }
+        fn half_then_fail(tx: &Transaction) -> SqlResult<()> {
+            tx.execute("INSERT INTO t (x) VALUES (?1)", params![1])?;
+            tx.execute_batch("ALTER TABLE missing ADD COLUMN y INTEGER")
+        }
+        let migrations = [
+            Migration {
+                name: "create",
+                up: create_t,
+            },
+            Migration {
+                name: "broken",
+                up: half_then_fail,
+            },
+        ];
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+        assert!(apply(&mut conn, &migrations).is_err());
+        // 第一个迁移已提交，第二个连同版本号一起回滚
+        assert_eq!(user_version(&conn), 1);
+        assert_eq!(count(&conn, "t"), 0);
+        assert!(foreign_keys(&conn));
+    }
+
+    fn foreign_keys(conn: &Connection) -> bool {
+        conn.pragma_query_value(None, "foreign_keys", |r| r.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn restores_foreign_key_setting() {
+        for enabled in [true, false] {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.pragma_update(None, "foreign_keys", enabled).unwrap();
+            migrate(&mut conn).unwrap();
+            assert_eq!(foreign_keys(&conn), enabled);
+        }
+    }
+}
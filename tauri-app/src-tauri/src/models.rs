@@ -0,0 +1,58 @@
+//! 可选用的嵌入模型：内置模型放在资源目录根部，附加模型各占 models/<模型名>/ 子目录
+//!
+//! 附加模型目录与内置模型相同：model.onnx、tokenizer.json 与 model.manifest.json，
+//! 清单中的 model_id 须与目录名一致。集合创建时选定其中一个模型。
+
+use crate::MODEL_NAME;
+use std::path::{Path, PathBuf};
+
+/// 附加嵌入模型所在的资源子目录
+pub const MODELS_DIR: &str = "models";
+
+/// 模型文件所在目录：内置模型为资源目录本身，附加模型为 models/<模型名>/
+pub fn model_dir(resources: &Path, model_id: &str) -> PathBuf {
+    if model_id == MODEL_NAME {
+        resources.to_path_buf()
+    } else {
+        resources.join(MODELS_DIR).join(model_id)
+    }
+}
+
+/// 模型名同时用作目录名与向量文件名的一部分，只能是单个路径分量
+fn check_id(model_id: &str) -> Result<(), String> {
+    let valid =
+        !model_id.is_empty() && !model_id.starts_with('.') && !model_id.contains(['/', '\\', ':']);
+    if !valid {
+        return Err(format!("模型名「{model_id}」无效"));
+    }
+    Ok(())
+}
+
+/// 资源目录中已安装（含 model.onnx）的嵌入模型，内置模型在前，附加模型按名称排序
+pub fn installed(resources: &Path) -> Vec<String> {
+    let mut extra: Vec<String> = std::fs::read_dir(resources.join(MODELS_DIR))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|id| check_id(id).is_ok() && id != MODEL_NAME)
+        .filter(|id| model_dir(resources, id).join("model.onnx").exists())
+        .collect();
+    extra.sort();
+    let mut models = Vec::with_capacity(extra.len() + 1);
+    if resources.join("model.onnx").exists() {
+        models.push(MODEL_NAME.to_string());
+    }
+    models.extend(extra);
+    models
+}
+
+/// 检查模型可供新集合使用：名称有效且已安装
+pub fn check_installed(resources: &Path, model_id: &str) -> Result<(), String> {
+    check_id(model_id)?;
+    if !installed(resources).iter().any(|m| m == model_id) {
+        return Err(format!(
+            "模型 {model_id} 未安装（附加模型须放在资源目录的 {MODELS_DIR}/{model_id}/ 下）"
+        ));
+    }
+    Ok(())
+}
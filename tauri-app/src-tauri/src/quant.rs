@@ -0,0 +1,317 @@
+//! 向量存储格式：f32 / f16 / int8 标量量化
+//!
+//! BLOB 布局：首字节为格式标记，其后为数据
+//! - f32：dim × 4 字节 little-endian
+//! - f16：dim × 2 字节 little-endian（IEEE 754 half）
+//! - int8：4 字节 f32 缩放系数 + dim × 1 字节（对称量化，x ≈ q × scale）
+
+use crate::embedding::{bytes_to_vec, cosine_sim as dot_f32, vec_to_bytes};
+use half::f16;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VectorFormat {
+    F32,
+    F16,
+    Int8,
+}
+
+impl VectorFormat {
+    /// BLOB 首字节的格式标记
+    pub fn tag(self) -> u8 {
+        match self {
+            VectorFormat::F32 => 0,
+            VectorFormat::F16 => 1,
+            VectorFormat::Int8 => 2,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(VectorFormat::F32),
+            1 => Some(VectorFormat::F16),
+            2 => Some(VectorFormat::Int8),
+            _ => None,
+        }
+    }
+
+    /// 去掉格式标记后每个向量占用的字节数
+    pub fn row_bytes(self, dim: usize) -> usize {
+        match self {
+            VectorFormat::F32 => dim * 4,
+            VectorFormat::F16 => dim * 2,
+            VectorFormat::Int8 => 4 + dim,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VectorFormat::F32 => "f32",
+            VectorFormat::F16 => "f16",
+            VectorFormat::Int8 => "int8",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "f32" => Some(VectorFormat::F32),
+            "f16" => Some(VectorFormat::F16),
+            "int8" => Some(VectorFormat::Int8),
+            _ => None,
+        }
+    }
+}
+
+/// 按存储格式保存的向量（内存缓存中也保持该格式，不展开为 f32）
+#[derive(Clone, Debug)]
+pub enum QuantVec {
+    F32(Vec<f32>),
+    F16(Vec<f16>),
+    Int8 { scale: f32, data: Vec<i8> },
+}
+
+impl QuantVec {
+    pub fn quantize(v: &[f32], format: VectorFormat) -> Self {
+        match format {
+            VectorFormat::F32 => QuantVec::F32(v.to_vec()),
+            VectorFormat::F16 => QuantVec::F16(v.iter().map(|&x| f16::from_f32(x)).collect()),
+            VectorFormat::Int8 => {
+                let (scale, data) = quantize_i8(v);
+                QuantVec::Int8 { scale, data }
+            }
+        }
+    }
+
+    pub fn format(&self) -> VectorFormat {
+        match self {
+            QuantVec::F32(_) => VectorFormat::F32,
+            QuantVec::F16(_) => VectorFormat::F16,
+            QuantVec::Int8 { .. } => VectorFormat::Int8,
+        }
+    }
+
+    /// 还原为 f32（仅格式转换时使用，检索不需要）
+    pub fn to_f32(&self) -> Vec<f32> {
+        match self {
+            QuantVec::F32(v) => v.clone(),
+            QuantVec::F16(v) => v.iter().map(|x| x.to_f32()).collect(),
+            QuantVec::Int8 { scale, data } => data.iter().map(|&q| q as f32 * scale).collect(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![self.format().tag()];
+        match self {
+            QuantVec::F32(v) => out.extend(vec_to_bytes(v)),
+            QuantVec::F16(v) => out.extend(v.iter().flat_map(|x| x.to_le_bytes())),
+            QuantVec::Int8 { scale, data } => {
+                out.extend(scale.to_le_bytes());
+                out.extend(data.iter().map(|&q| q as u8));
+            }
+        }
+        out
+    }
+
+    pub fn dim(&self) -> usize {
+        match self {
+            QuantVec::F32(v) => v.len(),
+            QuantVec::F16(v) => v.len(),
+            QuantVec::Int8 { data, .. } => data.len(),
+        }
+    }
+
+    /// 解析带格式标记的 BLOB；`dim` 给出时校验维度
+    pub fn from_bytes(b: &[u8], dim: Option<usize>) -> Result<Self, String> {
+        let (&tag, body) = b.split_first().ok_or("向量 BLOB 为空")?;
+        let v = match tag {
+            0 => QuantVec::F32(bytes_to_vec(body, dim)?),
+            1 if body.len().is_multiple_of(2) => QuantVec::F16(
+                body.chunks_exact(2)
+                    .map(|c| f16::from_le_bytes([c[0], c[1]]))
+                    .collect(),
+            ),
+            2 if body.len() >= 4 => QuantVec::Int8 {
+                scale: f32::from_le_bytes([body[0], body[1], body[2], body[3]]),
+                data: body[4..].iter().map(|&q| q as i8).collect(),
+            },
+            _ => return Err(format!("无法识别的向量 BLOB（标记 {tag}，长度 {}）", b.len())),
+        };
+        match dim {
+            Some(d) if v.dim() != d => Err(format!("向量维度为 {}，期望 {d}", v.dim())),
+            _ => Ok(v),
+        }
+    }
+}
+
+/// 查询向量：同时保留 f32 与 int8 形式，与任意存储格式直接计算点积
+pub struct QueryVec {
+    f32: Vec<f32>,
+    i8_scale: f32,
+    i8: Vec<i8>,
+}
+
+impl QueryVec {
+    pub fn new(v: Vec<f32>) -> Self {
+        let (i8_scale, i8) = quantize_i8(&v);
+        Self {
+            f32: v,
+            i8_scale,
+            i8,
+        }
+    }
+
+    pub fn dim(&self) -> usize {
+        self.f32.len()
+    }
+}
+
+/// 点积累加的路数
+const LANES: usize = 16;
+
+/// Σ query[i] × load(row[i])，两者长度相同
+///
+/// 浮点加法不满足结合律，单个累加器时编译器必须按顺序求和、无法向量化；
+/// 拆成 LANES 路独立累加后，内层定长循环可编译为 SIMD 乘加
+#[inline(always)]
+pub fn dot_lanes<T: Copy>(query: &[f32], row: &[T], load: impl Fn(T) -> f32) -> f32 {
+    let (q, r) = (query.chunks_exact(LANES), row.chunks_exact(LANES));
+    let tail: f32 = q
+        .remainder()
+        .iter()
+        .zip(r.remainder())
+        .map(|(a, &b)| a * load(b))
+        .sum();
+    let mut acc = [0.0f32; LANES];
+    for (q, r) in q.zip(r) {
+        for ((s, a), &b) in acc.iter_mut().zip(q).zip(r) {
+            *s += a * load(b);
+        }
+    }
+    acc.iter().sum::<f32>() + tail
+}
+
+/// 查询与存储向量的余弦相似度（均已 L2 归一化，等价于点积），不展开存储向量
+///
+/// int8 时查询也量化为 int8，整数累加后再乘以两个缩放系数
+pub fn cosine_sim(query: &QueryVec, v: &QuantVec) -> f32 {
+    match v {
+        QuantVec::F32(v) => dot_f32(&query.f32, v),
+        QuantVec::F16(v) => dot_lanes(&query.f32, v, f16::to_f32),
+        QuantVec::Int8 { scale, data } => {
+            let acc: i32 = query
+                .i8
+                .iter()
+                .zip(data)
+                .map(|(&a, &b)| a as i32 * b as i32)
+                .sum();
+            acc as f32 * query.i8_scale * scale
+        }
+    }
+}
+
+/// 与 cosine_sim 相同，但直接读取去掉格式标记的 BLOB 数据（如内存映射文件中的一行）
+pub fn cosine_sim_raw(query: &QueryVec, format: VectorFormat, row: &[u8]) -> f32 {
+    match format {
+        VectorFormat::F32 => dot_lanes(&query.f32, row.as_chunks().0, f32::from_le_bytes),
+        VectorFormat::F16 => dot_lanes(&query.f32, row.as_chunks().0, |b| {
+            f16::from_le_bytes(b).to_f32()
+        }),
+        VectorFormat::Int8 => {
+            let scale = f32::from_le_bytes([row[0], row[1], row[2], row[3]]);
+            let acc: i32 = query
+                .i8
+                .iter()
+                .zip(&row[4..])
+                .map(|(&a, &b)| a as i32 * b as i8 as i32)
+                .sum();
+            acc as f32 * query.i8_scale * scale
+        }
+    }
+}
+
+/// 对称 int8 量化：scale = max|x| / 127
+fn quantize_i8(v: &[f32]) -> (f32, Vec<i8>) {
+    let max = v.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+    if max == 0.0 {
+        return (1.0, vec![0; v.len()]);
+    }
+    let scale = max / 127.0;
+    let data = v
+        .iter()
+        .map(|&x| (x / scale).round().clamp(-127.0, 127.0) as i8)
+        .collect();
+    (scale, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::l2_normalize;
+
+    const FORMATS: [VectorFormat; 3] = [VectorFormat::F32, VectorFormat::F16, VectorFormat::Int8];
+
+    fn unit(seed: u32, dim: usize) -> Vec<f32> {
+        l2_normalize(
+            (0..dim as u32)
+                .map(|i| ((seed * 31 + i * 17) % 23) as f32 - 11.0)
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn bytes_round_trip_every_format() {
+        let v = unit(1, 37);
+        for format in FORMATS {
+            let q = QuantVec::quantize(&v, format);
+            let bytes = q.to_bytes();
+            assert_eq!(bytes[0], format.tag());
+            assert_eq!(bytes.len(), format.row_bytes(37) + 1);
+            let back = QuantVec::from_bytes(&bytes, Some(37)).unwrap();
+            assert_eq!(back.format(), format);
+            assert_eq!(back.to_f32(), q.to_f32());
+        }
+        assert_eq!(QuantVec::quantize(&v, VectorFormat::F32).to_f32(), v);
+    }
+
+    #[test]
+    fn quantized_similarity_stays_close_to_f32() {
+        // 理论误差：f16 约 1e-3，int8 约 1e-2（两侧各有 1/254 的相对误差）
+        for (format, tolerance) in [
+            (VectorFormat::F32, 1e-6),
+            (VectorFormat::F16, 2e-3),
+            (VectorFormat::Int8, 2e-2),
+        ] {
+            for seed in 0..8 {
+                let query = QueryVec::new(unit(seed, 384));
+                let doc = unit(seed + 100, 384);
+                let exact = dot_f32(&query.f32, &doc);
+                let q = QuantVec::quantize(&doc, format);
+                let sim = cosine_sim(&query, &q);
+                assert!(
+                    (sim - exact).abs() < tolerance,
+                    "{}: {sim} vs {exact}",
+                    format.as_str()
+                );
+                let raw = cosine_sim_raw(&query, format, &q.to_bytes()[1..]);
+                assert!((raw - sim).abs() < 1e-5, "{}", format.as_str());
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_tag_and_length() {
+        let cases: [(&[u8], Option<usize>); 7] = [
+            (&[], None),
+            (&[9, 0, 0, 0, 0], None),
+            (&[0, 0, 0, 0x80], None),
+            (&[1, 0, 0, 0], None),
+            (&[2, 0, 0, 0x80], None),
+            (&[0, 0, 0, 0x80, 0x3f], Some(2)),
+            (&[2, 0, 0, 0x80, 0x3f, 1, 2], Some(3)),
+        ];
+        for (bytes, dim) in cases {
+            assert!(QuantVec::from_bytes(bytes, dim).is_err(), "{bytes:?}");
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! 交叉编码器重排：对 (query, chunk) 成对打分，修正双塔向量粗排的顺序
+
+use crate::embedding::{build_session, SessionOptions};
+use crate::manifest::{ModelManifest, MANIFEST_FILE};
+use ort::session::Session;
+use ort::value::Tensor;
+use std::path::Path;
+use tokenizers::{Tokenizer, TruncationParams, TruncationStrategy};
+
+/// 重排模型所在的资源子目录（model.onnx + tokenizer.json + model.manifest.json）
+pub const RERANKER_DIR: &str = "reranker";
+
+/// query + chunk 拼接后的最大 token 数（超出时优先截断较长的一侧）
+const MAX_PAIR_SEQ: usize = 256;
+
+pub struct Reranker {
+    session: Session,
+    tokenizer: Tokenizer,
+    has_type_ids: bool,
+    model_id: String,
+}
+
+impl Reranker {
+    /// 从 `dir` 加载重排模型；清单为必需项，加载前校验文件完整性
+    pub fn load(dir: &Path, opts: &SessionOptions) -> Result<Self, String> {
+        let manifest = ModelManifest::load(dir)?
+            .ok_or_else(|| format!("缺少 {}", dir.join(MANIFEST_FILE).display()))?;
+        manifest.verify_files(dir)?;
+
+        // 优化模型缓存路径属于嵌入模型，重排模型不复用
+        let mut opts = opts.resolve();
+        opts.optimized_model_path = None;
+        let session = build_session(&opts)?
+            .commit_from_file(dir.join("model.onnx"))
+            .map_err(|e| format!("重排模型加载失败: {e}"))?;
+
+        let has_type_ids = session
+            .inputs()
+            .iter()
+            .any(|i| i.name() == "token_type_ids");
+
+        let mut tokenizer = Tokenizer::from_file(dir.join("tokenizer.json"))
+            .map_err(|e| format!("重排 Tokenizer 加载失败: {e}"))?;
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: MAX_PAIR_SEQ,
+                strategy: TruncationStrategy::LongestFirst,
+                ..Default::default()
+            }))
+            .map_err(|e| e.to_string())?
+            .with_padding(None);
+
+        Ok(Self {
+            session,
+            tokenizer,
+            has_type_ids,
+            model_id: manifest.model_id,
+        })
+    }
+
+    pub fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    /// (query, passage) 的相关度（0–1）
+    ///
+    /// 每次只打分一对，调用方在两次之间释放模型锁并检查时间预算
+    pub fn score_pair(&mut self, query: &str, passage: &str) -> Result<f32, String> {
+        let enc = self
+            .tokenizer
+            .encode((query, passage), true)
+            .map_err(|e| e.to_string())?;
+        let seq_len = enc.get_ids().len();
+
+        let to_tensor = |v: &[u32]| {
+            let data: Vec<i64> = v.iter().map(|&x| x as i64).collect();
+            Tensor::<i64>::from_array(([1_usize, seq_len], data)).map_err(|e| e.to_string())
+        };
+        let ids_ort = to_tensor(enc.get_ids())?;
+        let mask_ort = to_tensor(enc.get_attention_mask())?;
+
+        let outputs = if self.has_type_ids {
+            let types_ort = to_tensor(enc.get_type_ids())?;
+            self.session
+                .run(ort::inputs![
+                    "input_ids"      => ids_ort,
+                    "attention_mask" => mask_ort,
+                    "token_type_ids" => types_ort,
+                ])
+                .map_err(|e| format!("重排推理失败: {e}"))?
+        } else {
+            self.session
+                .run(ort::inputs![
+                    "input_ids"      => ids_ort,
+                    "attention_mask" => mask_ort,
+                ])
+                .map_err(|e| format!("重排推理失败: {e}"))?
+        };
+
+        let (_, logits) = outputs[0]
+            .try_extract_tensor::<f32>()
+            .map_err(|e| e.to_string())?;
+        relevance(logits)
+    }
+}
+
+/// logits 转为相关度：[1, 1] 单分数取 sigmoid，[1, 2] 二分类取 softmax 后"相关"一列
+fn relevance(logits: &[f32]) -> Result<f32, String> {
+    let sigmoid = |x: f32| 1.0 / (1.0 + (-x).exp());
+    match logits {
+        [logit] => Ok(sigmoid(*logit)),
+        // softmax([a, b])[1] = sigmoid(b - a)
+        [irrelevant, relevant] => Ok(sigmoid(relevant - irrelevant)),
+        _ => Err(format!(
+            "重排模型输出 {} 个 logit，应为 1 或 2 个",
+            logits.len()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relevance_by_head_shape() {
+        assert!((relevance(&[0.0]).unwrap() - 0.5).abs() < 1e-6);
+        assert!((relevance(&[2.0]).unwrap() - 0.880_797).abs() < 1e-5);
+        // 两列相同时相关度为 0.5，而不是对最后一列单独取 sigmoid
+        assert!((relevance(&[3.0, 3.0]).unwrap() - 0.5).abs() < 1e-6);
+        let (a, b) = (1.0f32, -2.0f32);
+        let softmax = b.exp() / (a.exp() + b.exp());
+        assert!((relevance(&[a, b]).unwrap() - softmax).abs() < 1e-6);
+        assert!(relevance(&[]).is_err());
+        assert!(relevance(&[0.1, 0.2, 0.7]).is_err());
+    }
+}
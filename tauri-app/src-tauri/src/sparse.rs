@@ -0,0 +1,165 @@
+//! 稀疏学习型向量（SPLADE 类模型）：每个 chunk 表示为词表上的少量加权 term，
+//! 存入 SQLite 倒排表，兼顾关键词的精确匹配与模型的词项扩展
+
+use crate::embedding::{build_session, SessionOptions};
+use crate::inference::{Pending, Priority, Workers};
+use crate::manifest::{ModelManifest, MANIFEST_FILE};
+use ort::session::Session;
+use ort::value::Tensor;
+use std::path::Path;
+use tokenizers::{Tokenizer, TruncationParams};
+
+/// 稀疏模型所在的资源子目录（model.onnx + tokenizer.json + model.manifest.json）
+pub const SPARSE_DIR: &str = "sparse";
+
+const MAX_SEQ: usize = 256;
+
+/// 每个 chunk 最多保留的 term 数（按权重取前 N，控制倒排表体积）
+pub const DOC_MAX_TERMS: usize = 256;
+/// 查询最多保留的 term 数
+pub const QUERY_MAX_TERMS: usize = 64;
+
+pub struct SparseEncoder {
+    session: Session,
+    tokenizer: Tokenizer,
+    has_type_ids: bool,
+    model_id: String,
+}
+
+impl SparseEncoder {
+    /// 从 `dir` 加载稀疏模型；清单为必需项，加载前校验文件完整性
+    pub fn load(dir: &Path, opts: &SessionOptions) -> Result<Self, String> {
+        let manifest = ModelManifest::load(dir)?
+            .ok_or_else(|| format!("缺少 {}", dir.join(MANIFEST_FILE).display()))?;
+        manifest.verify_files(dir)?;
+
+        let mut opts = opts.resolve();
+        opts.optimized_model_path = None;
+        let session = build_session(&opts)?
+            .commit_from_file(dir.join("model.onnx"))
+            .map_err(|e| format!("稀疏模型加载失败: {e}"))?;
+
+        let has_type_ids = session
+            .inputs()
+            .iter()
+            .any(|i| i.name() == "token_type_ids");
+
+        let mut tokenizer = Tokenizer::from_file(dir.join("tokenizer.json"))
+            .map_err(|e| format!("稀疏模型 Tokenizer 加载失败: {e}"))?;
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: MAX_SEQ,
+                ..Default::default()
+            }))
+            .map_err(|e| e.to_string())?
+            .with_padding(None);
+
+        Ok(Self {
+            session,
+            tokenizer,
+            has_type_ids,
+            model_id: manifest.model_id,
+        })
+    }
+
+    /// 编码为 (term_id, weight) 列表，按权重降序，最多 `max_terms` 项
+    ///
+    /// weight = max_t log(1 + relu(logit[t, term]))，只统计非 padding 位置
+    pub fn encode(&mut self, text: &str, max_terms: usize) -> Result<Vec<(u32, f32)>, String> {
+        let enc = self
+            .tokenizer
+            .encode(text, true)
+            .map_err(|e| e.to_string())?;
+        let seq_len = enc.get_ids().len();
+        let mask = enc.get_attention_mask().to_vec();
+
+        let to_tensor = |v: &[u32]| {
+            let data: Vec<i64> = v.iter().map(|&x| x as i64).collect();
+            Tensor::<i64>::from_array(([1_usize, seq_len], data)).map_err(|e| e.to_string())
+        };
+        let ids_ort = to_tensor(enc.get_ids())?;
+        let mask_ort = to_tensor(&mask)?;
+
+        let outputs = if self.has_type_ids {
+            let types_ort = to_tensor(enc.get_type_ids())?;
+            self.session
+                .run(ort::inputs![
+                    "input_ids"      => ids_ort,
+                    "attention_mask" => mask_ort,
+                    "token_type_ids" => types_ort,
+                ])
+                .map_err(|e| format!("稀疏模型推理失败: {e}"))?
+        } else {
+            self.session
+                .run(ort::inputs![
+                    "input_ids"      => ids_ort,
+                    "attention_mask" => mask_ort,
+                ])
+                .map_err(|e| format!("稀疏模型推理失败: {e}"))?
+        };
+
+        // MLM logits: [1, seq_len, vocab]
+        let (shape, logits) = outputs[0]
+            .try_extract_tensor::<f32>()
+            .map_err(|e| e.to_string())?;
+        if shape.len() != 3 || shape[1] as usize != seq_len {
+            return Err(format!("稀疏模型输出形状 {shape:?} 不是 [1, {seq_len}, vocab]"));
+        }
+        let vocab = shape[2] as usize;
+
+        let mut weights = vec![0.0f32; vocab];
+        for (t, &m) in mask.iter().enumerate() {
+            if m == 0 {
+                continue;
+            }
+            let row = &logits[t * vocab..(t + 1) * vocab];
+            for (w, &x) in weights.iter_mut().zip(row) {
+                if x > 0.0 {
+                    *w = w.max(x.ln_1p());
+                }
+            }
+        }
+
+        let mut terms: Vec<(u32, f32)> = weights
+            .into_iter()
+            .enumerate()
+            .filter(|(_, w)| *w > 0.0)
+            .map(|(i, w)| (i as u32, w))
+            .collect();
+        terms.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        terms.truncate(max_terms);
+        Ok(terms)
+    }
+}
+
+/// 稀疏模型的专用工作线程：导入以 Bulk 优先级提交，查询以 Query 优先级插队，
+/// 编码期间不占用任何共享锁
+pub struct SparseEngine {
+    workers: Workers<SparseEncoder>,
+    model_id: String,
+}
+
+impl SparseEngine {
+    pub fn start(encoder: SparseEncoder) -> Result<Self, String> {
+        let model_id = encoder.model_id.clone();
+        Ok(Self {
+            workers: Workers::start("locallens-sparse", vec![encoder])?,
+            model_id,
+        })
+    }
+
+    pub fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    /// 提交一条文本的编码任务（不等待）
+    pub fn submit_encode(
+        &self,
+        priority: Priority,
+        text: String,
+        max_terms: usize,
+    ) -> Pending<Result<Vec<(u32, f32)>, String>> {
+        self.workers
+            .submit(priority, move |m| m.encode(&text, max_terms))
+    }
+}
@@ -0,0 +1,16 @@
+//! 各模块单元测试共用的夹具
+
+use rusqlite::Connection;
+use std::path::PathBuf;
+
+/// 已迁移到最新结构的内存数据库
+pub fn db() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    crate::migrations::migrate(&mut conn).unwrap();
+    conn
+}
+
+/// 系统临时目录下的测试文件路径；带上进程号，避免并行运行的测试进程互相覆盖
+pub fn temp_path(name: &str, ext: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("locallens-{}-{name}.{ext}", std::process::id()))
+}
@@ -0,0 +1,143 @@
+//! 有界 top-k：扫描时只保留分数最高的 k 个候选，不为全部候选分配内存再整体排序
+//!
+//! 小顶堆的堆顶是当前第 k 名，绝大多数候选只需与它比较一次即可丢弃，
+//! 整体为 O(n log k)；各分片的结果可以合并，便于并行扫描。
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// 分数按 total_cmp 全序比较，分数相同时 chunk_id 小者优先，保证结果稳定
+#[derive(Clone, Copy, Debug)]
+struct Hit {
+    score: f32,
+    id: i64,
+}
+
+impl Ord for Hit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+impl PartialOrd for Hit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Hit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Hit {}
+
+pub struct TopK {
+    k: usize,
+    heap: BinaryHeap<Reverse<Hit>>,
+}
+
+impl TopK {
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            heap: BinaryHeap::with_capacity(k + 1),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// 加入一个候选，NaN 分数被忽略
+    #[inline]
+    pub fn push(&mut self, id: i64, score: f32) {
+        if score.is_nan() {
+            return;
+        }
+        let hit = Hit { score, id };
+        if self.heap.len() < self.k {
+            self.heap.push(Reverse(hit));
+        } else if let Some(mut min) = self.heap.peek_mut() {
+            if hit > min.0 {
+                *min = Reverse(hit);
+            }
+        }
+    }
+
+    /// 合并另一个分片的结果
+    pub fn merge(&mut self, other: TopK) {
+        for Reverse(hit) in other.heap {
+            self.push(hit.id, hit.score);
+        }
+    }
+
+    /// 按分数降序返回 (chunk_id, 分数)
+    pub fn into_sorted_vec(self) -> Vec<(i64, f32)> {
+        // Reverse 的升序即分数降序
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(hit)| (hit.id, hit.score))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 带重复分数的伪随机候选
+    fn candidates(n: usize) -> Vec<(i64, f32)> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        (0..n as i64)
+            .map(|id| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (id, (state % 1000) as f32 / 1000.0)
+            })
+            .collect()
+    }
+
+    fn full_sort(mut all: Vec<(i64, f32)>, k: usize) -> Vec<(i64, f32)> {
+        all.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        all.truncate(k);
+        all
+    }
+
+    #[test]
+    fn matches_full_sort() {
+        let all = candidates(5_000);
+        for k in [0, 1, 20, 5_000, 6_000] {
+            let mut top = TopK::new(k);
+            for &(id, score) in &all {
+                top.push(id, score);
+            }
+            top.push(-1, f32::NAN);
+            assert_eq!(top.into_sorted_vec(), full_sort(all.clone(), k), "k = {k}");
+        }
+    }
+
+    #[test]
+    fn merged_shards_match_single_pass() {
+        let all = candidates(10_000);
+        let mut merged = TopK::new(50);
+        for shard in all.chunks(1_024) {
+            let mut top = TopK::new(50);
+            for &(id, score) in shard {
+                top.push(id, score);
+            }
+            merged.merge(top);
+        }
+        assert_eq!(merged.len(), 50);
+        assert_eq!(merged.into_sorted_vec(), full_sort(all, 50));
+    }
+}
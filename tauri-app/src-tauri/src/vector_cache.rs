@@ -0,0 +1,366 @@
+//! 暴力检索用的向量缓存：内存映射的向量文件（避免每次搜索都查 DB，也不占用堆内存），
+//! 加上文件写入之后导入、删除产生的增量
+//!
+//! 增量较多时在后台合并进新的向量文件（退出时也合并一次），下次启动可直接映射，
+//! 不必从数据库重写全部向量。
+
+use crate::collections::ChunkScope;
+use crate::quant::{cosine_sim, QuantVec, QueryVec};
+use crate::topk::TopK;
+use crate::vector_store::VectorStore;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+/// 向量缓存中增量条目的下限阈值，超过 max(该值, 文件向量数 / 4) 时重写向量文件
+pub const CACHE_OVERLAY_MAX: usize = 10_000;
+
+pub struct VectorCache {
+    /// 向量维度即文件中的维度（截断设置变化时需重建）
+    store: Option<Arc<VectorStore>>,
+    /// 文件写入后新增的向量（保持存储格式）
+    added: HashMap<i64, QuantVec>,
+    /// 文件中已被删除的 chunk
+    removed: HashSet<i64>,
+    /// 缓存内容对应的向量变更代数（见 bump_vector_generation）
+    generation: u64,
+    /// false 表示缓存失效（向量整体改写或增量不连续），下次搜索时重建
+    valid: bool,
+}
+
+impl VectorCache {
+    pub fn new() -> Self {
+        Self {
+            store: None,
+            added: HashMap::new(),
+            removed: HashSet::new(),
+            generation: 0,
+            valid: false,
+        }
+    }
+
+    pub fn invalidate(&mut self) {
+        self.valid = false;
+        self.store = None;
+        self.added.clear();
+        self.removed.clear();
+    }
+
+    /// 换成新写入的向量文件，代数取文件记录的代数，增量清空
+    pub fn replace(&mut self, store: VectorStore) {
+        self.generation = store.generation();
+        self.store = Some(Arc::new(store));
+        self.added.clear();
+        self.removed.clear();
+        self.valid = true;
+    }
+
+    /// 应用一次已提交写入的增量，`generation` 为该次提交后的代数
+    ///
+    /// 已包含该次修改（重建读到了提交后的数据）时忽略；中间缺了某次修改时标记失效
+    pub fn apply(&mut self, delta: CacheDelta, generation: u64) {
+        let Some(store) = self.store.as_ref().filter(|_| self.valid) else {
+            return;
+        };
+        if generation <= self.generation {
+            return;
+        }
+        if generation != self.generation + 1 {
+            eprintln!(
+                "[LocalLens] 向量缓存停留在第 {} 代，收到第 {generation} 代的增量，下次搜索时重建",
+                self.generation
+            );
+            return self.invalidate();
+        }
+        let (dim, format) = (store.dim(), store.format());
+        for id in delta.removed {
+            self.added.remove(&id);
+            self.removed.insert(id);
+        }
+        for (id, v) in delta.added {
+            if v.dim() != dim || v.format() != format {
+                return self.invalidate();
+            }
+            self.added.insert(id, v);
+        }
+        self.generation = generation;
+    }
+
+    /// 当前映射的向量文件（缓存无效时为 None），ANN 索引直接读取其中的向量
+    pub fn store(&self) -> Option<&Arc<VectorStore>> {
+        self.store.as_ref().filter(|_| self.valid)
+    }
+
+    fn overlay_len(&self) -> usize {
+        self.added.len() + self.removed.len()
+    }
+
+    fn overlay_limit(store: &VectorStore) -> usize {
+        CACHE_OVERLAY_MAX.max(store.len() / 4)
+    }
+
+    /// 缓存有效、维度一致且增量不多时无需重建
+    pub fn is_current(&self, dim: usize) -> bool {
+        self.valid
+            && self
+                .store
+                .as_ref()
+                .is_some_and(|s| s.dim() == dim && self.overlay_len() <= Self::overlay_limit(s))
+    }
+
+    /// 增量达到重建上限的一半时应在后台合并，避免之后的搜索同步重写全部向量
+    pub fn needs_compaction(&self) -> bool {
+        self.valid
+            && self
+                .store
+                .as_ref()
+                .is_some_and(|s| self.overlay_len() * 2 > Self::overlay_limit(s))
+    }
+
+    /// 取出合并所需的数据（文件映射共享，增量复制）；缓存无效或没有增量时为 None
+    pub fn compaction(&self) -> Option<Compaction> {
+        let store = self.store.clone().filter(|_| self.valid)?;
+        if self.overlay_len() == 0 {
+            return None;
+        }
+        let mut added: Vec<(i64, QuantVec)> =
+            self.added.iter().map(|(id, v)| (*id, v.clone())).collect();
+        added.sort_unstable_by_key(|(id, _)| *id);
+        Some(Compaction {
+            store,
+            added,
+            removed: self.removed.clone(),
+            generation: self.generation,
+        })
+    }
+
+    /// 合并期间缓存没有变化（同一个文件、同一代数且仍有效）时才能换上合并结果
+    pub fn unchanged_since(&self, compaction: &Compaction) -> bool {
+        self.valid
+            && self.generation == compaction.generation
+            && self
+                .store
+                .as_ref()
+                .is_some_and(|s| Arc::ptr_eq(s, &compaction.store))
+    }
+
+    /// `scope` 内与查询最相似的 `k` 个向量（文件中未删除的向量加上新增向量），按分数降序
+    pub fn top_k(&self, query: &QueryVec, k: usize, scope: &ChunkScope) -> Vec<(i64, f32)> {
+        let mut top = match &self.store {
+            Some(store) => store.top_k(query, k, |id| {
+                self.removed.contains(&id) || !scope.allows(id)
+            }),
+            None => TopK::new(k),
+        };
+        for (id, v) in self.added.iter().filter(|(id, _)| scope.allows(**id)) {
+            top.push(*id, cosine_sim(query, v));
+        }
+        top.into_sorted_vec()
+    }
+}
+
+/// 一次写入对向量的增量（按 chunk_id）
+#[derive(Default)]
+pub struct CacheDelta {
+    pub added: Vec<(i64, QuantVec)>,
+    pub removed: Vec<i64>,
+}
+
+impl CacheDelta {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+
+    pub fn extend(&mut self, other: CacheDelta) {
+        self.added.extend(other.added);
+        self.removed.extend(other.removed);
+    }
+}
+
+/// 某一代缓存的快照：向量文件加上按 chunk_id 排序的增量
+pub struct Compaction {
+    store: Arc<VectorStore>,
+    added: Vec<(i64, QuantVec)>,
+    removed: HashSet<i64>,
+    generation: u64,
+}
+
+impl Compaction {
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// 把文件中未删除的向量与新增向量按 chunk_id 归并写入 `path`
+    ///
+    /// `last_id` 为该代数下数据库中最大的 chunk_id
+    pub fn write(&self, path: &Path, last_id: i64) -> Result<(), String> {
+        let tag = self.store.format().tag();
+        let kept = self
+            .store
+            .rows()
+            .filter(|(id, _)| !self.removed.contains(id))
+            .map(|(id, row)| {
+                let mut blob = Vec::with_capacity(row.len() + 1);
+                blob.push(tag);
+                blob.extend_from_slice(row);
+                (id, blob)
+            });
+        let added = self.added.iter().map(|(id, v)| (*id, v.to_bytes()));
+        let rows = merge_by_id(kept, added);
+        let skipped = VectorStore::write_rows(
+            path,
+            self.store.dim(),
+            self.store.format(),
+            self.generation,
+            last_id,
+            rows,
+        )?;
+        debug_assert_eq!(skipped, 0);
+        Ok(())
+    }
+}
+
+/// 归并两个按 id 升序的序列；id 相同时取 `b` 中的一项（新增覆盖文件中的旧值）
+fn merge_by_id<T>(
+    a: impl Iterator<Item = (i64, T)>,
+    b: impl Iterator<Item = (i64, T)>,
+) -> impl Iterator<Item = (i64, T)> {
+    let mut a = a.peekable();
+    let mut b = b.peekable();
+    std::iter::from_fn(move || match (a.peek(), b.peek()) {
+        (Some(x), Some(y)) if x.0 < y.0 => a.next(),
+        (Some(x), Some(y)) if x.0 == y.0 => {
+            a.next();
+            b.next()
+        }
+        (Some(_), None) => a.next(),
+        _ => b.next(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quant::VectorFormat;
+    use crate::test_support::temp_path;
+
+    fn v(x: f32, y: f32) -> QuantVec {
+        QuantVec::quantize(&[x, y], VectorFormat::F32)
+    }
+
+    /// 第 `generation` 代、含 chunk 1..=n 的二维向量文件
+    fn cache(name: &str, n: i64, generation: u64) -> VectorCache {
+        let path = temp_path(name, "vectors");
+        let rows = (1..=n).map(|id| (id, v(1.0, id as f32).to_bytes()));
+        VectorStore::write_rows(&path, 2, VectorFormat::F32, generation, n, rows).unwrap();
+        let store = VectorStore::open(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        let mut cache = VectorCache::new();
+        cache.replace(store);
+        cache
+    }
+
+    fn delta(added: &[i64], removed: &[i64]) -> CacheDelta {
+        CacheDelta {
+            added: added.iter().map(|id| (*id, v(0.0, 1.0))).collect(),
+            removed: removed.to_vec(),
+        }
+    }
+
+    fn ids(cache: &VectorCache) -> Vec<i64> {
+        let query = QueryVec::new(vec![1.0, 0.0]);
+        let mut ids: Vec<i64> = cache
+            .top_k(&query, 100, &ChunkScope::All)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn in_order_delta_is_applied() {
+        let mut cache = cache("in-order", 3, 5);
+        cache.apply(delta(&[4], &[2]), 6);
+        assert_eq!(cache.generation, 6);
+        assert!(cache.is_current(2));
+        assert_eq!(ids(&cache), vec![1, 3, 4]);
+    }
+
+    #[test]
+    fn duplicate_or_older_generation_is_ignored() {
+        let mut cache = cache("older", 3, 5);
+        cache.apply(delta(&[4], &[]), 5);
+        cache.apply(delta(&[5], &[1]), 3);
+        assert_eq!(cache.generation, 5);
+        assert!(cache.is_current(2));
+        assert_eq!(ids(&cache), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn generation_gap_invalidates() {
+        let mut cache = cache("gap", 3, 5);
+        cache.apply(delta(&[4], &[]), 7);
+        assert!(!cache.is_current(2));
+        assert!(cache.compaction().is_none());
+    }
+
+    #[test]
+    fn mismatched_vector_invalidates() {
+        let mut cache = cache("dim", 3, 5);
+        let wrong_dim = CacheDelta {
+            added: vec![(4, QuantVec::quantize(&[1.0, 0.0, 0.0], VectorFormat::F32))],
+            removed: vec![],
+        };
+        cache.apply(wrong_dim, 6);
+        assert!(!cache.is_current(2));
+
+        let mut cache = self::cache("format", 3, 5);
+        let wrong_format = CacheDelta {
+            added: vec![(4, QuantVec::quantize(&[1.0, 0.0], VectorFormat::Int8))],
+            removed: vec![],
+        };
+        cache.apply(wrong_format, 6);
+        assert!(!cache.is_current(2));
+        // 维度不同的查询同样需要重建
+        assert!(!self::cache("query-dim", 3, 5).is_current(3));
+    }
+
+    #[test]
+    fn large_overlay_needs_compaction() {
+        let mut cache = cache("overlay", 3, 0);
+        let added: Vec<i64> = (4..4 + CACHE_OVERLAY_MAX as i64).collect();
+        cache.apply(delta(&added[..CACHE_OVERLAY_MAX / 2], &[]), 1);
+        assert!(cache.is_current(2) && !cache.needs_compaction());
+        cache.apply(delta(&added[CACHE_OVERLAY_MAX / 2..], &[]), 2);
+        assert!(cache.is_current(2) && cache.needs_compaction());
+        cache.apply(delta(&[0], &[]), 3);
+        assert!(!cache.is_current(2));
+    }
+
+    #[test]
+    fn compaction_merges_overlay_into_file() {
+        let mut cache = cache("compact", 4, 5);
+        cache.apply(delta(&[6, 5], &[2]), 6);
+        cache.apply(delta(&[], &[6]), 7);
+        let compaction = cache.compaction().unwrap();
+        assert!(cache.unchanged_since(&compaction));
+
+        let path = temp_path("compacted", "vectors");
+        compaction.write(&path, 6).unwrap();
+        let store = VectorStore::open(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(store.generation(), 7);
+        assert_eq!(
+            store.rows().map(|(id, _)| id).collect::<Vec<_>>(),
+            vec![1, 3, 4, 5]
+        );
+
+        // 合并期间应用了新的增量时不能换上旧快照
+        cache.apply(delta(&[7], &[]), 8);
+        assert!(!cache.unchanged_since(&compaction));
+        cache.replace(store);
+        assert_eq!(ids(&cache), vec![1, 3, 4, 5]);
+        assert!(cache.compaction().is_none());
+    }
+}
@@ -0,0 +1,515 @@
+//! 内存映射的向量文件：chunk_embeddings 中某个嵌入模型的向量的连续副本，供暴力检索顺序扫描
+//!
+//! 文件布局（little-endian）：
+//! - 64 字节文件头：标记、维度、格式、向量数、id 校验值、id 区偏移、向量变更代数、
+//!   读取的最大 chunk_id（含跳过的向量）
+//! - 向量矩阵：从第 64 字节开始，每行固定 `row_bytes` 字节（即去掉格式标记的 BLOB）
+//! - id 区：按 8 字节对齐，每行对应一个 i64 chunk_id
+//!
+//! 文件只整体写入临时文件再改名替换，映射期间不会被原地修改。
+
+use crate::collections::model_join;
+use crate::quant::{cosine_sim_raw, QueryVec, VectorFormat};
+use crate::topk::TopK;
+use memmap2::Mmap;
+use rusqlite::Connection;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"LLVEC\x00\x00\x01";
+const HEADER_LEN: usize = 64;
+
+/// top_k 并行打分时每个分片的行数
+#[cfg(feature = "parallel")]
+const SHARD_ROWS: usize = 16_384;
+
+/// 用于与数据库比对的 id 摘要
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Fingerprint {
+    count: u64,
+    max_id: i64,
+    id_sum: i64,
+}
+
+pub struct VectorStore {
+    mmap: Mmap,
+    dim: usize,
+    format: VectorFormat,
+    stride: usize,
+    ids_offset: usize,
+    fingerprint: Fingerprint,
+    generation: u64,
+    /// 写入时该模型在 chunk_embeddings 中最大的 chunk_id（含维度或格式不符而跳过的向量）
+    last_id: i64,
+}
+
+impl VectorStore {
+    /// 把使用 `model_id` 的集合中维度与格式符合的向量按 chunk_id 顺序写入 `path`，
+    /// 返回跳过的（维度或格式不符的）向量数
+    ///
+    /// `generation` 为读取时该模型的向量变更代数，调用方应在同一个读事务中取得
+    pub fn write(
+        conn: &Connection,
+        path: &Path,
+        model_id: &str,
+        dim: usize,
+        format: VectorFormat,
+        generation: u64,
+    ) -> Result<usize, String> {
+        let last_id = max_chunk_id(conn, model_id)?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT e.chunk_id, e.embedding FROM chunk_embeddings e {}
+                 ORDER BY e.chunk_id",
+                model_join(1)
+            ))
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([model_id], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(|e| e.to_string())?;
+        Self::write_rows(
+            path,
+            dim,
+            format,
+            generation,
+            last_id,
+            rows.filter_map(|r| r.ok()),
+        )
+    }
+
+    /// 把按 chunk_id 升序排列的 (chunk_id, 带格式标记的 BLOB) 写入 `path`，
+    /// 返回跳过的（维度或格式不符的）向量数
+    ///
+    /// `last_id` 为同一代数下该模型在 chunk_embeddings 中最大的 chunk_id，供启动时核对
+    pub fn write_rows(
+        path: &Path,
+        dim: usize,
+        format: VectorFormat,
+        generation: u64,
+        last_id: i64,
+        rows: impl IntoIterator<Item = (i64, Vec<u8>)>,
+    ) -> Result<usize, String> {
+        let stride = format.row_bytes(dim);
+        let io_err = |e: std::io::Error| format!("写入向量文件 {} 失败: {e}", path.display());
+        let mut w = BufWriter::new(File::create(path).map_err(io_err)?);
+        w.write_all(&[0u8; HEADER_LEN]).map_err(io_err)?;
+
+        let mut ids = Vec::new();
+        let mut skipped = 0;
+        for (id, blob) in rows {
+            if blob.len() == stride + 1 && blob[0] == format.tag() {
+                w.write_all(&blob[1..]).map_err(io_err)?;
+                ids.push(id);
+            } else {
+                skipped += 1;
+            }
+        }
+
+        let matrix_end = HEADER_LEN + ids.len() * stride;
+        let ids_offset = matrix_end.next_multiple_of(8);
+        w.write_all(&vec![0u8; ids_offset - matrix_end])
+            .map_err(io_err)?;
+        for id in &ids {
+            w.write_all(&id.to_le_bytes()).map_err(io_err)?;
+        }
+
+        let fp = Fingerprint {
+            count: ids.len() as u64,
+            max_id: ids.last().copied().unwrap_or(0),
+            id_sum: ids.iter().fold(0i64, |acc, id| acc.wrapping_add(*id)),
+        };
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&(dim as u32).to_le_bytes());
+        header.extend_from_slice(&[format.tag(), 0, 0, 0]);
+        header.extend_from_slice(&fp.count.to_le_bytes());
+        header.extend_from_slice(&fp.max_id.to_le_bytes());
+        header.extend_from_slice(&fp.id_sum.to_le_bytes());
+        header.extend_from_slice(&(ids_offset as u64).to_le_bytes());
+        header.extend_from_slice(&generation.to_le_bytes());
+        header.extend_from_slice(&last_id.to_le_bytes());
+        header.resize(HEADER_LEN, 0);
+
+        let mut file = w.into_inner().map_err(|e| io_err(e.into_error()))?;
+        file.seek(SeekFrom::Start(0))
+            .and_then(|_| file.write_all(&header))
+            .and_then(|_| file.sync_all())
+            .map_err(io_err)?;
+        Ok(skipped)
+    }
+
+    /// 映射已有的向量文件并检查文件头与长度
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("打开 {} 失败: {e}", path.display()))?;
+        // SAFETY: 向量文件只通过"写临时文件 + 改名"整体替换，映射期间内容不会被修改
+        let mmap = unsafe { Mmap::map(&file) }
+            .map_err(|e| format!("映射 {} 失败: {e}", path.display()))?;
+
+        let bytes = &mmap[..];
+        if bytes.len() < HEADER_LEN || &bytes[..8] != MAGIC {
+            return Err(format!("{} 不是向量文件", path.display()));
+        }
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let dim = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let format = VectorFormat::from_tag(bytes[12]).ok_or("向量文件格式标记无效")?;
+        let fingerprint = Fingerprint {
+            count: u64_at(16),
+            max_id: u64_at(24) as i64,
+            id_sum: u64_at(32) as i64,
+        };
+        let ids_offset = u64_at(40) as usize;
+        let generation = u64_at(48);
+        let last_id = u64_at(56) as i64;
+        let stride = format.row_bytes(dim);
+
+        let count = fingerprint.count as usize;
+        if dim == 0
+            || ids_offset < HEADER_LEN + count * stride
+            || bytes.len() < ids_offset + count * 8
+        {
+            return Err(format!(
+                "{} 长度与文件头不符（可能写入中断）",
+                path.display()
+            ));
+        }
+        Ok(Self {
+            mmap,
+            dim,
+            format,
+            stride,
+            ids_offset,
+            fingerprint,
+            generation,
+            last_id,
+        })
+    }
+
+    /// 与数据库比对：变更代数与格式一致，且最大 chunk_id 相同
+    ///
+    /// 每次修改该模型的向量都会推进其变更代数，代数一致即可信任文件；最大 chunk_id 按主键
+    /// 倒序查找，启动时不扫描向量。调试构建另外比对全部符合维度与格式的向量 id 摘要
+    pub fn validate(
+        &self,
+        conn: &Connection,
+        model_id: &str,
+        format: VectorFormat,
+        generation: u64,
+    ) -> Result<(), String> {
+        if generation != self.generation {
+            return Err(format!(
+                "向量文件写于第 {} 代，数据库已到第 {generation} 代",
+                self.generation
+            ));
+        }
+        if format != self.format {
+            return Err(format!(
+                "向量文件为 {}，当前存储格式为 {}",
+                self.format.as_str(),
+                format.as_str()
+            ));
+        }
+        let last_id = max_chunk_id(conn, model_id)?;
+        if last_id != self.last_id {
+            return Err(format!(
+                "向量文件最大 id 为 {}，数据库为 {last_id}",
+                self.last_id
+            ));
+        }
+        if cfg!(debug_assertions) {
+            self.validate_fingerprint(conn, model_id)?;
+        }
+        Ok(())
+    }
+
+    /// 逐行比对向量 id 摘要（需要读取全部向量，只在调试构建的启动检查与测试中使用）
+    fn validate_fingerprint(&self, conn: &Connection, model_id: &str) -> Result<(), String> {
+        let db = conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*), COALESCE(MAX(e.chunk_id), 0), COALESCE(SUM(e.chunk_id), 0)
+                     FROM chunk_embeddings e {}
+                     WHERE length(e.embedding) = ?1 AND substr(e.embedding, 1, 1) = ?2",
+                    model_join(3)
+                ),
+                rusqlite::params![self.stride as i64 + 1, vec![self.format.tag()], model_id],
+                |r| {
+                    Ok(Fingerprint {
+                        count: r.get::<_, i64>(0)? as u64,
+                        max_id: r.get(1)?,
+                        id_sum: r.get(2)?,
+                    })
+                },
+            )
+            .map_err(|e| e.to_string())?;
+        if db != self.fingerprint {
+            return Err(format!(
+                "向量文件有 {} 条，数据库有 {} 条（或 id 不一致）",
+                self.fingerprint.count, db.count
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn format(&self) -> VectorFormat {
+        self.format
+    }
+
+    /// 写入文件时数据库的向量变更代数
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn len(&self) -> usize {
+        self.fingerprint.count as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn chunk_id(&self, row: usize) -> i64 {
+        let at = self.ids_offset + row * 8;
+        i64::from_le_bytes(self.mmap[at..at + 8].try_into().unwrap())
+    }
+
+    /// 第 `row` 行的向量数据（去掉格式标记）
+    pub fn row(&self, row: usize) -> &[u8] {
+        self.matrix(row..row + 1)
+    }
+
+    /// chunk_id 所在的行（文件按 chunk_id 升序写入，二分查找）
+    pub fn find(&self, chunk_id: i64) -> Option<usize> {
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.chunk_id(mid).cmp(&chunk_id) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    /// 第 `rows` 行的向量数据，按行连续存放
+    fn matrix(&self, rows: Range<usize>) -> &[u8] {
+        &self.mmap[HEADER_LEN + rows.start * self.stride..HEADER_LEN + rows.end * self.stride]
+    }
+
+    /// 按 chunk_id 升序返回 (chunk_id, 去掉格式标记的向量数据)
+    pub fn rows(&self) -> impl Iterator<Item = (i64, &[u8])> + '_ {
+        self.matrix(0..self.len())
+            .chunks_exact(self.stride)
+            .enumerate()
+            .map(|(row, v)| (self.chunk_id(row), v))
+    }
+
+    /// 顺序扫描全部向量，返回 (chunk_id, 与查询的余弦相似度)
+    pub fn scores<'a>(&'a self, query: &'a QueryVec) -> impl Iterator<Item = (i64, f32)> + 'a {
+        self.rows()
+            .map(move |(id, v)| (id, cosine_sim_raw(query, self.format, v)))
+    }
+
+    /// 与查询最相似的 `k` 个向量，`skip` 返回 true 的 chunk 不参与
+    ///
+    /// 启用 parallel 特性且向量多于一个分片时，各分片在 rayon 线程池中并行扫描后合并
+    pub fn top_k(&self, query: &QueryVec, k: usize, skip: impl Fn(i64) -> bool + Sync) -> TopK {
+        let scan = |rows: Range<usize>| {
+            let mut top = TopK::new(k);
+            let start = rows.start;
+            for (i, v) in self.matrix(rows).chunks_exact(self.stride).enumerate() {
+                let id = self.chunk_id(start + i);
+                if !skip(id) {
+                    top.push(id, cosine_sim_raw(query, self.format, v));
+                }
+            }
+            top
+        };
+        let len = self.len();
+
+        #[cfg(feature = "parallel")]
+        if len > SHARD_ROWS {
+            use rayon::prelude::*;
+            return (0..len.div_ceil(SHARD_ROWS))
+                .into_par_iter()
+                .map(|shard| scan(shard * SHARD_ROWS..((shard + 1) * SHARD_ROWS).min(len)))
+                .reduce(
+                    || TopK::new(k),
+                    |mut a, b| {
+                        a.merge(b);
+                        a
+                    },
+                );
+        }
+        scan(0..len)
+    }
+}
+
+/// 该模型在 chunk_embeddings 中最大的 chunk_id
+///
+/// 按主键倒序找到第一条属于该模型的向量；只有一个模型在用时只读一行
+pub fn max_chunk_id(conn: &Connection, model_id: &str) -> Result<i64, String> {
+    conn.query_row(
+        &format!(
+            "SELECT COALESCE((SELECT e.chunk_id FROM chunk_embeddings e {}
+                              ORDER BY e.chunk_id DESC LIMIT 1), 0)",
+            model_join(1)
+        ),
+        [model_id],
+        |r| r.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quant::QuantVec;
+    use crate::test_support::{self, temp_path};
+    use crate::MODEL_NAME;
+
+    fn db_with_vectors(vectors: &[(i64, Vec<f32>)], format: VectorFormat) -> Connection {
+        let conn = test_support::db();
+        conn.execute(
+            "INSERT INTO files (path, name) VALUES ('/a.txt', 'a.txt')",
+            [],
+        )
+        .unwrap();
+        for (id, v) in vectors {
+            conn.execute(
+                "INSERT INTO chunks (id, file_id, content, chunk_index) VALUES (?1, 1, 'x', ?1)",
+                [id],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO chunk_embeddings (chunk_id, embedding) VALUES (?1, ?2)",
+                rusqlite::params![id, QuantVec::quantize(v, format).to_bytes()],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    #[test]
+    fn scores_match_stored_vectors() {
+        for format in [VectorFormat::F32, VectorFormat::F16, VectorFormat::Int8] {
+            let vectors = vec![
+                (3, vec![1.0, 0.0, 0.0]),
+                (7, vec![0.0, 0.6, 0.8]),
+                (9, vec![0.0, 0.0, 1.0]),
+            ];
+            let conn = db_with_vectors(&vectors, format);
+            // 维度不符的向量被跳过
+            conn.execute(
+                "INSERT INTO chunks (id, file_id, content, chunk_index) VALUES (10, 1, 'y', 10)",
+                [],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO chunk_embeddings (chunk_id, embedding) VALUES (10, ?1)",
+                [QuantVec::quantize(&[1.0, 0.0], format).to_bytes()],
+            )
+            .unwrap();
+
+            let path = temp_path(format.as_str(), "vectors");
+            assert_eq!(
+                VectorStore::write(&conn, &path, MODEL_NAME, 3, format, 1).unwrap(),
+                1
+            );
+            let store = VectorStore::open(&path).unwrap();
+            store.validate(&conn, MODEL_NAME, format, 1).unwrap();
+
+            let query = QueryVec::new(vec![0.0, 0.6, 0.8]);
+            let scores: Vec<(i64, f32)> = store.scores(&query).collect();
+            assert_eq!(store.find(7), Some(1));
+            assert_eq!(store.find(8), None);
+            assert_eq!(
+                store.row(2),
+                &QuantVec::quantize(&[0.0, 0.0, 1.0], format).to_bytes()[1..]
+            );
+            std::fs::remove_file(&path).ok();
+            assert_eq!(
+                scores.iter().map(|s| s.0).collect::<Vec<_>>(),
+                vec![3, 7, 9]
+            );
+            assert!(scores[0].1.abs() < 0.02);
+            assert!((scores[1].1 - 1.0).abs() < 0.02);
+            assert!((scores[2].1 - 0.8).abs() < 0.02);
+        }
+    }
+
+    #[test]
+    fn validate_detects_database_changes() {
+        let conn = db_with_vectors(
+            &[(1, vec![1.0, 0.0]), (2, vec![0.0, 1.0])],
+            VectorFormat::F32,
+        );
+        let path = temp_path("validate", "vectors");
+        // 其他模型的集合中的向量不写入，也不影响核对
+        conn.execute_batch(
+            "
+            INSERT INTO collections (id, name, model_id) VALUES (2, 'Other', 'other');
+            INSERT INTO files (id, collection_id, path, name) VALUES (2, 2, '/b.txt', 'b.txt');
+            INSERT INTO chunks (id, file_id, content, chunk_index) VALUES (5, 2, 'z', 0);
+            INSERT INTO chunk_embeddings (chunk_id, embedding) VALUES (5, x'00');
+            ",
+        )
+        .unwrap();
+        VectorStore::write(&conn, &path, MODEL_NAME, 2, VectorFormat::F32, 4).unwrap();
+        let store = VectorStore::open(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(store.generation(), 4);
+        assert_eq!(store.len(), 2);
+        assert_eq!(max_chunk_id(&conn, MODEL_NAME).unwrap(), 2);
+        assert_eq!(max_chunk_id(&conn, "other").unwrap(), 5);
+        store
+            .validate(&conn, MODEL_NAME, VectorFormat::F32, 4)
+            .unwrap();
+        assert!(store
+            .validate(&conn, MODEL_NAME, VectorFormat::F32, 5)
+            .is_err());
+        assert!(store
+            .validate(&conn, MODEL_NAME, VectorFormat::F16, 4)
+            .is_err());
+
+        // 最大 id 不变的修改只有逐行摘要能发现（代数正常推进时不会出现）
+        let tx = conn.unchecked_transaction().unwrap();
+        tx.execute("DELETE FROM chunk_embeddings WHERE chunk_id = 1", [])
+            .unwrap();
+        assert!(store.validate_fingerprint(&tx, MODEL_NAME).is_err());
+        tx.rollback().unwrap();
+        store.validate_fingerprint(&conn, MODEL_NAME).unwrap();
+
+        conn.execute("DELETE FROM chunk_embeddings WHERE chunk_id = 2", [])
+            .unwrap();
+        assert!(store
+            .validate(&conn, MODEL_NAME, VectorFormat::F32, 4)
+            .is_err());
+    }
+
+    #[test]
+    fn truncated_file_is_rejected() {
+        let conn = db_with_vectors(&[(1, vec![1.0, 0.0])], VectorFormat::F32);
+        let path = temp_path("truncated", "vectors");
+        VectorStore::write(&conn, &path, MODEL_NAME, 2, VectorFormat::F32, 0).unwrap();
+        let len = std::fs::metadata(&path).unwrap().len();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 4)
+            .unwrap();
+        let result = VectorStore::open(&path);
+        std::fs::remove_file(&path).ok();
+        assert!(result.is_err());
+    }
+}
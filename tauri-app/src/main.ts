@@ -8,15 +8,65 @@ interface ImportResult {
   chunks_created: number;
   skipped: number;
   embeddings_generated: number;
+  embeddings_cached: number;  // 其中直接复用缓存的条数
 }
 
 interface SearchResult {
+  chunk_id: number;
   content: string;
   file_name: string;
   file_path: string;
   chunk_index: number;
-  score: number;       // 语义相似度 0–1（关键词模式为 0）
+  score: number;       // 语义相似度 0–1（关键词模式为 BM25 相关度）
   is_semantic: boolean;
+  mode: SearchMode;                  // 实际使用的检索方式
+  matched_span: MatchedSpan | null;  // 长段落中命中的窗口
+  rerank_score: number | null;       // 交叉编码器相关度 0–1（未重排为 null）
+  highlight: TextHighlight | null;   // 全文索引标出的命中
+  contributions: Contribution[];     // 混合检索中各路信号的贡献
+  source_range: SourceRange | null;  // chunk 在原文件中的字节区间
+  neighbours: Neighbours;            // 同一文件中前后相邻的 chunk
+  file_meta: FileMeta;
+}
+
+interface SourceRange {
+  byte_start: number;
+  byte_end: number;
+}
+
+interface Neighbours {
+  prev: string | null;
+  next: string | null;
+}
+
+type SearchMode = "semantic" | "sparse" | "keyword" | "hybrid";
+
+interface Contribution {
+  signal: SearchMode;
+  rank: number;          // 该路中的名次（从 1 开始）
+  score: number;         // 该路原始分数
+  contribution: number;  // 计入融合分数的部分
+}
+
+/** 命中片段以 U+E000 / U+E001 包围 */
+interface TextHighlight {
+  snippet: string;
+  content: string;
+}
+
+interface FileMeta {
+  size_bytes: number | null;
+  modified_at: string | null;  // UTC "YYYY-MM-DD HH:MM:SS"
+  content_hash: string | null;
+  format: string;
+  chunk_count: number;
+}
+
+interface MatchedSpan {
+  byte_start: number;
+  byte_end: number;
+  text: string;
+  score: number;
 }
 
 interface ImportProgressPayload {
@@ -42,7 +92,12 @@ const $ = <T extends HTMLElement>(id: string) =>
 
 // ── 模型状态 ──────────────────────────────────────────────────────────────────
 
-type ModelStatus = "loading" | "ready" | "unavailable" | `failed:${string}`;
+type ModelStatus =
+  | "loading"
+  | "ready"
+  | "unavailable"
+  | `failed:${string}`
+  | `corrupted:${string}`;
 
 async function initModelStatus() {
   try {
@@ -82,6 +137,11 @@ function applyModelStatus(status: ModelStatus) {
     badge.textContent = "关键词模式";
     badge.classList.add("unavailable");
     indicator.title = "模型文件未找到，使用关键词搜索";
+  } else if (status.startsWith("corrupted:")) {
+    // 模型文件校验失败：提示原因及处理方式
+    badge.textContent = "模型已损坏";
+    badge.classList.add("failed");
+    indicator.title = status.slice(10);
   } else {
     badge.textContent = "关键词模式";
     badge.classList.add("failed");
@@ -145,7 +205,8 @@ async function importFolder() {
     const result = await invoke<ImportResult>("select_and_import_folder");
     const embNote =
       result.embeddings_generated > 0
-        ? `，生成 ${result.embeddings_generated} 条向量`
+        ? `，生成 ${result.embeddings_generated} 条向量` +
+          (result.embeddings_cached > 0 ? `（${result.embeddings_cached} 条来自缓存）` : "")
         : "（未生成向量，模型未就绪）";
     statusEl.textContent =
       `已导入 ${result.files_imported} 个文件，` +
@@ -181,6 +242,13 @@ function escapeHtml(s: string): string {
     .replace(/>/g, "&gt;");
 }
 
+/** 把后端 FTS 高亮标记转成 <mark>（先转义，标记字符不受影响） */
+function ftsMarkup(text: string): string {
+  return escapeHtml(text)
+    .replace(/\uE000/g, "<mark>")
+    .replace(/\uE001/g, "</mark>");
+}
+
 function highlight(text: string, keyword: string): string {
   const safe = escapeHtml(text);
   if (!keyword.trim()) return safe;
@@ -231,19 +299,66 @@ const FILE_ICON = `<svg width="13" height="13" viewBox="0 0 16 16" fill="none"
   <path d="M6 8h5M6 11h3" stroke-opacity="0.6"/>
 </svg>`;
 
+function formatBytes(n: number): string {
+  if (n < 1024) return `${n} B`;
+  if (n < 1024 * 1024) return `${(n / 1024).toFixed(1)} KB`;
+  return `${(n / 1024 / 1024).toFixed(1)} MB`;
+}
+
+/** 文件名悬停提示：路径 + 大小 / 修改时间 / 段落数 */
+function fileTitle(r: SearchResult): string {
+  const m = r.file_meta;
+  const parts = [m.format.toUpperCase(), `${m.chunk_count} 段`];
+  if (m.size_bytes !== null) parts.push(formatBytes(m.size_bytes));
+  if (m.modified_at) parts.push(`修改于 ${m.modified_at} UTC`);
+  return `${r.file_path}\n${parts.join(" · ")}`;
+}
+
+const SIGNAL_LABELS: Record<SearchMode, string> = {
+  semantic: "语义",
+  sparse: "稀疏",
+  keyword: "关键词",
+  hybrid: "混合",
+};
+
+/** 混合检索徽章悬停提示：每路信号的名次与贡献 */
+function contributionTitle(r: SearchResult): string {
+  const lines = r.contributions.map(
+    (c) => `${SIGNAL_LABELS[c.signal]} #${c.rank}（${c.score.toFixed(3)}）→ ${c.contribution.toFixed(4)}`
+  );
+  if (r.rerank_score !== null) lines.push(`重排相关度 ${Math.round(r.rerank_score * 100)}%`);
+  return lines.join("\n");
+}
+
 function buildCard(r: SearchResult, query: string): HTMLElement {
   const card = document.createElement("div");
   card.className = "result-card";
 
-  const { text: snippetText, isFull } = extractSnippet(
-    r.content, query, 80, r.is_semantic
-  );
-  const snippetHtml = highlight(snippetText, query);
-  const fullHtml    = highlight(r.content,    query);
+  // 长段落优先展示与查询最相近的窗口；全文索引命中直接使用后端摘要与高亮
+  let snippetHtml: string;
+  let fullHtml: string;
+  let isFull: boolean;
+  if (r.highlight) {
+    snippetHtml = ftsMarkup(r.highlight.snippet);
+    fullHtml    = ftsMarkup(r.highlight.content);
+    isFull      = r.highlight.snippet === r.highlight.content;
+  } else {
+    const snippet = r.matched_span
+      ? { text: `…${r.matched_span.text}…`, isFull: false }
+      : extractSnippet(r.content, query, 80, r.is_semantic);
+    snippetHtml = highlight(snippet.text, query);
+    fullHtml    = highlight(r.content,    query);
+    isFull      = snippet.isFull;
+  }
 
-  // 底部徽章：KW / AI + 相似度
-  const modeBadgeHtml = r.is_semantic
-    ? `<span class="mode-badge ai-badge">AI</span><span class="score-val">${Math.round(r.score * 100)}%</span>`
+  // 底部徽章：KW / SP / AI / HY + 相似度（重排过的结果显示重排相关度）
+  const shownScore = r.rerank_score ?? r.score;
+  const modeBadgeHtml = r.mode === "hybrid"
+    ? `<span class="mode-badge hy-badge" title="${escapeHtml(contributionTitle(r))}">HY</span>`
+    : r.mode === "semantic"
+    ? `<span class="mode-badge ai-badge">AI</span><span class="score-val">${Math.round(shownScore * 100)}%</span>`
+    : r.mode === "sparse"
+    ? `<span class="mode-badge sp-badge">SP</span>`
     : `<span class="mode-badge kw-badge">KW</span>`;
 
   // 展开按钮（内容超出摘要时才显示）
@@ -254,7 +369,7 @@ function buildCard(r: SearchResult, query: string): HTMLElement {
   card.innerHTML = `
     <div class="card-header">
       <span class="card-file-icon">${FILE_ICON}</span>
-      <span class="card-file-name" title="${escapeHtml(r.file_path)}">${escapeHtml(r.file_name)}</span>
+      <span class="card-file-name" title="${escapeHtml(fileTitle(r))}">${escapeHtml(r.file_name)}</span>
       <span class="card-chunk-badge">段落&nbsp;#${r.chunk_index + 1}</span>
     </div>
     <div class="card-snippet">${snippetHtml}</div>
@@ -351,7 +466,10 @@ async function doSearch() {
   header.style.display = "flex";
 
   try {
-    const results = await invoke<SearchResult[]>("search_text", { query });
+    const results = await invoke<SearchResult[]>("search_text", {
+      query,
+      options: { mode: "hybrid" },
+    });
 
     if (results.length === 0) {
       count.textContent = `未找到「${query}」相关内容`;
@@ -359,8 +477,7 @@ async function doSearch() {
     }
 
     allResults = results;
-    const mode = results[0].is_semantic ? "语义搜索" : "关键词搜索";
-    count.textContent = `${mode}：找到 ${results.length} 条结果`;
+    count.textContent = `${SIGNAL_LABELS[results[0].mode]}检索：找到 ${results.length} 条结果`;
     renderPage();
   } catch (e) {
     count.textContent = `搜索出错: ${e}`;